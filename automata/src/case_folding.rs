@@ -0,0 +1,50 @@
+//! Unicode simple case folding, used by case insensitive arms
+//!
+//! A char folds to a single char, so `'K'`, `'k'` and the kelvin sign `'K'` all fold to `'k'`,
+//! and `'ς'` folds to `'σ'`. Folds that would need several chars, such as `'ß'` to `"ss"`, are
+//! left aside, and so are the Turkic folds of `İ` and `ı`. The folds come from the `C` and `S`
+//! entries of CaseFolding.txt, see `table`.
+
+mod table;
+
+use self::table::{FOLDED_FROM, SIMPLE_FOLDS};
+
+/// The char a char folds to, which is the char itself when it has no fold
+pub fn simple_fold(chr: char) -> char {
+    match SIMPLE_FOLDS.binary_search_by_key(&chr, |&(from, _)| from) {
+        Ok(index) => SIMPLE_FOLDS[index].1,
+        Err(_) => chr,
+    }
+}
+
+/// Every char that folds to the same char as `chr`, `chr` included, in ascending order
+pub fn case_variants(chr: char) -> Vec<char> {
+    let folded = simple_fold(chr);
+    let start = FOLDED_FROM.partition_point(|&(_, to)| to < folded);
+    let end = FOLDED_FROM.partition_point(|&(_, to)| to <= folded);
+
+    let mut variants = vec![folded];
+    variants.extend(FOLDED_FROM[start..end].iter().map(|&(from, _)| from));
+
+    variants.sort();
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_variants_test() {
+        assert_eq!(case_variants('a'), vec!['A', 'a']);
+        assert_eq!(case_variants('K'), vec!['K', 'k', '\u{212A}']);
+        assert_eq!(case_variants('ς'), vec!['Σ', 'ς', 'σ']);
+        assert_eq!(case_variants('ſ'), vec!['S', 's', 'ſ']);
+        assert_eq!(case_variants('ǅ'), vec!['Ǆ', 'ǅ', 'ǆ']);
+        assert_eq!(case_variants('i'), vec!['I', 'i']);
+        assert_eq!(case_variants('ı'), vec!['ı']);
+        assert_eq!(case_variants('İ'), vec!['İ']);
+        assert_eq!(case_variants('ß'), vec!['ß', 'ẞ']);
+        assert_eq!(case_variants('1'), vec!['1']);
+    }
+}
@@ -0,0 +1,178 @@
+//! Char equivalence classes
+//!
+//! Chars that every state treats the same way are put in the same class. Table driven backends
+//! can then store one column per class, and look up `class[byte]` before `table[state][class]`.
+//!
+//! Chars that no arm mentions are all in class 0. The other classes are numbered from 1 in the
+//! order of their smallest char.
+
+use machine::TransitionTable;
+use statements::*;
+use automata_core::string_interning::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// The transition of a state for a char
+type Behaviour = (InternedString, Destination, Lookahead);
+
+/// The class of every char of a machine
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CharClasses {
+    /// Class of the chars not in class 0
+    classes: BTreeMap<char, u32>,
+    class_count: u32,
+}
+
+impl CharClasses {
+    /// Group the chars of some states
+    pub(crate) fn from_states(state_table: &BTreeMap<InternedString, TransitionTable>) -> Self {
+        let mut behaviours: BTreeMap<char, Vec<Behaviour>> = BTreeMap::new();
+
+        for (&state, transition_table) in state_table {
+            for input in transition_table.explicit_inputs() {
+                if let Some((destination, lookahead)) =
+                    transition_table.explicit_destination_for(input)
+                {
+                    behaviours
+                        .entry(input)
+                        .or_default()
+                        .push((state, destination, lookahead));
+                }
+            }
+        }
+
+        let mut class_ids: HashMap<Vec<Behaviour>, u32> = HashMap::new();
+        let mut classes = BTreeMap::new();
+
+        for (chr, behaviour) in behaviours {
+            let next_class = class_ids.len() as u32 + 1;
+            let class = *class_ids.entry(behaviour).or_insert(next_class);
+            classes.insert(chr, class);
+        }
+
+        Self {
+            classes,
+            class_count: class_ids.len() as u32 + 1,
+        }
+    }
+
+    /// The class of a char
+    pub fn class_of(&self, chr: char) -> u32 {
+        self.classes.get(&chr).cloned().unwrap_or(0)
+    }
+
+    /// Number of classes, class 0 included
+    pub fn class_count(&self) -> u32 {
+        self.class_count
+    }
+
+    /// Runs of consecutive chars sharing a class other than 0, as `(first, last, class)`
+    pub fn ranges(&self) -> Vec<(char, char, u32)> {
+        let mut ranges: Vec<(char, char, u32)> = Vec::new();
+
+        for (&chr, &class) in &self.classes {
+            match ranges.last_mut() {
+                Some(&mut (_, ref mut last, last_class))
+                    if last_class == class && *last as u32 + 1 == chr as u32 =>
+                {
+                    *last = chr
+                }
+                _ => ranges.push((chr, chr, class)),
+            }
+        }
+
+        ranges
+    }
+
+    /// The class of each byte, or of the chars below 256
+    pub fn byte_classes(&self) -> Vec<u32> {
+        (0..=255u8)
+            .map(|byte| self.class_of(char::from(byte)))
+            .collect()
+    }
+
+    /// The smallest char of each class, by class
+    /// Class 0 may have no byte, in which case its char is above 255
+    pub fn representatives(&self) -> Vec<char> {
+        let mut representatives = vec!['\0'; self.class_count as usize];
+
+        for (&chr, &class) in self.classes.iter().rev() {
+            representatives[class as usize] = chr;
+        }
+
+        representatives[0] = (0..=::std::char::MAX as u32)
+            .filter_map(::std::char::from_u32)
+            .find(|chr| !self.classes.contains_key(chr))
+            .unwrap_or('\0');
+
+        representatives
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Automata;
+    use automata_core::string_interning::*;
+    use definitions::*;
+    use statements::*;
+    use states::*;
+
+    #[test]
+    fn char_classes_test() {
+        let mut start = StateDefinition::new(intern("start"));
+        start.push_statement(Statement::new(
+            Destination::State(intern("identifier")),
+            StatementMatchKind::Range(CharRange::new('a', 'z')),
+            Lookahead::Keep,
+        ));
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("X")),
+                StatementMatchKind::Literal('x'),
+                Lookahead::Consume,
+            )
+            .with_priority(1),
+        );
+
+        let mut minus = Statement::new(
+            Destination::Return(intern("ARROW")),
+            StatementMatchKind::Literal('-'),
+            Lookahead::Consume,
+        );
+        minus.context = Some(TrailingContext::new(
+            false,
+            vec![StatementMatchKind::Literal('>')],
+        ));
+        start.push_statement(minus);
+
+        let mut identifier = StateDefinition::new(intern("identifier"));
+        identifier.push_statement(Statement::new(
+            Destination::State(intern("Self")),
+            StatementMatchKind::Range(CharRange::new('a', 'z')),
+            Lookahead::Keep,
+        ));
+        identifier.push_statement(Statement::new(
+            Destination::Return(intern("IDENTIFIER")),
+            StatementMatchKind::Default,
+            Lookahead::Keep,
+        ));
+
+        let mut definitions = Definitions::new();
+        definitions.push_state(start);
+        definitions.push_state(identifier);
+
+        let classes = Automata::resolve_from(definitions).char_classes().clone();
+
+        assert_eq!(classes.class_count(), 5);
+        assert_eq!(classes.class_of('a'), classes.class_of('w'));
+        assert_eq!(classes.class_of('a'), classes.class_of('z'));
+        assert_ne!(classes.class_of('a'), classes.class_of('x'));
+        assert_ne!(classes.class_of('>'), 0);
+        assert_eq!(classes.class_of('0'), 0);
+        assert_eq!(classes.class_of('λ'), 0);
+
+        let representatives = classes.representatives();
+        assert_eq!(representatives[0], '\0');
+        assert_eq!(representatives[classes.class_of('q') as usize], 'a');
+        assert_eq!(classes.byte_classes()[b'x' as usize], classes.class_of('x'));
+    }
+}
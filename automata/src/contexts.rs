@@ -0,0 +1,277 @@
+//! Trailing contexts, as in `'-' / '>' =>> return ARROW`
+//!
+//! Contexts are compiled into states once every other arm is resolved, so running a machine
+//! never looks ahead. When a char has arms with a context in a state, the state marks the
+//! position before the char, consumes it and goes to a state checking the first context. That
+//! state reads the input that follows, then rolls back to the mark and continues in a state
+//! reading the char again:
+//!
+//! * When the context decided for the arm, the char leads where the arm does
+//! * Otherwise the char is marked again to check the next context. After the last one, the char
+//!   leads where it does without its context arms
+//!
+//! A context decides for its arm once one of its alternatives has been read, and against it as
+//! soon as none of them can match anymore or the input ends. A negated context decides the other
+//! way around.
+//!
+//! The chars of a state with the same context arms and leading to the same place without them
+//! share their states. They are named after the state and a number, for example
+//! `start_context_1`, with the chars read so far appended for the states checking sequences, and
+//! `_applies` or `_does_not_apply` for the states reading the char again.
+
+use super::Automata;
+use automata_core::string_interning::*;
+use machine::*;
+use provenance::*;
+use statements::*;
+use std::collections::BTreeMap;
+
+/// The chars of a state with the same context arms, leading to the same place without them
+#[derive(Debug)]
+struct ContextGroup {
+    state: InternedString,
+    inputs: Vec<char>,
+    /// The context arms, in the order they are tried
+    guards: Vec<(TrailingContext, Destination, Lookahead)>,
+    unguarded: Option<(Destination, Lookahead)>,
+}
+
+/// Prefix tree of the alternatives of a context
+#[derive(Debug, Default)]
+struct ContextTrie {
+    /// The chars that can be read next, whether an alternative ends with them, and what
+    /// can be read after them otherwise
+    edges: BTreeMap<char, (bool, ContextTrie)>,
+}
+
+impl ContextTrie {
+    fn new(context: &TrailingContext) -> Self {
+        let mut trie = ContextTrie::default();
+
+        for alternative in &context.alternatives {
+            match *alternative {
+                StatementMatchKind::Literal(chr) => trie.insert(&[chr]),
+                StatementMatchKind::Range(range) => {
+                    for chr in range {
+                        trie.insert(&[chr]);
+                    }
+                }
+                StatementMatchKind::Sequence(ref sequence) => trie.insert(sequence),
+                StatementMatchKind::Default | StatementMatchKind::EndOfInput => {}
+            }
+        }
+
+        trie
+    }
+
+    /// Add an alternative. Once an alternative is read the context has decided, so the ones it
+    /// starts are left out
+    fn insert(&mut self, sequence: &[char]) {
+        let (&first, rest) = match sequence.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+
+        let edge = self.edges.entry(first).or_default();
+
+        if rest.is_empty() {
+            *edge = (true, ContextTrie::default());
+        } else if !edge.0 {
+            edge.1.insert(rest);
+        }
+    }
+}
+
+/// The states taking part in checking one context arm
+struct GuardStates {
+    base_name: String,
+    check: InternedString,
+    applies: InternedString,
+    does_not_apply: InternedString,
+}
+
+impl Automata {
+    /// Replace the context arms of every state with states checking them
+    pub(crate) fn compile_contexts(&mut self) {
+        let mut groups: Vec<ContextGroup> = Vec::new();
+
+        for state in self.states() {
+            let mut inputs = self
+                .fallback_chain(state)
+                .iter()
+                .flat_map(|transition_table| transition_table.explicit_inputs())
+                .collect::<Vec<_>>();
+            inputs.sort();
+            inputs.dedup();
+
+            for input in inputs {
+                let guards = self.guarded_transitions(state, input);
+                if guards.is_empty() {
+                    continue;
+                }
+
+                let unguarded = self.unguarded_transition(state, input);
+                let group = groups.iter_mut().find(|group| {
+                    group.state == state && group.guards == guards && group.unguarded == unguarded
+                });
+
+                match group {
+                    Some(group) => group.inputs.push(input),
+                    None => groups.push(ContextGroup {
+                        state,
+                        inputs: vec![input],
+                        guards,
+                        unguarded,
+                    }),
+                }
+            }
+        }
+
+        for transition_table in self.state_table.values_mut() {
+            transition_table.clear_context_destinations();
+        }
+
+        let mut counts: BTreeMap<InternedString, usize> = BTreeMap::new();
+
+        for group in groups {
+            let count = counts.entry(group.state).or_insert(0);
+            self.compile_context_group(&group, count);
+        }
+    }
+
+    fn compile_context_group(&mut self, group: &ContextGroup, count: &mut usize) {
+        let guard_states = group
+            .guards
+            .iter()
+            .map(|_| {
+                *count += 1;
+                let base_name = self
+                    .interner
+                    .with_str(group.state, |state| format!("{}_context_{}", state, count))
+                    .unwrap();
+                let part_name = |part: &str| format!("{}_{}", base_name, part);
+
+                GuardStates {
+                    check: self.context_state_name(&base_name),
+                    applies: self.context_state_name(&part_name("applies")),
+                    does_not_apply: self.context_state_name(&part_name("does_not_apply")),
+                    base_name,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (index, &(ref context, destination, lookahead)) in group.guards.iter().enumerate() {
+            let states = &guard_states[index];
+            let mut applies = TransitionTable::new();
+            let mut does_not_apply = TransitionTable::new();
+
+            for &input in &group.inputs {
+                applies.add_destination(input, destination, lookahead);
+
+                match guard_states.get(index + 1) {
+                    Some(next) => does_not_apply.add_mark_transition(input, next.check),
+                    None => {
+                        if let Some((destination, lookahead)) = group.unguarded {
+                            does_not_apply.add_destination(input, destination, lookahead);
+                        }
+                    }
+                }
+            }
+
+            self.add_context_state(group.state, states.applies, ContextPart::Applies, applies);
+            self.add_context_state(
+                group.state,
+                states.does_not_apply,
+                ContextPart::DoesNotApply,
+                does_not_apply,
+            );
+
+            let (found, not_found) = if context.negated {
+                (states.does_not_apply, states.applies)
+            } else {
+                (states.applies, states.does_not_apply)
+            };
+
+            self.add_check_states(
+                (group.state, &states.base_name),
+                states.check,
+                &ContextTrie::new(context),
+                "",
+                (found, not_found),
+            );
+        }
+
+        let transition_table = self.state_table.get_mut(&group.state).unwrap();
+        for &input in &group.inputs {
+            transition_table.replace_destination(
+                input,
+                Destination::Mark(guard_states[0].check),
+                Lookahead::Consume,
+            );
+        }
+    }
+
+    /// Add the states reading the alternatives of a context below a node of its tree, given the
+    /// state the arm belongs to and the name of the first state checking the context
+    /// `targets` are where to roll back to when an alternative is read, and when none can be
+    fn add_check_states(
+        &mut self,
+        origin: (InternedString, &str),
+        name: InternedString,
+        trie: &ContextTrie,
+        prefix: &str,
+        targets: (InternedString, InternedString),
+    ) {
+        let (owner, base_name) = origin;
+        let (found, not_found) = targets;
+        let mut transition_table = TransitionTable::new();
+
+        for (&chr, &(ends, ref child)) in &trie.edges {
+            if ends {
+                transition_table.add_rollback_transition(chr, found);
+                continue;
+            }
+
+            let child_prefix = format!("{}{}", prefix, chr);
+            let child_name = self.context_state_name(&format!("{}_{}", base_name, child_prefix));
+
+            self.add_check_states(origin, child_name, child, &child_prefix, targets);
+            transition_table.add_transition(chr, child_name);
+        }
+
+        transition_table.set_default_transition(Destination::Rollback(not_found), Lookahead::Keep);
+        transition_table.set_end_of_input_transition(Destination::Rollback(not_found));
+
+        let part = ContextPart::Check {
+            prefix: prefix.to_string(),
+        };
+        self.add_context_state(owner, name, part, transition_table);
+    }
+
+    /// A generated name no other state has, appending a number if needed
+    fn context_state_name(&self, base_name: &str) -> InternedString {
+        let mut name = self.interner.intern_generated(base_name);
+        let mut suffix = 1;
+
+        while self.generated_states.contains_key(&name) || self.state_table.contains_key(&name) {
+            suffix += 1;
+            name = self
+                .interner
+                .intern_generated(format!("{}_{}", base_name, suffix));
+        }
+
+        name
+    }
+
+    fn add_context_state(
+        &mut self,
+        owner: InternedString,
+        name: InternedString,
+        part: ContextPart,
+        transition_table: TransitionTable,
+    ) {
+        self.generated_states
+            .insert(name, Provenance::Context { state: owner, part });
+        self.state_table.insert(name, transition_table);
+    }
+}
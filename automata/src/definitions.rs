@@ -0,0 +1,100 @@
+use super::states::*;
+use automata_core::string_interning::*;
+
+/// What a machine reads
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Alphabet {
+    /// Chars of some text
+    #[default]
+    Chars,
+    /// Bytes, written `b'\x00'`, `b"\xDE\xAD"` or `0x00..0xFF`
+    /// Each byte is stored as the char of the same value, from U+0000 to U+00FF
+    Bytes,
+}
+
+/// Everything declared by an automata definition file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definitions {
+    /// The state definitions, in declaration order
+    pub states: Vec<StateDefinition>,
+    /// The states declared with `entry`, in declaration order
+    pub entry_points: Vec<InternedString>,
+    /// The keyword tables, in declaration order
+    pub keywords: Vec<KeywordDefinition>,
+    /// Token pairs declared with `priority A > B;`, the first token wins
+    pub priorities: Vec<(InternedString, InternedString)>,
+    /// What the machine reads
+    pub alphabet: Alphabet,
+    /// The interner the names come from
+    pub interner: Interner,
+}
+
+impl Definitions {
+    /// Create an empty set of definitions, with names from the global interner
+    pub fn new() -> Self {
+        Self::with_interner(Interner::global())
+    }
+
+    /// Create an empty set of definitions, with names from an interner
+    pub fn with_interner(interner: Interner) -> Self {
+        Self {
+            states: Vec::new(),
+            entry_points: Vec::new(),
+            keywords: Vec::new(),
+            priorities: Vec::new(),
+            alphabet: Alphabet::Chars,
+            interner,
+        }
+    }
+
+    /// Push a new state definition
+    pub fn push_state(&mut self, state_definition: StateDefinition) {
+        self.states.push(state_definition);
+    }
+
+    /// Push a new entry point
+    pub fn push_entry_point(&mut self, state: InternedString) {
+        self.entry_points.push(state);
+    }
+
+    /// Push a new keyword table
+    pub fn push_keywords(&mut self, keyword_definition: KeywordDefinition) {
+        self.keywords.push(keyword_definition);
+    }
+
+    /// Push a new token priority, `higher` wins over `lower`
+    pub fn push_priority(&mut self, higher: InternedString, lower: InternedString) {
+        self.priorities.push((higher, lower));
+    }
+}
+
+impl Default for Definitions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keywords that replace the tokens returned by a state
+/// `keywords identifier { "fn" => KW_FN }`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeywordDefinition {
+    /// The state whose tokens are looked up
+    pub state: InternedString,
+    /// Keyword text and token pairs, in declaration order
+    pub keywords: Vec<(String, InternedString)>,
+}
+
+impl KeywordDefinition {
+    /// Create an empty keyword table for a state
+    pub fn new(state: InternedString) -> Self {
+        Self {
+            state,
+            keywords: Vec::new(),
+        }
+    }
+
+    /// Push a new keyword
+    pub fn push_keyword(&mut self, keyword: String, token: InternedString) {
+        self.keywords.push((keyword, token));
+    }
+}
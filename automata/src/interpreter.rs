@@ -0,0 +1,1189 @@
+//! Runs a resolved Automata directly on some input
+//!
+//! Every token is scanned from the lexer mode on top of the mode stack, which starts out
+//! holding the entry point. For each char, the state's explicit transitions are looked at
+//! first, then its default transition.
+//!
+//! * `=> state` consumes the char and moves to `state`
+//! * `=> return TOKEN` ends the token and leaves the char in place for the next token, while
+//!   `=>> return TOKEN` consumes it as the last char of the token
+//! * `=> error "message"` reports an error carrying `message`. Like a return, it leaves the
+//!   char in place unless written `=>> error "message"`
+//! * `=> push mode` consumes the char, pushes `mode` and starts a new token from it
+//! * `=> pop` consumes the char, pops the current mode and starts a new token from the
+//!   mode below it
+//!
+//! The chars read before a push or pop are not part of any token. Trailing contexts are checked
+//! by generated states, see `contexts`: a mark remembers the position before its char and
+//! consumes it, a rollback goes back to that position, whether the input has ended or not.
+//!
+//! When the input ends, the current state's `eof` arm is followed. An `eof => state` arm
+//! continues with the `eof` arm of `state`, while `eof => push mode` and `eof => pop` only
+//! update the mode stack. Without an `eof` arm, the lexer stops quietly between tokens and
+//! reports an error in the middle of one. Once an `eof` arm fires at the start of a token,
+//! the lexer stops.
+//!
+//! After an error, the lexer either stops or resumes right after the error, depending on its
+//! ErrorRecovery. When an error didn't consume anything, resuming skips the offending char.
+//!
+//! Byte machines run on `&[u8]` the same way, each byte being read as the char of the same value.
+
+use super::Automata;
+use automata_core::string_interning::*;
+use definitions::Alphabet;
+use statements::{Destination, Lookahead};
+use std::iter::Map;
+use std::slice::Iter;
+use std::str::Chars;
+
+/// Maximum number of modes on the stack, entry point included, unless configured otherwise
+pub const DEFAULT_MAX_STACK_DEPTH: usize = 64;
+
+/// What the lexer does after an error
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorRecovery {
+    /// Stop lexing
+    Stop,
+    /// Keep lexing after the error
+    Resume,
+}
+
+/// Runs an Automata on input
+#[derive(Debug)]
+pub struct Interpreter<'automata> {
+    automata: &'automata Automata,
+    max_stack_depth: usize,
+    error_recovery: ErrorRecovery,
+}
+
+impl<'automata> Interpreter<'automata> {
+    /// Create a new Interpreter for an Automata
+    pub fn new(automata: &'automata Automata) -> Self {
+        Self {
+            automata,
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
+            error_recovery: ErrorRecovery::Stop,
+        }
+    }
+
+    /// Set the maximum number of modes on the stack, entry point included
+    pub fn with_max_stack_depth(mut self, max_stack_depth: usize) -> Self {
+        self.max_stack_depth = max_stack_depth;
+        self
+    }
+
+    /// Set what the lexer does after an error, stopping by default
+    pub fn with_error_recovery(mut self, error_recovery: ErrorRecovery) -> Self {
+        self.error_recovery = error_recovery;
+        self
+    }
+
+    /// Lex some text starting from an entry point
+    /// Returns `None` if `entry_point` isn't one of the automata's entry points, or if the
+    /// automata reads bytes
+    pub fn lex<'input>(
+        &self,
+        entry_point: InternedString,
+        input: &'input str,
+    ) -> Option<Lexer<'automata, &'input str>> {
+        self.lexer(entry_point, input)
+    }
+
+    /// Lex some bytes starting from an entry point
+    /// Returns `None` if `entry_point` isn't one of the automata's entry points, or if the
+    /// automata reads chars
+    pub fn lex_bytes<'input>(
+        &self,
+        entry_point: InternedString,
+        input: &'input [u8],
+    ) -> Option<Lexer<'automata, &'input [u8]>> {
+        self.lexer(entry_point, input)
+    }
+
+    fn lexer<I: LexerInput>(
+        &self,
+        entry_point: InternedString,
+        input: I,
+    ) -> Option<Lexer<'automata, I>> {
+        if !self.automata.entry_points().contains(&entry_point)
+            || self.automata.alphabet() != I::ALPHABET
+        {
+            return None;
+        }
+
+        Some(Lexer {
+            automata: self.automata,
+            input,
+            position: 0,
+            mark: 0,
+            mode_stack: vec![entry_point],
+            max_stack_depth: self.max_stack_depth,
+            error_recovery: self.error_recovery,
+            finished: false,
+        })
+    }
+}
+
+/// Input a Lexer runs on, text or bytes
+/// Positions are byte offsets in both cases
+pub trait LexerInput: Copy {
+    /// The alphabet of the machines that can run on this input
+    const ALPHABET: Alphabet;
+
+    /// Iterator over the chars of the input from a position
+    type Chars: Iterator<Item = char> + Clone;
+
+    /// The chars of the input from a position
+    fn chars_from(self, position: usize) -> Self::Chars;
+
+    /// How many bytes a char read from this input takes
+    fn char_len(chr: char) -> usize;
+
+    /// The text between two positions, to look keywords up
+    fn text(self, start: usize, end: usize) -> String;
+
+    /// The error for a char no transition reads
+    fn unexpected(chr: char) -> LexErrorKind;
+}
+
+impl<'input> LexerInput for &'input str {
+    const ALPHABET: Alphabet = Alphabet::Chars;
+
+    type Chars = Chars<'input>;
+
+    fn chars_from(self, position: usize) -> Self::Chars {
+        self[position..].chars()
+    }
+
+    fn char_len(chr: char) -> usize {
+        chr.len_utf8()
+    }
+
+    fn text(self, start: usize, end: usize) -> String {
+        self[start..end].to_string()
+    }
+
+    fn unexpected(chr: char) -> LexErrorKind {
+        LexErrorKind::UnexpectedChar(chr)
+    }
+}
+
+impl<'input> LexerInput for &'input [u8] {
+    const ALPHABET: Alphabet = Alphabet::Bytes;
+
+    type Chars = Map<Iter<'input, u8>, fn(&u8) -> char>;
+
+    fn chars_from(self, position: usize) -> Self::Chars {
+        fn byte_char(byte: &u8) -> char {
+            char::from(*byte)
+        }
+
+        self[position..].iter().map(byte_char as fn(&u8) -> char)
+    }
+
+    fn char_len(_: char) -> usize {
+        1
+    }
+
+    fn text(self, start: usize, end: usize) -> String {
+        self[start..end].iter().map(|&byte| char::from(byte)).collect()
+    }
+
+    fn unexpected(chr: char) -> LexErrorKind {
+        LexErrorKind::UnexpectedByte(chr as u8)
+    }
+}
+
+/// A token read from the input
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Lexeme {
+    /// The returned token's name
+    pub token: InternedString,
+    /// Byte range of the token in the input
+    pub span: (usize, usize),
+}
+
+/// An error found by the lexer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    /// Byte range from the start of the token to the error
+    pub span: (usize, usize),
+}
+
+/// A kind of lexing error
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LexErrorKind {
+    /// No transition for this char
+    UnexpectedChar(char),
+    /// No transition for this byte
+    UnexpectedByte(u8),
+    /// The input ended in the middle of a token
+    UnexpectedEndOfInput,
+    /// A token would have been returned without reading anything
+    EmptyToken(InternedString),
+    /// A transition goes to a state that isn't defined
+    UndefinedState(InternedString),
+    /// A push went over the maximum stack depth
+    StackOverflow,
+    /// A pop was attempted with only the entry point on the stack
+    StackUnderflow,
+    /// An `=> error "message"` arm was reached
+    Custom(InternedString),
+}
+
+/// Iterator over the tokens of some input
+#[derive(Debug)]
+pub struct Lexer<'automata, I: LexerInput> {
+    automata: &'automata Automata,
+    input: I,
+    position: usize,
+    /// Position before the char of the last mark
+    mark: usize,
+    mode_stack: Vec<InternedString>,
+    max_stack_depth: usize,
+    error_recovery: ErrorRecovery,
+    finished: bool,
+}
+
+impl<'automata, I: LexerInput> Lexer<'automata, I> {
+    /// The modes on the stack, entry point first
+    pub fn mode_stack(&self) -> &[InternedString] {
+        &self.mode_stack
+    }
+
+    /// Scan the next token
+    fn scan_token(&mut self) -> Option<Result<Lexeme, LexError>> {
+        let start = self.position;
+        let state = *self.mode_stack.last()?;
+
+        self.scan(start, state)
+    }
+
+    /// Scan from a state, the token having started at `start`
+    fn scan(
+        &mut self,
+        mut start: usize,
+        mut state: InternedString,
+    ) -> Option<Result<Lexeme, LexError>> {
+        macro_rules! lex_err {
+            ($kind: expr) => {
+                return Some(Err(LexError {
+                    kind: $kind,
+                    span: (start, self.position),
+                }));
+            };
+        }
+
+        loop {
+            if !self.automata.state_table.contains_key(&state) {
+                lex_err!(LexErrorKind::UndefinedState(state));
+            }
+
+            let chr = match self.input.chars_from(self.position).next() {
+                Some(chr) => chr,
+                None => return self.end_of_input(start, state),
+            };
+
+            let (destination, lookahead) = match self.automata.unguarded_transition(state, chr) {
+                Some(transition) => transition,
+                None => {
+                    lex_err!(I::unexpected(chr));
+                }
+            };
+
+            let next_position = self.position + I::char_len(chr);
+
+            match destination {
+                Destination::State(next_state) => {
+                    self.position = next_position;
+                    state = next_state;
+                }
+                Destination::Return(token) => {
+                    if lookahead == Lookahead::Consume {
+                        self.position = next_position;
+                    }
+
+                    if self.position == start {
+                        lex_err!(LexErrorKind::EmptyToken(token));
+                    }
+
+                    return Some(Ok(Lexeme {
+                        token: self.reclassify(state, token, start),
+                        span: (start, self.position),
+                    }));
+                }
+                Destination::Error(message) => {
+                    if lookahead == Lookahead::Consume {
+                        self.position = next_position;
+                    }
+
+                    lex_err!(LexErrorKind::Custom(message));
+                }
+                Destination::Push(mode) => {
+                    if self.mode_stack.len() >= self.max_stack_depth {
+                        lex_err!(LexErrorKind::StackOverflow);
+                    }
+
+                    self.mode_stack.push(mode);
+                    self.position = next_position;
+                    start = next_position;
+                    state = mode;
+                }
+                Destination::Pop => {
+                    if self.mode_stack.len() <= 1 {
+                        lex_err!(LexErrorKind::StackUnderflow);
+                    }
+
+                    let _ = self.mode_stack.pop();
+                    self.position = next_position;
+                    start = next_position;
+                    state = *self.mode_stack.last().unwrap();
+                }
+                Destination::Mark(next_state) => {
+                    self.mark = self.position;
+                    self.position = next_position;
+                    state = next_state;
+                }
+                Destination::Rollback(next_state) => {
+                    self.position = self.mark;
+                    state = next_state;
+                }
+            }
+        }
+    }
+
+    /// Replace a token returned by `state` if its text is one of the state's keywords
+    fn reclassify(
+        &self,
+        state: InternedString,
+        token: InternedString,
+        start: usize,
+    ) -> InternedString {
+        self.automata
+            .keyword_table(self.automata.keyword_state(state))
+            .and_then(|keyword_table| keyword_table.get(&self.input.text(start, self.position)))
+            .unwrap_or(token)
+    }
+
+    /// Get ready to scan the next token after an error
+    fn recover_from(&mut self, error: LexError) {
+        match self.error_recovery {
+            ErrorRecovery::Stop => self.finished = true,
+            ErrorRecovery::Resume => {
+                if error.span.0 != error.span.1 {
+                    return;
+                }
+
+                match self.input.chars_from(self.position).next() {
+                    Some(chr) => self.position += I::char_len(chr),
+                    None => self.finished = true,
+                }
+            }
+        }
+    }
+
+    /// Follow the `eof` arms once the input has ended in `state`
+    fn end_of_input(
+        &mut self,
+        start: usize,
+        mut state: InternedString,
+    ) -> Option<Result<Lexeme, LexError>> {
+        let mut visited_states = Vec::new();
+
+        macro_rules! lex_err {
+            ($kind: expr) => {
+                return Some(Err(LexError {
+                    kind: $kind,
+                    span: (start, self.position),
+                }));
+            };
+        }
+
+        loop {
+            if !self.automata.state_table.contains_key(&state) {
+                lex_err!(LexErrorKind::UndefinedState(state));
+            }
+
+            let destination = match self.automata.end_of_input_transition_for(state) {
+                Some(destination) => destination,
+                None if self.position == start && visited_states.is_empty() => return None,
+                None => {
+                    lex_err!(LexErrorKind::UnexpectedEndOfInput);
+                }
+            };
+
+            if let Destination::Rollback(next_state) = destination {
+                self.position = self.mark;
+                return self.scan(start, next_state);
+            }
+
+            if self.position == start {
+                self.finished = true;
+            }
+
+            match destination {
+                Destination::State(next_state) | Destination::Mark(next_state) => {
+                    visited_states.push(state);
+
+                    if visited_states.contains(&next_state) {
+                        lex_err!(LexErrorKind::UnexpectedEndOfInput);
+                    }
+
+                    state = next_state;
+                }
+                Destination::Return(token) => {
+                    return Some(Ok(Lexeme {
+                        token: self.reclassify(state, token, start),
+                        span: (start, self.position),
+                    }));
+                }
+                Destination::Error(message) => {
+                    lex_err!(LexErrorKind::Custom(message));
+                }
+                // The chars read since `start` can't be returned, they are reported, and the
+                // push or pop is done once the input is read again from where they end
+                Destination::Push(_) | Destination::Pop if self.position != start => {
+                    lex_err!(LexErrorKind::UnexpectedEndOfInput);
+                }
+                Destination::Push(mode) => {
+                    if self.mode_stack.len() >= self.max_stack_depth {
+                        lex_err!(LexErrorKind::StackOverflow);
+                    }
+
+                    self.mode_stack.push(mode);
+                    return None;
+                }
+                Destination::Pop => {
+                    if self.mode_stack.len() <= 1 {
+                        lex_err!(LexErrorKind::StackUnderflow);
+                    }
+
+                    let _ = self.mode_stack.pop();
+                    return None;
+                }
+                Destination::Rollback(_) => unreachable!(),
+            }
+        }
+    }
+}
+
+impl<'automata, I: LexerInput> Iterator for Lexer<'automata, I> {
+    type Item = Result<Lexeme, LexError>;
+
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.scan_token();
+
+        match result {
+            Some(Ok(_)) => {}
+            Some(Err(error)) => self.recover_from(error),
+            None => self.finished = true,
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::*;
+    use statements::*;
+    use test_fixtures::state;
+
+    /// Text outside `{` `}` is lexed as TEXT, the inside of each brace pair as EXPR
+    fn template_automata() -> Automata {
+        let mut definitions = Definitions::new();
+        definitions.push_entry_point(intern("text"));
+
+        definitions.push_state(state(
+            "text",
+            vec![
+                (
+                    StatementMatchKind::Literal('{'),
+                    Destination::Push(intern("expr")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::State(intern("text_body")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+        definitions.push_state(state(
+            "text_body",
+            vec![
+                (
+                    StatementMatchKind::Literal('{'),
+                    Destination::Return(intern("TEXT")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+        definitions.push_state(state(
+            "expr",
+            vec![
+                (
+                    StatementMatchKind::Literal('}'),
+                    Destination::Pop,
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Literal('{'),
+                    Destination::Push(intern("Self")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Range(CharRange::new('a', 'z')),
+                    Destination::Return(intern("EXPR")),
+                    Lookahead::Consume,
+                ),
+            ],
+        ));
+
+        Automata::resolve_from(definitions)
+    }
+
+    #[test]
+    fn push_pop_test() {
+        let automata = template_automata();
+        let interpreter = Interpreter::new(&automata);
+
+        let tokens = interpreter
+            .lex(intern("text"), "{ab}")
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Lexeme {
+                    token: intern("EXPR"),
+                    span: (1, 2),
+                }),
+                Ok(Lexeme {
+                    token: intern("EXPR"),
+                    span: (2, 3),
+                }),
+            ]
+        );
+
+        let mut lexer = interpreter.lex(intern("text"), "ab").unwrap();
+        assert_eq!(
+            lexer.next(),
+            Some(Err(LexError {
+                kind: LexErrorKind::UnexpectedEndOfInput,
+                span: (0, 2),
+            }))
+        );
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn stack_depth_test() {
+        let automata = template_automata();
+        let interpreter = Interpreter::new(&automata).with_max_stack_depth(3);
+
+        let error = interpreter
+            .lex(intern("text"), "{{a}{{")
+            .unwrap()
+            .filter_map(|result| result.err())
+            .next()
+            .unwrap();
+
+        assert_eq!(error.kind, LexErrorKind::StackOverflow);
+        assert_eq!(error.span, (5, 5));
+    }
+
+    #[test]
+    fn end_of_input_test() {
+        let mut definitions = Definitions::new();
+        definitions.push_state(state(
+            "start",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('0', '9')),
+                    Destination::State(intern("number")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::EndOfInput,
+                    Destination::Return(intern("EOF")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+        definitions.push_state(state(
+            "number",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('0', '9')),
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::Return(intern("NUMBER")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::EndOfInput,
+                    Destination::Return(intern("NUMBER")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+
+        let automata = Automata::resolve_from(definitions);
+        let interpreter = Interpreter::new(&automata);
+
+        let tokens = interpreter
+            .lex(intern("start"), "12")
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Lexeme {
+                    token: intern("NUMBER"),
+                    span: (0, 2),
+                }),
+                Ok(Lexeme {
+                    token: intern("EOF"),
+                    span: (2, 2),
+                }),
+            ]
+        );
+
+        let tokens = interpreter
+            .lex(intern("start"), "")
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![Ok(Lexeme {
+                token: intern("EOF"),
+                span: (0, 0),
+            })]
+        );
+    }
+
+    #[test]
+    fn end_of_input_in_token_test() {
+        for &destination in &[Destination::Pop, Destination::Push(intern("start"))] {
+            let mut definitions = Definitions::new();
+            definitions.push_state(state(
+                "start",
+                vec![
+                    (
+                        StatementMatchKind::Range(CharRange::new('a', 'z')),
+                        Destination::State(intern("word")),
+                        Lookahead::Keep,
+                    ),
+                    (
+                        StatementMatchKind::EndOfInput,
+                        Destination::Return(intern("END")),
+                        Lookahead::Keep,
+                    ),
+                ],
+            ));
+            definitions.push_state(state(
+                "word",
+                vec![
+                    (
+                        StatementMatchKind::Range(CharRange::new('a', 'z')),
+                        Destination::State(intern("Self")),
+                        Lookahead::Keep,
+                    ),
+                    (
+                        StatementMatchKind::Default,
+                        Destination::Return(intern("WORD")),
+                        Lookahead::Keep,
+                    ),
+                    (StatementMatchKind::EndOfInput, destination, Lookahead::Keep),
+                ],
+            ));
+
+            let automata = Automata::resolve_from(definitions);
+            let tokens = Interpreter::new(&automata)
+                .with_error_recovery(ErrorRecovery::Resume)
+                .lex(intern("start"), "ab")
+                .unwrap()
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                tokens,
+                vec![
+                    Err(LexError {
+                        kind: LexErrorKind::UnexpectedEndOfInput,
+                        span: (0, 2),
+                    }),
+                    Ok(Lexeme {
+                        token: intern("END"),
+                        span: (2, 2),
+                    }),
+                ],
+                "eof => {:?}",
+                destination
+            );
+        }
+    }
+
+    #[test]
+    fn error_test() {
+        let mut definitions = Definitions::new();
+        definitions.push_state(state(
+            "start",
+            vec![(
+                StatementMatchKind::Literal('"'),
+                Destination::State(intern("string")),
+                Lookahead::Keep,
+            )],
+        ));
+        definitions.push_state(state(
+            "string",
+            vec![
+                (
+                    StatementMatchKind::Literal('"'),
+                    Destination::Return(intern("STRING")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Literal('\n'),
+                    Destination::Error(intern("newline in string")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::EndOfInput,
+                    Destination::Error(intern("unterminated string")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+
+        let automata = Automata::resolve_from(definitions);
+        let interpreter = Interpreter::new(&automata).with_error_recovery(ErrorRecovery::Resume);
+
+        let tokens = interpreter
+            .lex(intern("start"), "\"a\nx\"b\"\"c")
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Err(LexError {
+                    kind: LexErrorKind::Custom(intern("newline in string")),
+                    span: (0, 2),
+                }),
+                Err(LexError {
+                    kind: LexErrorKind::UnexpectedChar('\n'),
+                    span: (2, 2),
+                }),
+                Err(LexError {
+                    kind: LexErrorKind::UnexpectedChar('x'),
+                    span: (3, 3),
+                }),
+                Ok(Lexeme {
+                    token: intern("STRING"),
+                    span: (4, 7),
+                }),
+                Err(LexError {
+                    kind: LexErrorKind::Custom(intern("unterminated string")),
+                    span: (7, 9),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_context_test() {
+        let mut definitions = Definitions::new();
+
+        let mut start = state(
+            "start",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('0', '9')),
+                    Destination::Return(intern("DIGIT")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Literal('.'),
+                    Destination::Return(intern("DOT")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Literal('<'),
+                    Destination::Return(intern("LESS")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Literal('-'),
+                    Destination::Return(intern("MINUS")),
+                    Lookahead::Consume,
+                ),
+            ],
+        );
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("LESS_BEFORE_ARROW")),
+                StatementMatchKind::Literal('<'),
+                Lookahead::Consume,
+            )
+            .with_context(TrailingContext::new(
+                false,
+                vec![StatementMatchKind::Sequence(vec!['<', '-'])],
+            )),
+        );
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("DIGIT_BEFORE_DOT")),
+                StatementMatchKind::Range(CharRange::new('0', '9')),
+                Lookahead::Consume,
+            )
+            .with_context(TrailingContext::new(
+                false,
+                vec![StatementMatchKind::Literal('.')],
+            )),
+        );
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("SELF")),
+                StatementMatchKind::Sequence(vec!['S', 'e', 'l', 'f']),
+                Lookahead::Consume,
+            )
+            .with_context(TrailingContext::new(
+                true,
+                vec![StatementMatchKind::Range(CharRange::new('a', 'z'))],
+            )),
+        );
+        definitions.push_state(start);
+
+        let automata = Automata::resolve_from(definitions);
+        let interpreter = Interpreter::new(&automata);
+
+        let tokens = interpreter
+            .lex(intern("start"), "1.2Self")
+            .unwrap()
+            .map(|result| result.map(|lexeme| lexeme.token))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(intern("DIGIT_BEFORE_DOT")),
+                Ok(intern("DOT")),
+                Ok(intern("DIGIT")),
+                Ok(intern("SELF")),
+            ]
+        );
+
+        let tokens = interpreter
+            .lex(intern("start"), "<<<-<")
+            .unwrap()
+            .map(|result| result.map(|lexeme| (lexeme.token, lexeme.span)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ok((intern("LESS"), (0, 1))),
+                Ok((intern("LESS_BEFORE_ARROW"), (1, 2))),
+                Ok((intern("LESS"), (2, 3))),
+                Ok((intern("MINUS"), (3, 4))),
+                Ok((intern("LESS"), (4, 5))),
+            ]
+        );
+
+        let error = interpreter
+            .lex(intern("start"), "Selfish")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        assert_eq!(
+            error,
+            Err(LexError {
+                kind: LexErrorKind::UnexpectedChar('f'),
+                span: (0, 3),
+            })
+        );
+    }
+
+    #[test]
+    fn keywords_test() {
+        let mut definitions = Definitions::new();
+        definitions.push_state(state(
+            "start",
+            vec![(
+                StatementMatchKind::Range(CharRange::new('a', 'z')),
+                Destination::State(intern("identifier")),
+                Lookahead::Keep,
+            )],
+        ));
+        definitions.push_state(state(
+            "identifier",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('a', 'z')),
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::Return(intern("IDENTIFIER")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::EndOfInput,
+                    Destination::Return(intern("IDENTIFIER")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+
+        let mut keyword_definition = KeywordDefinition::new(intern("identifier"));
+        keyword_definition.push_keyword("fn".to_string(), intern("KW_FN"));
+        keyword_definition.push_keyword("for".to_string(), intern("KW_FOR"));
+        definitions.push_keywords(keyword_definition);
+
+        let automata = Automata::resolve_from(definitions);
+        let interpreter = Interpreter::new(&automata).with_error_recovery(ErrorRecovery::Resume);
+
+        let tokens = interpreter
+            .lex(intern("start"), "fn fork for")
+            .unwrap()
+            .filter_map(|result| result.ok())
+            .map(|lexeme| lexeme.token)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![intern("KW_FN"), intern("IDENTIFIER"), intern("KW_FOR")]
+        );
+    }
+
+    #[test]
+    fn sequence_test() {
+        let mut definitions = Definitions::new();
+        definitions.push_state(state(
+            "start",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('a', 'z')),
+                    Destination::State(intern("identifier")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Sequence("for".chars().collect()),
+                    Destination::Return(intern("FOR")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Sequence("fn".chars().collect()),
+                    Destination::Return(intern("FN")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Sequence("=".chars().collect()),
+                    Destination::Return(intern("EQ")),
+                    Lookahead::Consume,
+                ),
+            ],
+        ));
+        definitions.push_state(state(
+            "identifier",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('a', 'z')),
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::Return(intern("IDENTIFIER")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::EndOfInput,
+                    Destination::Return(intern("IDENTIFIER")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+
+        let automata = Automata::resolve_from(definitions);
+        let interpreter = Interpreter::new(&automata).with_error_recovery(ErrorRecovery::Resume);
+
+        let tokens = interpreter
+            .lex(intern("start"), "for fn fo f=fox")
+            .unwrap()
+            .filter_map(|result| result.ok())
+            .map(|lexeme| lexeme.token)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![
+                intern("FOR"),
+                intern("FN"),
+                intern("IDENTIFIER"),
+                intern("IDENTIFIER"),
+                intern("EQ"),
+                intern("IDENTIFIER"),
+            ]
+        );
+    }
+
+    #[test]
+    fn case_insensitive_test() {
+        let mut definitions = Definitions::new();
+        let mut start = state(
+            "start",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('a', 'z')),
+                    Destination::State(intern("identifier")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Sequence("select".chars().collect()),
+                    Destination::Return(intern("SELECT")),
+                    Lookahead::Consume,
+                ),
+            ],
+        );
+        start.statements = start
+            .statements
+            .into_iter()
+            .map(|statement| statement.case_insensitive())
+            .collect();
+        definitions.push_state(start);
+        definitions.push_state(state(
+            "identifier",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('a', 'z')),
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::Return(intern("IDENTIFIER")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::EndOfInput,
+                    Destination::Return(intern("IDENTIFIER")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+
+        let automata = Automata::resolve_from(definitions);
+        let interpreter = Interpreter::new(&automata).with_error_recovery(ErrorRecovery::Resume);
+
+        let tokens = interpreter
+            .lex(intern("start"), "SeLeCt \u{17F}elect Sel")
+            .unwrap()
+            .filter_map(|result| result.ok())
+            .map(|lexeme| lexeme.token)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            tokens,
+            vec![intern("SELECT"), intern("SELECT"), intern("IDENTIFIER")]
+        );
+    }
+
+    #[test]
+    fn byte_test() {
+        let mut definitions = Definitions::new();
+        definitions.alphabet = Alphabet::Bytes;
+        definitions.push_state(state(
+            "start",
+            vec![
+                (
+                    StatementMatchKind::Sequence(vec!['\u{DE}', '\u{AD}']),
+                    Destination::Return(intern("MAGIC")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Range(CharRange::new('\u{00}', '\u{7F}')),
+                    Destination::State(intern("data")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Literal('\u{FF}'),
+                    Destination::Return(intern("END")),
+                    Lookahead::Consume,
+                ),
+            ],
+        ));
+        definitions.push_state(state(
+            "data",
+            vec![
+                (
+                    StatementMatchKind::Range(CharRange::new('\u{00}', '\u{7F}')),
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::Return(intern("DATA")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+
+        let automata = Automata::resolve_from(definitions);
+        let interpreter = Interpreter::new(&automata);
+
+        assert!(interpreter.lex(intern("start"), "").is_none());
+
+        let results = interpreter
+            .lex_bytes(intern("start"), &[0xDE, 0xAD, 0x01, 0x02, 0xFF, 0x80])
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(Lexeme {
+                    token: intern("MAGIC"),
+                    span: (0, 2),
+                }),
+                Ok(Lexeme {
+                    token: intern("DATA"),
+                    span: (2, 4),
+                }),
+                Ok(Lexeme {
+                    token: intern("END"),
+                    span: (4, 5),
+                }),
+                Err(LexError {
+                    kind: LexErrorKind::UnexpectedByte(0x80),
+                    span: (5, 5),
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Keyword tables, declared with `keywords identifier { "fn" => KW_FN }`
+//!
+//! Once the state of a table returns a token, the token's text is looked up in the table and
+//! the token is replaced by the keyword's token when there is a match. Lookups go through a
+//! perfect hash built with hash and displace: keys are spread in buckets with a first hash,
+//! then each bucket gets the seed of a second hash that sends its keys to free slots. There
+//! is one slot per keyword unless no seeds can be found, in which case slots are added.
+
+use automata_core::string_interning::*;
+
+/// Number of seeds tried for a bucket before the table is made bigger
+const MAX_DISPLACEMENT: u32 = 1 << 16;
+
+/// FNV-1a hash of a key starting from `seed`, followed by a final mix so low bits are usable
+/// Backends use this function to look keywords up the same way the table does
+pub fn keyword_hash(key: &str, seed: u32) -> u32 {
+    let mut hash = 0x811c_9dc5 ^ seed;
+
+    for byte in key.bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+/// The keywords of a state, looked up with a perfect hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeywordTable {
+    /// Seed of the second hash for each bucket
+    displacements: Vec<u32>,
+    /// Keyword text and token for each slot
+    slots: Vec<Option<(String, InternedString)>>,
+}
+
+impl KeywordTable {
+    /// Build a table for keyword text / token pairs
+    /// Keywords must be unique
+    pub fn new(keywords: Vec<(String, InternedString)>) -> Self {
+        let mut slot_count = keywords.len().max(1);
+
+        loop {
+            if let Some(table) = Self::try_build(&keywords, slot_count) {
+                return table;
+            }
+
+            slot_count += slot_count / 4 + 1;
+        }
+    }
+
+    /// Try to place every keyword in `slot_count` slots
+    fn try_build(keywords: &[(String, InternedString)], slot_count: usize) -> Option<Self> {
+        let bucket_count = keywords.len().div_ceil(2).max(1);
+
+        let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); bucket_count];
+        for (index, (keyword, _)) in keywords.iter().enumerate() {
+            buckets[keyword_hash(keyword, 0) as usize % bucket_count].push(index);
+        }
+
+        let mut bucket_order = (0..bucket_count).collect::<Vec<_>>();
+        bucket_order.sort_by(|a, b| buckets[*b].len().cmp(&buckets[*a].len()));
+
+        let mut displacements = vec![0; bucket_count];
+        let mut slots: Vec<Option<(String, InternedString)>> = vec![None; slot_count];
+
+        for bucket in bucket_order {
+            if buckets[bucket].is_empty() {
+                continue;
+            }
+
+            let mut placed = false;
+
+            'displacement: for displacement in 1..MAX_DISPLACEMENT {
+                let mut bucket_slots = Vec::new();
+
+                for &index in &buckets[bucket] {
+                    let slot = keyword_hash(&keywords[index].0, displacement) as usize % slot_count;
+
+                    if slots[slot].is_some() || bucket_slots.contains(&slot) {
+                        continue 'displacement;
+                    }
+
+                    bucket_slots.push(slot);
+                }
+
+                for (&index, slot) in buckets[bucket].iter().zip(bucket_slots) {
+                    slots[slot] = Some(keywords[index].clone());
+                }
+
+                displacements[bucket] = displacement;
+                placed = true;
+                break;
+            }
+
+            if !placed {
+                return None;
+            }
+        }
+
+        Some(Self {
+            displacements,
+            slots,
+        })
+    }
+
+    /// Find the token of a keyword
+    pub fn get(&self, text: &str) -> Option<InternedString> {
+        let bucket = keyword_hash(text, 0) as usize % self.displacements.len();
+        let slot = keyword_hash(text, self.displacements[bucket]) as usize % self.slots.len();
+
+        match self.slots[slot] {
+            Some((ref keyword, token)) if keyword == text => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Seed of the second hash for each bucket
+    pub fn displacements(&self) -> &[u32] {
+        &self.displacements
+    }
+
+    /// Keyword text and token for each slot
+    pub fn slots(&self) -> &[Option<(String, InternedString)>] {
+        &self.slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyword_table_test() {
+        let keywords = (0..60)
+            .map(|i| (format!("keyword{}", i), intern(format!("KW_{}", i))))
+            .collect::<Vec<_>>();
+
+        let table = KeywordTable::new(keywords.clone());
+
+        for (keyword, token) in keywords {
+            assert_eq!(table.get(&keyword), Some(token));
+        }
+
+        assert_eq!(table.get("keyword60"), None);
+        assert_eq!(table.get(""), None);
+    }
+}
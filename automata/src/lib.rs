@@ -2,136 +2,690 @@
 
 extern crate automata_core;
 
+extern crate bincode;
+extern crate serde;
 #[macro_use]
-extern crate lazy_static;
+extern crate serde_derive;
+extern crate serde_json;
 
 pub mod statements;
 pub mod states;
 pub mod machine;
+pub mod definitions;
+pub mod interpreter;
+pub mod keywords;
+pub mod priorities;
+pub mod case_folding;
+pub mod utf8;
+pub mod classes;
+pub mod serialization;
+pub mod text_format;
+pub mod provenance;
+mod sequences;
+mod contexts;
+#[cfg(test)]
+mod test_fixtures;
 
 use machine::*;
 use states::*;
 use statements::*;
+use definitions::*;
+use keywords::*;
+use priorities::*;
+use sequences::*;
+use classes::*;
+use provenance::*;
 use automata_core::string_interning::*;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-lazy_static! {
-    static ref KEYWORD_SELF: InternedString = {
-        return intern("Self");
-    };
+/// Used when no entry point is declared
+const DEFAULT_ENTRY_POINT: InternedString = KEYWORD_START;
+
+/// Replace `Self` in a state or push destination with the name of the state it's declared in
+pub(crate) fn resolve_self(destination: Destination, state: InternedString) -> Destination {
+    match destination {
+        Destination::State(name) if name == KEYWORD_SELF => Destination::State(state),
+        Destination::Push(mode) if mode == KEYWORD_SELF => Destination::Push(state),
+        destination => destination,
+    }
 }
 
 /// A state machine
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Automata {
-    state_table: HashMap<InternedString, TransitionTable>,
+    #[serde(serialize_with = "serialization::by_name")]
+    state_table: BTreeMap<InternedString, TransitionTable>,
+    entry_points: Vec<InternedString>,
+    #[serde(serialize_with = "serialization::by_name")]
+    keyword_tables: BTreeMap<InternedString, KeywordTable>,
+    alphabet: Alphabet,
+    char_classes: CharClasses,
+    /// Where each generated state comes from
+    #[serde(serialize_with = "serialization::by_name")]
+    generated_states: BTreeMap<InternedString, Provenance>,
+    /// The interner the names come from, set again when loading
+    #[serde(skip, default = "Interner::global")]
+    interner: Interner,
 }
 
 impl Automata {
-    /// Create an automata from a series of state definitions
-    pub fn resolve_from(state_definitions: Vec<StateDefinition>) -> Self {
+    /// Create an automata from the definitions of a file
+    /// When no entry point is declared, a state named `start` is used as the only one
+    /// Arms matching the same input are ordered by their priorities, see `priorities`. When
+    /// nothing orders them the first one declared is used: definitions aren't checked here,
+    /// `select_arms` reports those conflicts and `SyntaxParser` and `AutomataBuilder` turn them
+    /// into errors
+    pub fn resolve_from(definitions: Definitions) -> Self {
         let mut automata = Automata {
-            state_table: HashMap::new(),
+            state_table: BTreeMap::new(),
+            entry_points: definitions.entry_points,
+            keyword_tables: BTreeMap::new(),
+            alphabet: definitions.alphabet,
+            char_classes: CharClasses::default(),
+            generated_states: BTreeMap::new(),
+            interner: definitions.interner,
         };
 
-        for definition in &state_definitions {
-            automata.process_state(definition);
+        let priorities = TokenPriorities::new(&definitions.priorities);
+        let mut pending_sequence_states = Vec::new();
+
+        for definition in &definitions.states {
+            automata.process_state(definition, &priorities, &mut pending_sequence_states);
+        }
+
+        automata.resolve_sequence_fallbacks(pending_sequence_states);
+        automata.compile_contexts();
+
+        for keyword_definition in definitions.keywords {
+            automata.keyword_tables.insert(
+                keyword_definition.state,
+                KeywordTable::new(keyword_definition.keywords),
+            );
+        }
+
+        if automata.entry_points.is_empty()
+            && automata.state_table.contains_key(&DEFAULT_ENTRY_POINT)
+        {
+            automata.entry_points.push(DEFAULT_ENTRY_POINT);
         }
 
+        automata.char_classes = CharClasses::from_states(&automata.state_table);
+
         return automata;
     }
 
-    fn process_state(&mut self, definition: &StateDefinition) {
-        let mut transition_table = TransitionTable::new();
+    /// The states a lexer can start from, in declaration order
+    pub fn entry_points(&self) -> &[InternedString] {
+        &self.entry_points
+    }
+
+    /// The interner the names of the machine come from
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// What the machine reads
+    pub fn alphabet(&self) -> Alphabet {
+        self.alphabet
+    }
+
+    /// The keywords that replace the tokens returned by a state, if it has any
+    pub fn keyword_table(&self, state: InternedString) -> Option<&KeywordTable> {
+        self.keyword_tables.get(&state)
+    }
+
+    /// The state whose keywords apply to the tokens a state returns
+    /// That's the state itself, apart from the states generated to check trailing contexts,
+    /// which act for the state the arm belongs to
+    pub fn keyword_state(&self, state: InternedString) -> InternedString {
+        let mut state = state;
+
+        for _ in 0..self.generated_states.len() {
+            match self
+                .generated_states
+                .get(&state)
+                .and_then(Provenance::owner)
+            {
+                Some(owner) => state = owner,
+                None => break,
+            }
+        }
+
+        state
+    }
+
+    /// The chars grouped by how the machine treats them
+    pub fn char_classes(&self) -> &CharClasses {
+        &self.char_classes
+    }
+
+    /// The names of the states, sorted by name
+    pub fn states(&self) -> Vec<InternedString> {
+        let mut states = self.state_table.keys().cloned().collect::<Vec<_>>();
+        self.sort_by_name(&mut states);
+        states
+    }
+
+    /// Where a state the machine generated comes from, `None` for declared states
+    pub fn provenance(&self, state: InternedString) -> Option<&Provenance> {
+        self.generated_states.get(&state)
+    }
 
-        for statement in &definition.statements {
-            let mut destination = statement.destination;
+    /// Whether a state is defined
+    pub fn has_state(&self, state: InternedString) -> bool {
+        self.state_table.contains_key(&state)
+    }
+
+    /// Where an input leads from a state
+    pub fn unguarded_transition(
+        &self,
+        state: InternedString,
+        input: char,
+    ) -> Option<(Destination, Lookahead)> {
+        self.unguarded_transition_for(state, &[input])
+    }
+
+    /// The context arms of an input in a state while resolving, in the order they are tried
+    /// Context arms of fallbacks come after the state's own, up to a state with an explicit
+    /// transition for the input
+    pub(crate) fn guarded_transitions(
+        &self,
+        state: InternedString,
+        input: char,
+    ) -> Vec<(TrailingContext, Destination, Lookahead)> {
+        let mut guarded_transitions = Vec::new();
+
+        for transition_table in self.fallback_chain(state) {
+            guarded_transitions.extend(
+                transition_table
+                    .context_destinations(input)
+                    .iter()
+                    .cloned(),
+            );
+
+            if transition_table.explicit_destination_for(input).is_some() {
+                break;
+            }
+        }
+
+        guarded_transitions
+    }
+
+    /// The transition taken from a state when the input ends
+    pub fn end_of_input_transition(&self, state: InternedString) -> Option<Destination> {
+        self.end_of_input_transition_for(state)
+    }
+
+    /// The transition table of a state, without the transitions of its fallbacks
+    pub fn transition_table(&self, state: InternedString) -> Option<&TransitionTable> {
+        self.state_table.get(&state)
+    }
+
+    /// The transitions for explicit inputs out of a state, fallbacks included, sorted by input
+    pub fn transitions_from(&self, state: InternedString) -> Vec<Transition> {
+        let chain = self.fallback_chain(state);
+        let mut inputs = chain
+            .iter()
+            .flat_map(|transition_table| transition_table.explicit_inputs())
+            .collect::<Vec<_>>();
+        inputs.sort();
+        inputs.dedup();
+
+        let mut transitions = Vec::new();
+
+        for input in inputs {
+            let explicit_transition = chain
+                .iter()
+                .filter_map(|transition_table| transition_table.explicit_destination_for(input))
+                .next();
+
+            if let Some((destination, lookahead)) = explicit_transition {
+                transitions.push(Transition {
+                    input,
+                    destination,
+                    lookahead,
+                });
+            }
+        }
+
+        transitions
+    }
+
+    /// The transition taken from a state for inputs it has no explicit transition for
+    pub fn default_transition(&self, state: InternedString) -> Option<(Destination, Lookahead)> {
+        self.fallback_chain(state)
+            .iter()
+            .filter_map(|transition_table| transition_table.default_transition())
+            .next()
+    }
+
+    /// Every token the machine returns, keywords included, sorted by name
+    pub fn tokens(&self) -> Vec<InternedString> {
+        let mut tokens = Vec::new();
+
+        for transition_table in self.state_table.values() {
+            for input in transition_table.explicit_inputs() {
+                tokens.extend(
+                    transition_table
+                        .explicit_destination_for(input)
+                        .map(|(destination, _)| destination),
+                );
+            }
+
+            tokens.extend(
+                transition_table
+                    .default_transition()
+                    .map(|(destination, _)| destination),
+            );
+            tokens.extend(transition_table.end_of_input_transition());
+        }
 
-            if let Destination::State(destination_identifier) = destination {
-                if destination_identifier == *KEYWORD_SELF {
-                    destination = Destination::State(definition.name);
+        let mut tokens = tokens
+            .into_iter()
+            .filter_map(|destination| match destination {
+                Destination::Return(token) => Some(token),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for keyword_table in self.keyword_tables.values() {
+            tokens.extend(keyword_table.slots().iter().flatten().map(|&(_, token)| token));
+        }
+
+        tokens.sort();
+        tokens.dedup();
+        self.sort_by_name(&mut tokens);
+        tokens
+    }
+
+    /// Sort names by the strings they stand for, as output is
+    pub(crate) fn sort_by_name(&self, names: &mut [InternedString]) {
+        names.sort_by(|&first, &second| self.interner.compare(first, second));
+    }
+
+    /// Where reading an input leads from a state
+    /// Same as `unguarded_transition`
+    pub fn step(&self, state: InternedString, input: char) -> Option<(Destination, Lookahead)> {
+        self.unguarded_transition(state, input)
+    }
+
+    /// Where the first of some inputs with an explicit transition leads from a state, leaving
+    /// context transitions aside. Falls back to the default transitions
+    pub(crate) fn unguarded_transition_for(
+        &self,
+        state: InternedString,
+        inputs: &[char],
+    ) -> Option<(Destination, Lookahead)> {
+        let chain = self.fallback_chain(state);
+
+        inputs
+            .iter()
+            .filter_map(|&input| {
+                chain
+                    .iter()
+                    .filter_map(|transition_table| transition_table.explicit_destination_for(input))
+                    .next()
+            })
+            .next()
+            .or_else(|| {
+                chain
+                    .iter()
+                    .filter_map(|transition_table| transition_table.default_transition())
+                    .next()
+            })
+    }
+
+    /// The transition taken from a state when the input ends, looking at its fallbacks too
+    pub(crate) fn end_of_input_transition_for(&self, state: InternedString) -> Option<Destination> {
+        self.fallback_chain(state)
+            .iter()
+            .filter_map(|transition_table| transition_table.end_of_input_transition())
+            .next()
+    }
+
+    /// The transition table of a state, followed by the ones of its fallbacks
+    fn fallback_chain(&self, state: InternedString) -> Vec<&TransitionTable> {
+        let mut chain = Vec::new();
+        let mut current_state = Some(state);
+
+        while let Some(state) = current_state {
+            if chain.len() > self.state_table.len() {
+                break;
+            }
+
+            match self.state_table.get(&state) {
+                Some(transition_table) => {
+                    chain.push(transition_table);
+                    current_state = transition_table.fallback();
                 }
+                None => break,
             }
+        }
+
+        chain
+    }
+
+    fn process_state(
+        &mut self,
+        definition: &StateDefinition,
+        priorities: &TokenPriorities,
+        pending_sequence_states: &mut Vec<PendingSequenceState>,
+    ) {
+        let mut transition_table = TransitionTable::new();
+        let mut sequence_trie = SequenceTrie::default();
+        let selection = select_arms(definition, priorities);
+
+        // Arms with a trailing context are tried in order, so the higher priorities go first
+        let mut order = (0..definition.statements.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| -definition.statements[index].priority);
+
+        for index in order {
+            let statement = &definition.statements[index];
+            let destination = resolve_self(statement.destination, definition.name);
 
             match statement.match_kind {
                 StatementMatchKind::Default => {
-                    transition_table.set_default_transition(destination);
+                    transition_table.set_default_transition(destination, statement.lookahead);
                 }
-                StatementMatchKind::Range(range) => for chr in range {
-                    transition_table.add_destination(chr, destination);
-                },
-                StatementMatchKind::Literal(chr) => {
-                    transition_table.add_destination(chr, destination);
+                StatementMatchKind::EndOfInput => {
+                    transition_table.set_end_of_input_transition(destination);
                 }
-                StatementMatchKind::Sequence(ref sequence) => {
-                    self.process_sequence(
-                        sequence,
-                        &destination,
-                        &mut transition_table,
-                        definition.name,
+                StatementMatchKind::Sequence(ref sequence) if sequence.len() > 1 => {
+                    sequence_trie.insert(
+                        &selection.sequence_inputs(statement, index),
+                        destination,
+                        statement,
+                        index,
                     );
                 }
+                _ => for chr in statement.chars() {
+                    if selection.is_selected(statement, index, chr) {
+                        Self::add_statement_destination(
+                            &mut transition_table,
+                            chr,
+                            statement,
+                            destination,
+                        );
+                    }
+                },
             }
         }
 
+        let mut expansion =
+            SequenceExpansion::new(definition.name, priorities, pending_sequence_states);
+        self.expand_sequence_trie(
+            &mut expansion,
+            &sequence_trie,
+            definition.name,
+            &mut transition_table,
+        );
+
         self.state_table.insert(definition.name, transition_table);
     }
 
-    fn process_sequence(
-        &mut self,
-        sequence: &Vec<char>,
-        destination: &Destination,
-        source_state_transitions: &mut TransitionTable,
-        source_name: InternedString,
+    /// Add the destination of a statement for one of the chars it matches
+    /// Statements with a trailing context only apply when the context follows
+    fn add_statement_destination(
+        transition_table: &mut TransitionTable,
+        input: char,
+        statement: &Statement,
+        destination: Destination,
     ) {
-        let mut intermediate_states: Vec<StateDefinition> = Vec::new();
+        match statement.context {
+            Some(ref context) => transition_table.add_context_destination(
+                input,
+                context.clone(),
+                destination,
+                statement.lookahead,
+            ),
+            None => transition_table.add_destination(input, destination, statement.lookahead),
+        }
+    }
+}
 
-        let sequence_as_str = sequence.iter().collect::<String>();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        'sequence: for i in 0..sequence.len() {
-            let input = sequence[i];
+    #[test]
+    fn query_test() {
+        let mut start = StateDefinition::new(intern("start"));
+        start.push_statement(Statement::new(
+            Destination::State(intern("identifier")),
+            StatementMatchKind::Range(CharRange::new('a', 'b')),
+            Lookahead::Keep,
+        ));
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("ARROW")),
+                StatementMatchKind::Literal('-'),
+                Lookahead::Consume,
+            )
+            .with_context(TrailingContext::new(
+                false,
+                vec![StatementMatchKind::Literal('>')],
+            )),
+        );
+        start.push_statement(Statement::new(
+            Destination::Return(intern("MINUS")),
+            StatementMatchKind::Literal('-'),
+            Lookahead::Consume,
+        ));
 
-            let destination_name = match destination {
-                Destination::State(interned_string) => {
-                    format!("{}", intern_get_str(*interned_string).unwrap())
-                }
-                Destination::Return(interned_string) => {
-                    format!("return_{}", intern_get_str(*interned_string).unwrap())
-                }
-            };
-
-            let intermediate_state_name = intern(format!(
-                "{}_to_{}_intermediate_{}_for_{}",
-                intern_get_str(source_name).unwrap(),
-                destination_name,
-                i,
-                sequence_as_str
-            ));
-
-            match i {
-                0 => {
-                    source_state_transitions
-                        .add_destination(input, Destination::State(intermediate_state_name));
-                }
-                _ if i == sequence.len() - 1 => {
-                    intermediate_states[i - 1].push_statement(Statement::new(
-                        *destination,
-                        StatementMatchKind::Literal(input),
+        let mut identifier = StateDefinition::new(intern("identifier"));
+        identifier.push_statement(Statement::new(
+            Destination::State(intern("Self")),
+            StatementMatchKind::Range(CharRange::new('a', 'b')),
+            Lookahead::Keep,
+        ));
+        identifier.push_statement(Statement::new(
+            Destination::Return(intern("IDENTIFIER")),
+            StatementMatchKind::Default,
+            Lookahead::Keep,
+        ));
+
+        let mut keywords = KeywordDefinition::new(intern("identifier"));
+        keywords.push_keyword("ab".to_string(), intern("KEYWORD_AB"));
+
+        let mut definitions = Definitions::new();
+        definitions.push_state(start);
+        definitions.push_state(identifier);
+        definitions.push_keywords(keywords);
+
+        let automata = Automata::resolve_from(definitions);
+        let start = intern("start");
+        let check = intern_generated("start_context_1");
+        let applies = intern_generated("start_context_1_applies");
+        let does_not_apply = intern_generated("start_context_1_does_not_apply");
+
+        assert_eq!(
+            automata.states(),
+            vec![intern("identifier"), start, check, applies, does_not_apply]
+        );
+        assert_eq!(
+            automata.transitions_from(start),
+            vec![
+                Transition {
+                    input: '-',
+                    destination: Destination::Mark(check),
+                    lookahead: Lookahead::Consume,
+                },
+                Transition {
+                    input: 'a',
+                    destination: Destination::State(intern("identifier")),
+                    lookahead: Lookahead::Consume,
+                },
+                Transition {
+                    input: 'b',
+                    destination: Destination::State(intern("identifier")),
+                    lookahead: Lookahead::Consume,
+                },
+            ]
+        );
+
+        assert_eq!(automata.default_transition(start), None);
+        assert_eq!(
+            automata.default_transition(intern("identifier")),
+            Some((Destination::Return(intern("IDENTIFIER")), Lookahead::Keep))
+        );
+        assert_eq!(
+            automata.tokens(),
+            vec![
+                intern("ARROW"),
+                intern("IDENTIFIER"),
+                intern("KEYWORD_AB"),
+                intern("MINUS"),
+            ]
+        );
+
+        assert_eq!(
+            automata.step(check, '>'),
+            Some((Destination::Rollback(applies), Lookahead::Consume))
+        );
+        assert_eq!(
+            automata.default_transition(check),
+            Some((Destination::Rollback(does_not_apply), Lookahead::Keep))
+        );
+        assert_eq!(
+            automata.end_of_input_transition(check),
+            Some(Destination::Rollback(does_not_apply))
+        );
+        assert_eq!(
+            automata.step(applies, '-'),
+            Some((Destination::Return(intern("ARROW")), Lookahead::Consume))
+        );
+        assert_eq!(
+            automata.step(does_not_apply, '-'),
+            Some((Destination::Return(intern("MINUS")), Lookahead::Consume))
+        );
+        assert_eq!(automata.keyword_state(applies), start);
+        assert_eq!(
+            automata
+                .provenance(check)
+                .unwrap()
+                .describe(automata.interner()),
+            "checking a trailing context in state start"
+        );
+        assert_eq!(automata.step(start, 'z'), None);
+        assert!(automata.transition_table(intern("missing")).is_none());
+    }
+
+    #[test]
+    fn provenance_test() {
+        let span = Span::new(20, 26, 2, 4);
+        let mut start = StateDefinition::new(intern("start"));
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("SELF")),
+                StatementMatchKind::Sequence("Self".chars().collect()),
+                Lookahead::Consume,
+            )
+            .with_span(span),
+        );
+        start.push_statement(Statement::new(
+            Destination::State(intern("start_sequence_S")),
+            StatementMatchKind::Literal('x'),
+            Lookahead::Keep,
+        ));
+
+        // Named like a generated state on purpose
+        let mut user_state = StateDefinition::new(intern("start_sequence_S"));
+        user_state.push_statement(Statement::new(
+            Destination::Return(intern("USER")),
+            StatementMatchKind::Default,
+            Lookahead::Keep,
+        ));
+
+        let mut definitions = Definitions::new();
+        definitions.push_state(start);
+        definitions.push_state(user_state);
+
+        let automata = Automata::resolve_from(definitions);
+        let generated = intern_generated("start_sequence_Sel");
+
+        assert_eq!(
+            automata.step(intern("start"), 'S'),
+            Some((
+                Destination::State(intern_generated("start_sequence_S")),
+                Lookahead::Consume
+            ))
+        );
+        assert_eq!(
+            automata.default_transition(intern("start_sequence_S")),
+            Some((Destination::Return(intern("USER")), Lookahead::Keep))
+        );
+        assert_eq!(automata.provenance(intern("start_sequence_S")), None);
+
+        let provenance = automata.provenance(generated).unwrap();
+        assert_eq!(
+            provenance.describe(automata.interner()),
+            "after reading `Sel` in state start"
+        );
+        assert_eq!(provenance.position(), Some(3));
+        assert_eq!(
+            *provenance,
+            Provenance::Sequence {
+                state: intern("start"),
+                prefix: "Sel".to_string(),
+                arms: vec![ArmOrigin {
+                    index: 0,
+                    span: Some(span),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn deterministic_order_test() {
+        fn definitions(state_names: &[&str]) -> Definitions {
+            let interner = Interner::new();
+            let mut definitions = Definitions::with_interner(interner.clone());
+
+            for &name in state_names {
+                let mut state = StateDefinition::new(interner.intern(name));
+                for &(chr, token) in &[('z', "ZED"), ('a', "AY"), ('m', "EM")] {
+                    state.push_statement(Statement::new(
+                        Destination::Return(interner.intern(format!("{}_{}", name, token))),
+                        StatementMatchKind::Literal(chr),
+                        Lookahead::Consume,
                     ));
-                    break 'sequence;
                 }
-                _ => intermediate_states[i - 1].push_statement(Statement::new(
-                    Destination::State(intermediate_state_name),
-                    StatementMatchKind::Literal(input),
-                )),
+                state.push_statement(Statement::new(
+                    Destination::Return(interner.intern("LONG")),
+                    StatementMatchKind::Sequence("long".chars().collect()),
+                    Lookahead::Consume,
+                ));
+                definitions.push_state(state);
             }
 
-            let intermediate_state = StateDefinition::new(intermediate_state_name);
-            intermediate_states.push(intermediate_state);
+            definitions.push_entry_point(interner.intern("start"));
+            definitions
         }
 
-        intermediate_states.iter().for_each(|state_definition| {
-            self.process_state(state_definition);
-        })
+        // Interned in the reverse of their sorted order, then in that order
+        let names = ["zulu", "start", "mike", "alpha"];
+        let first = Automata::resolve_from(definitions(&names));
+        let second = Automata::resolve_from(definitions(&[names[3], names[1], names[2], names[0]]));
+        let interner = first.interner();
+
+        assert_eq!(first.to_text(), second.to_text());
+        assert_eq!(first.to_json().unwrap(), second.to_json().unwrap());
+        assert_eq!(
+            first.to_utf8_bytes().unwrap().to_text(),
+            second.to_utf8_bytes().unwrap().to_text()
+        );
+        assert_eq!(
+            first.states()[..4],
+            [
+                interner.intern("alpha"),
+                interner.intern_generated("alpha_sequence_l"),
+                interner.intern_generated("alpha_sequence_lo"),
+                interner.intern_generated("alpha_sequence_lon"),
+            ]
+        );
+        assert_eq!(
+            first.transitions_from(interner.intern("mike"))[0].destination,
+            Destination::Return(interner.intern("mike_AY"))
+        );
     }
 }
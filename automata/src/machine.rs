@@ -1,25 +1,59 @@
-use std::collections::HashMap;
-use statements::Destination;
+use std::collections::{BTreeMap, BTreeSet};
+use statements::{Destination, Lookahead, TrailingContext};
 use automata_core::string_interning::*;
 
+/// A transition out of a state, see `Automata::transitions_from`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub input: char,
+    pub destination: Destination,
+    pub lookahead: Lookahead,
+}
+
 /// A transition table for a state machine
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TransitionTable {
+    /// Transitions that only apply when followed by a context, in declaration order
+    /// Only used while resolving, contexts are then compiled into states, see `contexts`
+    #[serde(skip)]
+    context_transitions: BTreeMap<char, Vec<(TrailingContext, Destination, Lookahead)>>,
     /// Normal char -> state transitions
-    transitions: HashMap<char, Vec<InternedString>>,
+    transitions: BTreeMap<char, Vec<InternedString>>,
     /// Accepting transitions
-    return_states: HashMap<char, InternedString>,
+    return_states: BTreeMap<char, (InternedString, Lookahead)>,
+    /// Error transitions, along with their message
+    error_states: BTreeMap<char, (InternedString, Lookahead)>,
+    /// Mode pushing transitions
+    push_transitions: BTreeMap<char, InternedString>,
+    /// Mode popping transitions
+    pop_transitions: BTreeSet<char>,
+    /// Transitions remembering the position before their char
+    mark_transitions: BTreeMap<char, InternedString>,
+    /// Transitions going back to the remembered position
+    rollback_transitions: BTreeMap<char, InternedString>,
     /// Default transition
-    default_transition: Option<Destination>,
+    default_transition: Option<(Destination, Lookahead)>,
+    /// Transition taken when the input ends
+    end_of_input_transition: Option<Destination>,
+    /// State whose transitions apply to the inputs this table has no transition for
+    fallback: Option<InternedString>,
 }
 
 impl TransitionTable {
     /// Create a new TransitionTable
     pub fn new() -> Self {
         TransitionTable {
-            transitions: HashMap::new(),
+            transitions: BTreeMap::new(),
+            context_transitions: BTreeMap::new(),
             default_transition: None,
-            return_states: HashMap::new(),
+            end_of_input_transition: None,
+            return_states: BTreeMap::new(),
+            error_states: BTreeMap::new(),
+            push_transitions: BTreeMap::new(),
+            pop_transitions: BTreeSet::new(),
+            mark_transitions: BTreeMap::new(),
+            rollback_transitions: BTreeMap::new(),
+            fallback: None,
         }
     }
 
@@ -30,21 +64,186 @@ impl TransitionTable {
     }
 
     /// Add a return state
-    pub fn add_return_state(&mut self, input: char, state: InternedString) {
-        self.return_states.insert(input, state);
+    pub fn add_return_state(&mut self, input: char, state: InternedString, lookahead: Lookahead) {
+        self.return_states.insert(input, (state, lookahead));
+    }
+
+    /// Add an error state
+    pub fn add_error_state(&mut self, input: char, message: InternedString, lookahead: Lookahead) {
+        self.error_states.insert(input, (message, lookahead));
+    }
+
+    /// Add a mode push
+    pub fn add_push_transition(&mut self, input: char, mode: InternedString) {
+        self.push_transitions.insert(input, mode);
+    }
+
+    /// Add a mode pop
+    pub fn add_pop_transition(&mut self, input: char) {
+        self.pop_transitions.insert(input);
+    }
+
+    /// Add a mark
+    pub fn add_mark_transition(&mut self, input: char, state: InternedString) {
+        self.mark_transitions.insert(input, state);
+    }
+
+    /// Add a rollback
+    pub fn add_rollback_transition(&mut self, input: char, state: InternedString) {
+        self.rollback_transitions.insert(input, state);
     }
 
     /// Add a destination
-    /// Will dispatch to either normal transition or return state
-    pub fn add_destination(&mut self, input: char, destination: Destination) {
+    /// Will dispatch to either normal transition, return state, error state, mode push / pop
+    /// or mark / rollback. The lookahead only matters for return and error states
+    pub fn add_destination(&mut self, input: char, destination: Destination, lookahead: Lookahead) {
         match destination {
             Destination::State(state) => self.add_transition(input, state),
-            Destination::Return(return_state) => self.add_return_state(input, return_state),
+            Destination::Return(return_state) => {
+                self.add_return_state(input, return_state, lookahead)
+            }
+            Destination::Push(mode) => self.add_push_transition(input, mode),
+            Destination::Pop => self.add_pop_transition(input),
+            Destination::Error(message) => self.add_error_state(input, message, lookahead),
+            Destination::Mark(state) => self.add_mark_transition(input, state),
+            Destination::Rollback(state) => self.add_rollback_transition(input, state),
         }
     }
 
+    /// Add a destination that only applies when the input that follows matches a context
+    pub(crate) fn add_context_destination(
+        &mut self,
+        input: char,
+        context: TrailingContext,
+        destination: Destination,
+        lookahead: Lookahead,
+    ) {
+        let entry = self.context_transitions.entry(input).or_default();
+        entry.push((context, destination, lookahead));
+    }
+
     /// Set the default transition for a this table
-    pub fn set_default_transition(&mut self, destination: Destination) {
-        self.default_transition = Some(destination)
+    pub fn set_default_transition(&mut self, destination: Destination, lookahead: Lookahead) {
+        self.default_transition = Some((destination, lookahead))
+    }
+
+    /// Set the transition taken when the input ends
+    pub fn set_end_of_input_transition(&mut self, destination: Destination) {
+        self.end_of_input_transition = Some(destination)
+    }
+
+    /// Set the state this table falls back to
+    pub fn set_fallback(&mut self, state: InternedString) {
+        self.fallback = Some(state)
+    }
+
+    /// Replace whatever an input does, apart from context transitions, with a state transition
+    pub fn replace_with_transition(&mut self, input: char, destination: InternedString) {
+        self.replace_destination(input, Destination::State(destination), Lookahead::Consume);
+    }
+
+    /// Replace whatever an input does, apart from context transitions, with a destination
+    pub fn replace_destination(
+        &mut self,
+        input: char,
+        destination: Destination,
+        lookahead: Lookahead,
+    ) {
+        self.transitions.remove(&input);
+        self.return_states.remove(&input);
+        self.error_states.remove(&input);
+        self.push_transitions.remove(&input);
+        self.pop_transitions.remove(&input);
+        self.mark_transitions.remove(&input);
+        self.rollback_transitions.remove(&input);
+
+        self.add_destination(input, destination, lookahead);
+    }
+
+    /// Find where an input leads, leaving context transitions aside
+    /// State transitions are looked at before return states, error states, mode pushes, mode
+    /// pops, marks and rollbacks.
+    /// When there are several state transitions for an input, the first one added wins.
+    pub fn explicit_destination_for(&self, input: char) -> Option<(Destination, Lookahead)> {
+        if let Some(state) = self.transitions.get(&input).and_then(|states| states.first()) {
+            return Some((Destination::State(*state), Lookahead::Consume));
+        }
+
+        if let Some(&(return_state, lookahead)) = self.return_states.get(&input) {
+            return Some((Destination::Return(return_state), lookahead));
+        }
+
+        if let Some(&(message, lookahead)) = self.error_states.get(&input) {
+            return Some((Destination::Error(message), lookahead));
+        }
+
+        if let Some(mode) = self.push_transitions.get(&input) {
+            return Some((Destination::Push(*mode), Lookahead::Consume));
+        }
+
+        if self.pop_transitions.contains(&input) {
+            return Some((Destination::Pop, Lookahead::Consume));
+        }
+
+        if let Some(state) = self.mark_transitions.get(&input) {
+            return Some((Destination::Mark(*state), Lookahead::Consume));
+        }
+
+        if let Some(state) = self.rollback_transitions.get(&input) {
+            return Some((Destination::Rollback(*state), Lookahead::Consume));
+        }
+
+        None
+    }
+
+    /// Every input with an explicit transition, context transitions included
+    pub fn explicit_inputs(&self) -> Vec<char> {
+        let mut inputs = self
+            .context_transitions
+            .keys()
+            .chain(self.transitions.keys())
+            .chain(self.return_states.keys())
+            .chain(self.error_states.keys())
+            .chain(self.push_transitions.keys())
+            .chain(self.pop_transitions.iter())
+            .chain(self.mark_transitions.keys())
+            .chain(self.rollback_transitions.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        inputs.sort();
+        inputs.dedup();
+        inputs
+    }
+
+    /// The transitions of an input that only apply when followed by a context, in order
+    pub(crate) fn context_destinations(
+        &self,
+        input: char,
+    ) -> &[(TrailingContext, Destination, Lookahead)] {
+        match self.context_transitions.get(&input) {
+            Some(context_transitions) => context_transitions,
+            None => &[],
+        }
+    }
+
+    /// Drop the transitions that only apply when followed by a context, once compiled
+    pub(crate) fn clear_context_destinations(&mut self) {
+        self.context_transitions.clear();
+    }
+
+    /// The default transition, if there is one
+    pub fn default_transition(&self) -> Option<(Destination, Lookahead)> {
+        self.default_transition
+    }
+
+    /// The end of input transition, if there is one
+    pub fn end_of_input_transition(&self) -> Option<Destination> {
+        self.end_of_input_transition
+    }
+
+    /// The state this table falls back to, if there is one
+    pub fn fallback(&self) -> Option<InternedString> {
+        self.fallback
     }
 }
@@ -0,0 +1,567 @@
+//! Priorities between arms that match the same input
+//!
+//! The `#[priority(n)]` of an arm is looked at first, the higher one wins and arms default to 0.
+//! Arms with the same priority that return different tokens are then ordered by the
+//! `priority A > B;` declarations, which are transitive. Arms that still can't be ordered
+//! conflict, unless they lead to the same destination.
+//!
+//! Arms with a trailing context are tried in order when their input comes up, so they never
+//! conflict. Sequences only conflict with sequences of the same length matching a common input,
+//! a longer sequence goes on after a shorter arm matching its start unless the shorter arm wins
+//! over it. When nothing orders them, the shorter arm conflicts with the sequence if what it does
+//! can't be done once the sequence consumed the input, see `sequences`.
+
+use super::resolve_self;
+use states::*;
+use statements::*;
+use automata_core::string_interning::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The `priority A > B;` declarations of a file
+#[derive(Debug, Default)]
+pub struct TokenPriorities {
+    /// The tokens each token directly wins over
+    lower: BTreeMap<InternedString, Vec<InternedString>>,
+}
+
+impl TokenPriorities {
+    /// Collect pairs of tokens where the first one wins over the second one
+    pub fn new(priorities: &[(InternedString, InternedString)]) -> Self {
+        let mut lower: BTreeMap<InternedString, Vec<InternedString>> = BTreeMap::new();
+
+        for &(higher, lower_token) in priorities {
+            lower.entry(higher).or_default().push(lower_token);
+        }
+
+        Self { lower }
+    }
+
+    /// Whether `token` wins over `other`, directly or through other declarations
+    pub fn outranks(&self, token: InternedString, other: InternedString) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![token];
+
+        while let Some(current) = pending.pop() {
+            if !visited.insert(current) {
+                continue;
+            }
+
+            if let Some(lower) = self.lower.get(&current) {
+                if lower.contains(&other) {
+                    return true;
+                }
+
+                pending.extend(lower.iter().cloned());
+            }
+        }
+
+        false
+    }
+
+    /// A token that wins over itself, the first by name, if the declarations form a cycle
+    pub fn find_cycle(&self) -> Option<InternedString> {
+        self.lower
+            .keys()
+            .cloned()
+            .find(|&token| self.outranks(token, token))
+    }
+}
+
+/// Two arms of a state that match the same input when no priority decides between them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The state the arms belong to
+    pub state: InternedString,
+    /// Index of the arm declared first in the state's statements
+    pub first: usize,
+    /// Index of the arm declared last in the state's statements
+    pub second: usize,
+    /// The first input both arms match
+    pub input: String,
+    pub kind: ConflictKind,
+}
+
+/// Why two arms conflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Nothing orders the arms
+    Ambiguous,
+    /// A longer sequence takes the input over from the arm with this index, which can't be done
+    /// once the input is consumed
+    Replaced(usize),
+}
+
+/// The arms of a state that win their char
+#[derive(Debug, Default)]
+pub struct ArmSelection {
+    /// The arm that wins each char
+    chars: HashMap<char, usize>,
+    /// The chars each sequence keeps at each position, once the arms winning over it took theirs
+    sequences: HashMap<usize, Vec<Vec<char>>>,
+    /// Inputs left ambiguous, one per pair of arms
+    pub conflicts: Vec<Conflict>,
+}
+
+impl ArmSelection {
+    /// Whether a literal, range or single char sequence statement is used for a char it matches
+    /// Statements with a trailing context always are
+    pub fn is_selected(&self, statement: &Statement, index: usize, input: char) -> bool {
+        statement.context.is_some() || self.chars.get(&input) == Some(&index)
+    }
+
+    /// The chars a sequence statement matches at each position, without the ones that shorter
+    /// arms winning over it take
+    pub fn sequence_inputs(&self, statement: &Statement, index: usize) -> Vec<Vec<char>> {
+        self.sequences
+            .get(&index)
+            .cloned()
+            .unwrap_or_else(|| statement.sequence_inputs())
+    }
+}
+
+/// Pick the arm that wins each char of a state, and find the inputs no priority decides
+/// A sequence goes on after a shorter arm matching the same input unless that arm wins over it.
+/// Sequences of the same length are picked between when their intermediate states are built,
+/// only their conflicts are looked for here
+pub fn select_arms(definition: &StateDefinition, priorities: &TokenPriorities) -> ArmSelection {
+    let statements = &definition.statements;
+    let compare = |a: usize, b: usize| {
+        compare_statements(definition.name, &statements[a], &statements[b], priorities)
+    };
+
+    let mut char_candidates: HashMap<char, Vec<usize>> = HashMap::new();
+    let mut sequences: Vec<(usize, Vec<Vec<char>>)> = Vec::new();
+    let mut char_order = Vec::new();
+
+    for (index, statement) in definition.statements.iter().enumerate() {
+        if statement.context.is_some() {
+            continue;
+        }
+
+        match statement.match_kind {
+            StatementMatchKind::Sequence(ref sequence) if sequence.len() > 1 => {
+                sequences.push((index, statement.sequence_inputs()));
+            }
+            _ => for chr in statement.chars() {
+                let candidates = char_candidates.entry(chr).or_default();
+
+                if candidates.is_empty() {
+                    char_order.push(chr);
+                }
+
+                candidates.push(index);
+            },
+        }
+    }
+
+    let mut selection = ArmSelection::default();
+
+    for chr in char_order {
+        let winner = pick_winner(
+            definition,
+            &char_candidates[&chr],
+            priorities,
+            &mut selection.conflicts,
+            || chr.to_string(),
+        );
+        selection.chars.insert(chr, winner);
+    }
+
+    for &(sequence, ref sequence_inputs) in &sequences {
+        let mut kept_inputs = sequence_inputs.clone();
+
+        for &chr in &sequence_inputs[0] {
+            let replaced = selection
+                .chars
+                .get(&chr)
+                .cloned()
+                .or_else(|| default_arm(definition));
+
+            if let Some(replaced) = replaced {
+                match compare(replaced, sequence) {
+                    Some(Ordering::Greater) => kept_inputs[0].retain(|&kept| kept != chr),
+                    Some(Ordering::Less) => {}
+                    _ => check_replaced(
+                        definition,
+                        (replaced, sequence),
+                        &mut selection.conflicts,
+                        || chr.to_string(),
+                    ),
+                }
+            }
+        }
+
+        for &(shorter, ref shorter_inputs) in &sequences {
+            let len = shorter_inputs.len();
+            if len >= sequence_inputs.len() {
+                continue;
+            }
+
+            let common_input = match common_input(shorter_inputs, &sequence_inputs[..len]) {
+                Some(common_input) => common_input,
+                None => continue,
+            };
+
+            // The sequence can only leave the shorter one its last chars when every input it
+            // starts with goes through the shorter one, which isn't the case for some case
+            // insensitive sequences
+            let is_covered = kept_inputs[..len - 1]
+                .iter()
+                .zip(shorter_inputs)
+                .all(|(kept, shorter)| kept.iter().all(|chr| shorter.contains(chr)));
+
+            match compare(shorter, sequence) {
+                Some(Ordering::Greater) if is_covered => {
+                    kept_inputs[len - 1].retain(|chr| !shorter_inputs[len - 1].contains(chr))
+                }
+                Some(Ordering::Less) => {}
+                _ => check_replaced(
+                    definition,
+                    (shorter, sequence),
+                    &mut selection.conflicts,
+                    || common_input.clone(),
+                ),
+            }
+        }
+
+        selection.sequences.insert(sequence, kept_inputs);
+    }
+
+    for (position, &(first, ref first_inputs)) in sequences.iter().enumerate() {
+        for &(second, ref second_inputs) in &sequences[position + 1..] {
+            let common_input = common_input(first_inputs, second_inputs);
+
+            if let Some(common_input) = common_input {
+                pick_winner(
+                    definition,
+                    &[first, second],
+                    priorities,
+                    &mut selection.conflicts,
+                    || common_input.clone(),
+                );
+            }
+        }
+    }
+
+    selection
+}
+
+/// An input two sequences both match, if there is one
+fn common_input(first: &[Vec<char>], second: &[Vec<char>]) -> Option<String> {
+    if first.len() != second.len() {
+        return None;
+    }
+
+    first
+        .iter()
+        .zip(second)
+        .map(|(first_chars, second_chars)| {
+            first_chars
+                .iter()
+                .find(|chr| second_chars.contains(chr))
+                .cloned()
+        })
+        .collect()
+}
+
+/// The `_` arm of a state, if it has one
+fn default_arm(definition: &StateDefinition) -> Option<usize> {
+    definition
+        .statements
+        .iter()
+        .rposition(|statement| statement.match_kind == StatementMatchKind::Default)
+}
+
+/// Record a conflict when a sequence takes an input over from an arm that can't be kept once the
+/// input is consumed, given the indexes of the arm and the sequence
+fn check_replaced<F: Fn() -> String>(
+    definition: &StateDefinition,
+    arms: (usize, usize),
+    conflicts: &mut Vec<Conflict>,
+    input: F,
+) {
+    let (replaced, sequence) = arms;
+    let statement = &definition.statements[replaced];
+
+    let is_kept = matches!(
+        (statement.destination, statement.lookahead),
+        (Destination::State(_), _)
+            | (Destination::Return(_), Lookahead::Consume)
+            | (Destination::Error(_), Lookahead::Consume)
+    );
+
+    let kind = ConflictKind::Replaced(replaced);
+    if is_kept || conflicts.iter().any(|conflict| conflict.kind == kind) {
+        return;
+    }
+
+    conflicts.push(Conflict {
+        state: definition.name,
+        first: replaced.min(sequence),
+        second: replaced.max(sequence),
+        input: input(),
+        kind,
+    });
+}
+
+/// Pick the arm that wins over the others, recording the ones it can't be ordered with
+/// Conflicts between the same arms are only recorded once
+fn pick_winner<F: Fn() -> String>(
+    definition: &StateDefinition,
+    candidates: &[usize],
+    priorities: &TokenPriorities,
+    conflicts: &mut Vec<Conflict>,
+    input: F,
+) -> usize {
+    let statements = &definition.statements;
+    let compare = |a: usize, b: usize| {
+        compare_statements(definition.name, &statements[a], &statements[b], priorities)
+    };
+
+    let mut winner = candidates[0];
+
+    for &candidate in &candidates[1..] {
+        if compare(candidate, winner) == Some(Ordering::Greater) {
+            winner = candidate;
+        }
+    }
+
+    for &candidate in candidates {
+        if candidate == winner {
+            continue;
+        }
+
+        match compare(winner, candidate) {
+            Some(Ordering::Greater) | Some(Ordering::Equal) => {}
+            _ => {
+                let first = candidate.min(winner);
+                let second = candidate.max(winner);
+
+                if !conflicts.iter().any(|conflict| {
+                    conflict.first == first
+                        && conflict.second == second
+                        && conflict.kind == ConflictKind::Ambiguous
+                }) {
+                    conflicts.push(Conflict {
+                        state: definition.name,
+                        first,
+                        second,
+                        input: input(),
+                        kind: ConflictKind::Ambiguous,
+                    });
+                }
+            }
+        }
+    }
+
+    winner
+}
+
+/// Order two statements of a state matching the same input
+fn compare_statements(
+    state: InternedString,
+    a: &Statement,
+    b: &Statement,
+    priorities: &TokenPriorities,
+) -> Option<Ordering> {
+    compare_arms(
+        (a.priority, resolve_self(a.destination, state), a.lookahead),
+        (b.priority, resolve_self(b.destination, state), b.lookahead),
+        priorities,
+    )
+}
+
+/// Order two arms matching the same input, given their priority, destination and lookahead
+/// Arms leading to the same destination are equal, `None` means nothing orders them
+pub(crate) fn compare_arms(
+    a: (i32, Destination, Lookahead),
+    b: (i32, Destination, Lookahead),
+    priorities: &TokenPriorities,
+) -> Option<Ordering> {
+    let (a_priority, a_destination, a_lookahead) = a;
+    let (b_priority, b_destination, b_lookahead) = b;
+
+    if a_priority != b_priority {
+        return Some(a_priority.cmp(&b_priority));
+    }
+
+    if a_destination == b_destination && a_lookahead == b_lookahead {
+        return Some(Ordering::Equal);
+    }
+
+    if let (Destination::Return(a_token), Destination::Return(b_token)) =
+        (a_destination, b_destination)
+    {
+        match (
+            priorities.outranks(a_token, b_token),
+            priorities.outranks(b_token, a_token),
+        ) {
+            (true, false) => return Some(Ordering::Greater),
+            (false, true) => return Some(Ordering::Less),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_arms_test() {
+        let mut definition = StateDefinition::new(intern("start"));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("IDENTIFIER")),
+            StatementMatchKind::Sequence("if".chars().collect()),
+            Lookahead::Consume,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("KW_IF")),
+            StatementMatchKind::Sequence("if".chars().collect()),
+            Lookahead::Consume,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::State(intern("identifier")),
+            StatementMatchKind::Range(CharRange::new('a', 'z')),
+            Lookahead::Keep,
+        ));
+        definition.push_statement(
+            Statement::new(
+                Destination::Return(intern("X")),
+                StatementMatchKind::Literal('x'),
+                Lookahead::Consume,
+            ).with_priority(1),
+        );
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("Y")),
+            StatementMatchKind::Literal('y'),
+            Lookahead::Consume,
+        ));
+
+        let priorities = TokenPriorities::new(&[(intern("KW_IF"), intern("IDENTIFIER"))]);
+        let selection = select_arms(&definition, &priorities);
+
+        assert!(selection.is_selected(&definition.statements[3], 3, 'x'));
+        assert!(!selection.is_selected(&definition.statements[2], 2, 'x'));
+
+        assert_eq!(
+            selection.conflicts,
+            vec![Conflict {
+                state: intern("start"),
+                first: 2,
+                second: 4,
+                input: "y".to_string(),
+                kind: ConflictKind::Ambiguous,
+            }]
+        );
+    }
+
+    #[test]
+    fn sequence_priorities_test() {
+        let mut definition = StateDefinition::new(intern("start"));
+        definition.push_statement(
+            Statement::new(
+                Destination::Return(intern("S")),
+                StatementMatchKind::Literal('S'),
+                Lookahead::Consume,
+            ).with_priority(1),
+        );
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("KEYWORD_SELF")),
+            StatementMatchKind::Sequence("Self".chars().collect()),
+            Lookahead::Consume,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("LESS_EQUALS")),
+            StatementMatchKind::Sequence("<=".chars().collect()),
+            Lookahead::Keep,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("COMPARE")),
+            StatementMatchKind::Sequence("<=>".chars().collect()),
+            Lookahead::Consume,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("MINUS")),
+            StatementMatchKind::Literal('-'),
+            Lookahead::Keep,
+        ));
+        definition.push_statement(
+            Statement::new(
+                Destination::Return(intern("ARROW")),
+                StatementMatchKind::Sequence("->".chars().collect()),
+                Lookahead::Consume,
+            ).with_priority(1),
+        );
+
+        let priorities = TokenPriorities::new(&[(intern("LESS_EQUALS"), intern("COMPARE"))]);
+        let selection = select_arms(&definition, &priorities);
+        let sequence_inputs =
+            |index: usize| selection.sequence_inputs(&definition.statements[index], index);
+
+        assert_eq!(selection.conflicts, Vec::new());
+        assert_eq!(sequence_inputs(1)[0], Vec::<char>::new());
+        assert_eq!(sequence_inputs(3), vec![vec!['<'], vec![], vec!['>']]);
+        assert_eq!(sequence_inputs(5), vec![vec!['-'], vec!['>']]);
+    }
+
+    #[test]
+    fn replaced_arms_test() {
+        let mut definition = StateDefinition::new(intern("start"));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("MINUS")),
+            StatementMatchKind::Literal('-'),
+            Lookahead::Keep,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("ARROW")),
+            StatementMatchKind::Sequence("->".chars().collect()),
+            Lookahead::Consume,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("EQUALS")),
+            StatementMatchKind::Literal('='),
+            Lookahead::Consume,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("FAT_ARROW")),
+            StatementMatchKind::Sequence("=>".chars().collect()),
+            Lookahead::Consume,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("LESS_EQUALS")),
+            StatementMatchKind::Sequence("<=".chars().collect()),
+            Lookahead::Keep,
+        ));
+        definition.push_statement(Statement::new(
+            Destination::Return(intern("COMPARE")),
+            StatementMatchKind::Sequence("<=>".chars().collect()),
+            Lookahead::Consume,
+        ));
+
+        let selection = select_arms(&definition, &TokenPriorities::default());
+
+        assert_eq!(
+            selection.conflicts,
+            vec![
+                Conflict {
+                    state: intern("start"),
+                    first: 0,
+                    second: 1,
+                    input: "-".to_string(),
+                    kind: ConflictKind::Replaced(0),
+                },
+                Conflict {
+                    state: intern("start"),
+                    first: 4,
+                    second: 5,
+                    input: "<=".to_string(),
+                    kind: ConflictKind::Replaced(4),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Where the states a machine generates come from
+//!
+//! Resolving a machine adds states that were never declared: the intermediate states of sequence
+//! arms, the states checking trailing contexts, and the continuation states of UTF-8 lowering. Their names are interned with
+//! `Interner::intern_generated`, so they never collide with a declared state, and the machine keeps a
+//! `Provenance` for each of them to describe them in terms of the definitions.
+
+use automata_core::string_interning::*;
+use statements::Span;
+
+/// How a generated state came to be
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Provenance {
+    /// Reached after reading the start of some sequence arms of a declared state
+    Sequence {
+        /// The declared state the arms belong to
+        state: InternedString,
+        /// The chars read so far, as the first arm wrote them
+        prefix: String,
+        /// The arms going through the state, in declaration order
+        arms: Vec<ArmOrigin>,
+    },
+    /// Takes part in checking the trailing contexts of the arms of a state for a char
+    Context {
+        /// The state the arms belong to, whose keywords apply to the tokens returned
+        state: InternedString,
+        part: ContextPart,
+    },
+    /// Reads the continuation bytes of a char encoded with several bytes
+    Utf8Continuation,
+    /// Reads the first byte of a char again once its other bytes decided to leave it in place
+    Utf8Rollback {
+        /// The state that read the char, whose keywords apply to the tokens returned
+        state: InternedString,
+    },
+}
+
+/// What a state checking a trailing context does
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContextPart {
+    /// Reads the input following the char, `prefix` being what it read so far
+    Check { prefix: String },
+    /// Reads the char again once the context decided for the arm
+    Applies,
+    /// Reads the char again once the context decided against the arm, to try the next one
+    DoesNotApply,
+}
+
+/// An arm of a declared state
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArmOrigin {
+    /// Position of the arm in its state
+    pub index: usize,
+    /// Where the arm's input was written, if it was
+    pub span: Option<Span>,
+}
+
+impl Provenance {
+    /// The position in the sequence, which is the number of chars read so far
+    pub fn position(&self) -> Option<usize> {
+        match *self {
+            Provenance::Sequence { ref prefix, .. } => Some(prefix.chars().count()),
+            Provenance::Context { .. }
+            | Provenance::Utf8Continuation
+            | Provenance::Utf8Rollback { .. } => None,
+        }
+    }
+
+    /// The state a generated state acts for when it returns a token, if not itself
+    pub fn owner(&self) -> Option<InternedString> {
+        match *self {
+            Provenance::Context { state, .. } | Provenance::Utf8Rollback { state } => Some(state),
+            Provenance::Sequence { .. } | Provenance::Utf8Continuation => None,
+        }
+    }
+
+    /// Written like "after reading `Sel` in state start", with the names of an interner
+    pub fn describe(&self, interner: &Interner) -> String {
+        match *self {
+            Provenance::Sequence {
+                state, ref prefix, ..
+            } => interner
+                .with_str(state, |state| {
+                    format!("after reading `{}` in state {}", prefix, state)
+                })
+                .unwrap_or_else(|| format!("after reading `{}`", prefix)),
+            Provenance::Context { state, ref part } => {
+                let part = match *part {
+                    ContextPart::Check { ref prefix } if prefix.is_empty() => {
+                        "checking a trailing context".to_string()
+                    }
+                    ContextPart::Check { ref prefix } => {
+                        format!("checking a trailing context after `{}`", prefix)
+                    }
+                    ContextPart::Applies => "once a trailing context applied".to_string(),
+                    ContextPart::DoesNotApply => "once a trailing context didn't apply".to_string(),
+                };
+
+                interner
+                    .with_str(state, |state| format!("{} in state {}", part, state))
+                    .unwrap_or(part)
+            }
+            Provenance::Utf8Continuation => "in the middle of a UTF-8 encoded char".to_string(),
+            Provenance::Utf8Rollback { state } => interner
+                .with_str(state, |state| {
+                    format!(
+                        "back at the start of a UTF-8 encoded char in state {}",
+                        state
+                    )
+                })
+                .unwrap_or_else(|| "back at the start of a UTF-8 encoded char".to_string()),
+        }
+    }
+}
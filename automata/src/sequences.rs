@@ -0,0 +1,323 @@
+//! Sequence arms such as `"for" => ...`
+//!
+//! The sequence arms of a state are merged into a prefix tree, so `"for"`, `"fn"` and `"false"`
+//! share the intermediate state reached after `f`. Intermediate states are generated symbols
+//! named after the state and the prefix read so far, for example `start_sequence_fo`, and their
+//! `Provenance` records the arms going through them.
+//!
+//! A sequence can start with a char that the state already handles, as in `'a'..'z' => identifier`
+//! next to `"for" => ...`. The intermediate state then takes over that char, and falls back to
+//! what the state did with it:
+//!
+//! * A transition to a state becomes the fallback of the intermediate state. The intermediate
+//!   state behaves like that state for every input it has no transition for
+//! * A consuming return or error (`=>>`) becomes the default and `eof` arm of the intermediate
+//!   state, without consuming
+//! * Anything else can't be done once the char is consumed. `select_arms` reports such an arm
+//!   of the state as a conflict, while such a transition of a state that an intermediate state
+//!   falls back to is left out
+//!
+//! The same goes for a sequence ending with a char where a longer sequence continues, and for
+//! intermediate states of intermediate states, which fall back to the fallback's transitions.
+
+use super::Automata;
+use machine::*;
+use priorities::*;
+use provenance::*;
+use statements::*;
+use automata_core::string_interning::*;
+use std::cmp::Ordering;
+
+/// An arm whose sequence ends right after a node of the tree
+#[derive(Debug, Clone)]
+struct SequenceArm {
+    /// The chars the last position of the sequence matches
+    inputs: Vec<char>,
+    destination: Destination,
+    lookahead: Lookahead,
+    context: Option<TrailingContext>,
+    priority: i32,
+    origin: ArmOrigin,
+}
+
+/// Prefix tree of the sequence arms of a state
+/// Edges are labelled with sets of chars so case insensitive sequences share a single path.
+/// Sibling edges never share a char, an edge is split when a sequence only takes part of it
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SequenceTrie {
+    /// Arms whose last char comes after this node, in insertion order
+    arms: Vec<SequenceArm>,
+    /// Longer prefixes
+    children: Vec<(Vec<char>, SequenceTrie)>,
+}
+
+impl SequenceTrie {
+    /// Add a sequence arm given the chars matched at each position, with its destination
+    /// already resolved and its position in the state
+    pub(crate) fn insert(
+        &mut self,
+        sequence: &[Vec<char>],
+        destination: Destination,
+        statement: &Statement,
+        index: usize,
+    ) {
+        let (last, prefix) = match sequence.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+
+        let arm = SequenceArm {
+            inputs: last.clone(),
+            destination,
+            lookahead: statement.lookahead,
+            context: statement.context.clone(),
+            priority: statement.priority,
+            origin: ArmOrigin {
+                index,
+                span: statement.span,
+            },
+        };
+
+        self.insert_arm(prefix, &arm);
+    }
+
+    /// Add an arm below every node its prefix leads to, splitting edges on the way
+    fn insert_arm(&mut self, prefix: &[Vec<char>], arm: &SequenceArm) {
+        let (inputs, rest) = match prefix.split_first() {
+            Some(split) => split,
+            None => {
+                self.arms.push(arm.clone());
+                return;
+            }
+        };
+
+        let mut remaining = inputs.clone();
+        let mut index = 0;
+
+        while index < self.children.len() && !remaining.is_empty() {
+            let common = self.children[index]
+                .0
+                .iter()
+                .filter(|chr| remaining.contains(chr))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if common.is_empty() {
+                index += 1;
+                continue;
+            }
+
+            if common.len() < self.children[index].0.len() {
+                let split_child = self.children[index].1.clone();
+                self.children[index].0.retain(|chr| !common.contains(chr));
+                self.children.insert(index + 1, (common.clone(), split_child));
+                index += 1;
+            }
+
+            remaining.retain(|chr| !common.contains(chr));
+            self.children[index].1.insert_arm(rest, arm);
+            index += 1;
+        }
+
+        if !remaining.is_empty() {
+            let mut child = SequenceTrie::default();
+            child.insert_arm(rest, arm);
+            self.children.push((remaining, child));
+        }
+    }
+}
+
+impl SequenceTrie {
+    /// The arms ending below this node, sorted by position
+    fn origins(&self) -> Vec<ArmOrigin> {
+        let mut origins = self
+            .arms
+            .iter()
+            .map(|arm| arm.origin)
+            .chain(self.children.iter().flat_map(|(_, child)| child.origins()))
+            .collect::<Vec<_>>();
+        origins.sort_by_key(|origin| origin.index);
+        origins.dedup();
+        origins
+    }
+}
+
+/// An intermediate state whose fallback is found once every state is known
+#[derive(Debug)]
+pub(crate) struct PendingSequenceState {
+    name: InternedString,
+    parent: InternedString,
+    /// The chars leading from the parent to the intermediate state
+    inputs: Vec<char>,
+    /// What the parent did with the inputs before the intermediate state took them over
+    replaced: Option<(Destination, Lookahead)>,
+}
+
+/// What expanding the tree of a state carries from node to node
+pub(crate) struct SequenceExpansion<'a> {
+    /// The state the tree belongs to
+    source_name: InternedString,
+    /// The first char of each edge leading to the node being expanded
+    prefix: String,
+    priorities: &'a TokenPriorities,
+    /// Intermediate states created so far, parents first
+    pending_states: &'a mut Vec<PendingSequenceState>,
+}
+
+impl<'a> SequenceExpansion<'a> {
+    pub(crate) fn new(
+        source_name: InternedString,
+        priorities: &'a TokenPriorities,
+        pending_states: &'a mut Vec<PendingSequenceState>,
+    ) -> Self {
+        Self {
+            source_name,
+            prefix: String::new(),
+            priorities,
+            pending_states,
+        }
+    }
+}
+
+impl Automata {
+    /// Add the arms and intermediate states of a tree to the state named `table_name`
+    /// Intermediate states are named after the first char of each edge leading to them, with a
+    /// number appended in the rare case two prefixes give the same name
+    pub(crate) fn expand_sequence_trie(
+        &mut self,
+        expansion: &mut SequenceExpansion,
+        trie: &SequenceTrie,
+        table_name: InternedString,
+        transition_table: &mut TransitionTable,
+    ) {
+        let source_name = expansion.source_name;
+
+        for (index, arm) in trie.arms.iter().enumerate() {
+            for &chr in &arm.inputs {
+                if arm.context.is_none()
+                    && !Self::wins_sequence_input(trie, index, chr, expansion.priorities)
+                {
+                    continue;
+                }
+
+                match arm.context {
+                    Some(ref context) => transition_table.add_context_destination(
+                        chr,
+                        context.clone(),
+                        arm.destination,
+                        arm.lookahead,
+                    ),
+                    None => transition_table.add_destination(chr, arm.destination, arm.lookahead),
+                }
+            }
+        }
+
+        for (inputs, child) in &trie.children {
+            let prefix_len = expansion.prefix.len();
+            expansion.prefix.push(inputs[0]);
+
+            let base_name = self
+                .interner
+                .with_str(source_name, |source_name| {
+                    format!("{}_sequence_{}", source_name, expansion.prefix)
+                })
+                .unwrap();
+            let mut child_name = self.interner.intern_generated(&base_name);
+            let mut suffix = 1;
+
+            while self.generated_states.contains_key(&child_name) {
+                suffix += 1;
+                child_name = self
+                    .interner
+                    .intern_generated(format!("{}_{}", base_name, suffix));
+            }
+
+            self.generated_states.insert(
+                child_name,
+                Provenance::Sequence {
+                    state: source_name,
+                    prefix: expansion.prefix.clone(),
+                    arms: child.origins(),
+                },
+            );
+
+            let replaced = inputs
+                .iter()
+                .filter_map(|&chr| transition_table.explicit_destination_for(chr))
+                .next()
+                .or_else(|| transition_table.default_transition());
+
+            for &chr in inputs {
+                transition_table.replace_with_transition(chr, child_name);
+            }
+
+            expansion.pending_states.push(PendingSequenceState {
+                name: child_name,
+                parent: table_name,
+                inputs: inputs.clone(),
+                replaced,
+            });
+
+            let mut child_table = TransitionTable::new();
+            self.expand_sequence_trie(expansion, child, child_name, &mut child_table);
+            self.state_table.insert(child_name, child_table);
+            expansion.prefix.truncate(prefix_len);
+        }
+    }
+
+    /// Whether an arm without a context wins a char over the other arms ending at the same node
+    /// When no priority orders two arms, the one inserted first wins. Arms ending at other nodes
+    /// were ranked against it by `select_arms`, which left out the chars shorter arms win
+    fn wins_sequence_input(
+        trie: &SequenceTrie,
+        index: usize,
+        input: char,
+        priorities: &TokenPriorities,
+    ) -> bool {
+        let rank = |arm: &SequenceArm| (arm.priority, arm.destination, arm.lookahead);
+        let arm = &trie.arms[index];
+
+        trie.arms
+            .iter()
+            .enumerate()
+            .filter(|&(other_index, other)| {
+                other_index != index && other.context.is_none() && other.inputs.contains(&input)
+            })
+            .all(|(other_index, other)| {
+                match compare_arms(rank(arm), rank(other), priorities) {
+                    Some(Ordering::Greater) => true,
+                    Some(Ordering::Less) => false,
+                    _ => index < other_index,
+                }
+            })
+    }
+
+    /// Give intermediate states the behaviour of what they took over
+    /// States must come after their parent
+    pub(crate) fn resolve_sequence_fallbacks(&mut self, pending_states: Vec<PendingSequenceState>) {
+        for pending_state in pending_states {
+            let replaced = pending_state.replaced.or_else(|| {
+                let parent_fallback = self.state_table[&pending_state.parent].fallback()?;
+                self.unguarded_transition_for(parent_fallback, &pending_state.inputs)
+            });
+
+            let transition_table = self.state_table.get_mut(&pending_state.name).unwrap();
+
+            match replaced {
+                Some((Destination::State(state), _)) => transition_table.set_fallback(state),
+                Some((destination @ Destination::Return(_), Lookahead::Consume))
+                | Some((destination @ Destination::Error(_), Lookahead::Consume)) => {
+                    if transition_table.default_transition().is_none() {
+                        transition_table.set_default_transition(destination, Lookahead::Keep);
+                    }
+
+                    if transition_table.end_of_input_transition().is_none() {
+                        transition_table.set_end_of_input_transition(destination);
+                    }
+                }
+                // Reported by `select_arms` for the arms of the state itself
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Saving resolved Automata, to load them back without parsing their definitions again
+//!
+//! Automata are written to JSON or to a compact binary format, both starting with the version
+//! of the format. Loading checks the version first, so files written by an incompatible version
+//! are reported as such instead of failing somewhere in the middle.
+//!
+//! Interned strings are written as the strings they stand for, and interned again on load in the
+//! interner given. Generated names start with `$`, a `$` starting another name is doubled. States
+//! and keywords are written sorted by name.
+//!
+//! Serde has no way to pass the interner along, so it is entered with `Interner::scope` while the
+//! automata are written or read.
+
+use super::Automata;
+use automata_core::string_interning::*;
+use bincode;
+use serde::{Serialize, Serializer};
+use serde_json;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// Version of the format, bumped whenever the way Automata are written changes
+pub const FORMAT_VERSION: u32 = 3;
+
+#[derive(Serialize)]
+struct VersionedAutomata<'automata> {
+    format_version: u32,
+    automata: &'automata Automata,
+}
+
+/// The automata of some JSON, the version being checked before
+#[derive(Deserialize)]
+struct JsonAutomata {
+    automata: Automata,
+}
+
+/// What is read before the rest, to check the version
+#[derive(Deserialize)]
+struct FormatHeader {
+    format_version: u32,
+}
+
+/// Why Automata could not be written or read back
+#[derive(Debug)]
+pub enum SerializationError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    /// The data was written in another version of the format
+    UnsupportedVersion(u32),
+}
+
+impl Automata {
+    /// Write the automata as JSON
+    pub fn to_json(&self) -> Result<String, SerializationError> {
+        let versioned = VersionedAutomata {
+            format_version: FORMAT_VERSION,
+            automata: self,
+        };
+
+        self.interner
+            .scope(|| serde_json::to_string_pretty(&versioned))
+            .map_err(SerializationError::Json)
+    }
+
+    /// Read automata written by `to_json`, interning their names in an interner
+    pub fn from_json(json: &str, interner: &Interner) -> Result<Automata, SerializationError> {
+        let header: FormatHeader = serde_json::from_str(json).map_err(SerializationError::Json)?;
+        check_version(header.format_version)?;
+
+        interner
+            .scope(|| serde_json::from_str::<JsonAutomata>(json))
+            .map(|json_automata| json_automata.automata.with_interner(interner))
+            .map_err(SerializationError::Json)
+    }
+
+    /// Write the automata in the binary format
+    pub fn to_binary(&self) -> Result<Vec<u8>, SerializationError> {
+        self.interner
+            .scope(|| bincode::serialize(&(FORMAT_VERSION, self)))
+            .map_err(SerializationError::Binary)
+    }
+
+    /// Read automata written by `to_binary`, interning their names in an interner
+    pub fn from_binary(bytes: &[u8], interner: &Interner) -> Result<Automata, SerializationError> {
+        let format_version: u32 =
+            bincode::deserialize(bytes).map_err(SerializationError::Binary)?;
+        check_version(format_version)?;
+
+        interner
+            .scope(|| bincode::deserialize::<(u32, Automata)>(bytes))
+            .map(|(_, automata)| automata.with_interner(interner))
+            .map_err(SerializationError::Binary)
+    }
+
+    fn with_interner(mut self, interner: &Interner) -> Self {
+        self.interner = interner.clone();
+        self
+    }
+}
+
+/// Write a map sorted by the names of its keys, in the interner entered while writing
+pub(crate) fn by_name<V: Serialize, S: Serializer>(
+    map: &BTreeMap<InternedString, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by(|&(&first, _), &(&second, _)| intern_compare(first, second));
+
+    serializer.collect_map(entries)
+}
+
+fn check_version(format_version: u32) -> Result<(), SerializationError> {
+    if format_version == FORMAT_VERSION {
+        Ok(())
+    } else {
+        Err(SerializationError::UnsupportedVersion(format_version))
+    }
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerializationError::Json(ref error) => write!(f, "Invalid JSON automata: {}", error),
+            SerializationError::Binary(ref error) => {
+                write!(f, "Invalid binary automata: {}", error)
+            }
+            SerializationError::UnsupportedVersion(format_version) => write!(
+                f,
+                "Automata were saved in format version {}, only version {} can be read",
+                format_version, FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for SerializationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::*;
+    use interpreter::*;
+    use statements::*;
+    use states::*;
+
+    fn lex_all(automata: &Automata, input: &str) -> Vec<Result<Lexeme, LexError>> {
+        Interpreter::new(automata)
+            .with_error_recovery(ErrorRecovery::Resume)
+            .lex(intern("start"), input)
+            .unwrap()
+            .collect()
+    }
+
+    #[test]
+    fn serialization_test() {
+        let mut start = StateDefinition::new(intern("start"));
+        start.push_statement(Statement::new(
+            Destination::State(intern("identifier")),
+            StatementMatchKind::Range(CharRange::new('a', 'z')),
+            Lookahead::Keep,
+        ));
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("ARROW")),
+                StatementMatchKind::Literal('-'),
+                Lookahead::Consume,
+            )
+            .with_context(TrailingContext::new(
+                false,
+                vec![StatementMatchKind::Literal('>')],
+            )),
+        );
+        start.push_statement(Statement::new(
+            Destination::Push(intern("comment")),
+            StatementMatchKind::Literal('#'),
+            Lookahead::Keep,
+        ));
+        start.push_statement(Statement::new(
+            Destination::Return(intern("SELECT")),
+            StatementMatchKind::Sequence("select".chars().collect()),
+            Lookahead::Consume,
+        ));
+
+        let mut identifier = StateDefinition::new(intern("identifier"));
+        identifier.push_statement(Statement::new(
+            Destination::State(intern("Self")),
+            StatementMatchKind::Range(CharRange::new('a', 'z')),
+            Lookahead::Keep,
+        ));
+        identifier.push_statement(Statement::new(
+            Destination::Return(intern("IDENTIFIER")),
+            StatementMatchKind::Default,
+            Lookahead::Keep,
+        ));
+
+        let mut comment = StateDefinition::new(intern("comment"));
+        comment.push_statement(Statement::new(
+            Destination::Pop,
+            StatementMatchKind::Literal('\n'),
+            Lookahead::Keep,
+        ));
+        comment.push_statement(Statement::new(
+            Destination::Error(intern("unfinished comment")),
+            StatementMatchKind::EndOfInput,
+            Lookahead::Keep,
+        ));
+        comment.push_statement(Statement::new(
+            Destination::State(intern("Self")),
+            StatementMatchKind::Default,
+            Lookahead::Keep,
+        ));
+
+        let mut keywords = KeywordDefinition::new(intern("identifier"));
+        keywords.push_keyword("if".to_string(), intern("KEYWORD_IF"));
+
+        let mut definitions = Definitions::new();
+        definitions.push_state(start);
+        definitions.push_state(identifier);
+        definitions.push_state(comment);
+        definitions.push_keywords(keywords);
+
+        let automata = Automata::resolve_from(definitions);
+        let inputs = ["if sel selects ->-", "abc#skipped\nx", "a#b", "x-y"];
+
+        let json = automata.to_json().unwrap();
+        assert!(json.contains("\"format_version\": 3"));
+        assert!(json.contains("\"$start_sequence_s\""));
+        assert!(json.contains("\"KEYWORD_IF\""));
+        assert!(json.contains("\"unfinished comment\""));
+
+        let interner = automata.interner();
+        let from_json = Automata::from_json(&json, interner).unwrap();
+        let from_binary = Automata::from_binary(&automata.to_binary().unwrap(), interner).unwrap();
+
+        for input in &inputs {
+            assert_eq!(lex_all(&from_json, input), lex_all(&automata, input));
+            assert_eq!(lex_all(&from_binary, input), lex_all(&automata, input));
+        }
+
+        assert_eq!(from_json, automata);
+        assert_eq!(from_binary, automata);
+
+        let newer = json.replacen("\"format_version\": 3", "\"format_version\": 4", 1);
+        match Automata::from_json(&newer, interner) {
+            Err(SerializationError::UnsupportedVersion(4)) => {}
+            result => panic!("Expected an unsupported version, got {:?}", result),
+        }
+
+        let mut newer = automata.to_binary().unwrap();
+        newer[0] = 4;
+        match Automata::from_binary(&newer, interner) {
+            Err(SerializationError::UnsupportedVersion(4)) => {}
+            result => panic!("Expected an unsupported version, got {:?}", result),
+        }
+    }
+}
@@ -1,43 +1,191 @@
 use automata_core::string_interning::*;
+use case_folding::*;
 
 /// A statement is a rule that maps a pattern to a destination
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Statement {
     /// The match pattern
     pub match_kind: StatementMatchKind,
     /// The destination state's name
     pub destination: Destination,
+    /// What happens to the matched char when the destination is a return or an error
+    pub lookahead: Lookahead,
+    /// Input that has to follow the match for the statement to apply
+    pub context: Option<TrailingContext>,
+    /// Priority over other statements matching the same input, set with `#[priority(n)]`
+    pub priority: i32,
+    /// Whether chars also match the chars they share a case fold with
+    pub case_insensitive: bool,
+    /// Where the match pattern was written, when the statement comes from a file
+    pub span: Option<Span>,
 }
 
 impl Statement {
-    /// Create a new statement with destination and match kind
-    pub fn new(destination: Destination, match_kind: StatementMatchKind) -> Self {
+    /// Create a new statement with destination, match kind and lookahead
+    pub fn new(
+        destination: Destination,
+        match_kind: StatementMatchKind,
+        lookahead: Lookahead,
+    ) -> Self {
         Self {
             destination,
             match_kind,
+            lookahead,
+            context: None,
+            priority: 0,
+            case_insensitive: false,
+            span: None,
+        }
+    }
+
+    /// Only apply the statement when the match is followed by `context`
+    pub fn with_context(mut self, context: TrailingContext) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Give the statement a priority over other statements matching the same input
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Record where the match pattern was written
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Make the statement match regardless of case
+    pub fn case_insensitive(mut self) -> Self {
+        self.case_insensitive = true;
+        self
+    }
+
+    /// The chars matched by a literal, a range or a single char sequence, in ascending order
+    /// Other statements match no single char
+    pub fn chars(&self) -> Vec<char> {
+        let chars = match self.match_kind {
+            StatementMatchKind::Literal(chr) => vec![chr],
+            StatementMatchKind::Range(range) => range.into_iter().collect(),
+            StatementMatchKind::Sequence(ref sequence) if sequence.len() == 1 => sequence.clone(),
+            _ => return Vec::new(),
+        };
+
+        self.with_case_variants(chars)
+    }
+
+    /// The chars matched at each position of a sequence
+    pub fn sequence_inputs(&self) -> Vec<Vec<char>> {
+        match self.match_kind {
+            StatementMatchKind::Sequence(ref sequence) => sequence
+                .iter()
+                .map(|&chr| self.with_case_variants(vec![chr]))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Add the case variants of chars when the statement is case insensitive
+    fn with_case_variants(&self, mut chars: Vec<char>) -> Vec<char> {
+        if self.case_insensitive {
+            chars = chars.into_iter().flat_map(case_variants).collect();
+            chars.sort();
+            chars.dedup();
+        }
+
+        chars
+    }
+}
+
+/// A part of a source file, located the way the lexer locates tokens
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    /// Index of the first char
+    pub start: usize,
+    /// Index after the last char
+    pub end: usize,
+    /// Line of the first char, counted from 0
+    pub line: usize,
+    /// Column of the first char
+    pub column: usize,
+}
+
+impl Span {
+    /// Create a new Span
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+}
+
+/// Input that has to follow a match, written `pattern / context` or `pattern / !context`
+/// The context is only looked at, it never becomes part of the token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrailingContext {
+    /// The statement applies when none of the alternatives follow, instead of one of them
+    pub negated: bool,
+    /// Literals, ranges or sequences to look for after the match
+    pub alternatives: Vec<StatementMatchKind>,
+}
+
+impl TrailingContext {
+    /// Create a new TrailingContext
+    pub fn new(negated: bool, alternatives: Vec<StatementMatchKind>) -> Self {
+        Self {
+            negated,
+            alternatives,
         }
     }
 }
 
+/// What happens to the char that made a return or error arm match
+/// Transitions to a state, pushes, pops and marks always consume the char they match
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Lookahead {
+    /// `=>` leaves the char in place, it becomes the first char of the next token
+    Keep,
+    /// `=>>` consumes the char, it becomes the last char of the returned token
+    Consume,
+}
+
 /// A State Destination
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Destination {
     State(InternedString),
     Return(InternedString),
+    /// Switch to a lexer mode, remembering the current one
+    Push(InternedString),
+    /// Go back to the lexer mode that was active before the last push
+    Pop,
+    /// Report a lexing error with a message
+    Error(InternedString),
+    /// Remember the position before the char, then consume it and go to a state
+    /// Only generated, to check trailing contexts
+    Mark(InternedString),
+    /// Go back to the position remembered by the last mark and continue in a state, which reads
+    /// the char after the mark again. Only generated, to check trailing contexts
+    Rollback(InternedString),
 }
 
 /// A kind of statement
 /// represents a pattern to match
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StatementMatchKind {
     Literal(char),
     Range(CharRange),
     Sequence(Vec<char>),
     Default,
+    /// Matches once the input has ended
+    EndOfInput,
 }
 
 /// Range from one character to another
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CharRange {
     from: char,
     to: char,
@@ -49,6 +197,21 @@ impl CharRange {
     pub fn new(from: char, to: char) -> Self {
         CharRange { from, to }
     }
+
+    /// Check if a char is part of the range
+    pub fn contains(&self, chr: char) -> bool {
+        self.from <= chr && chr <= self.to
+    }
+
+    /// The first char of the range
+    pub fn from(&self) -> char {
+        self.from
+    }
+
+    /// The last char of the range
+    pub fn to(&self) -> char {
+        self.to
+    }
 }
 
 impl IntoIterator for CharRange {
@@ -58,7 +221,7 @@ impl IntoIterator for CharRange {
     fn into_iter(self) -> <Self as IntoIterator>::IntoIter {
         CharRangeIntoIterator {
             range: self,
-            current_char: self.from,
+            current_char: Some(self.from),
         }
     }
 }
@@ -66,24 +229,26 @@ impl IntoIterator for CharRange {
 /// Iterator for a CharRange
 pub struct CharRangeIntoIterator {
     range: CharRange,
-    current_char: char,
+    /// `None` once the last char has been returned
+    current_char: Option<char>,
 }
 
 impl Iterator for CharRangeIntoIterator {
     type Item = char;
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        use std::u8;
-        let return_char = self.current_char;
-        let current_char_u8 = return_char as u8;
+        let return_char = self.current_char?;
 
-        let next_char = (current_char_u8 + 1) as char;
-
-        if return_char <= self.range.to {
-            self.current_char = next_char;
-            return Some(return_char);
+        if return_char > self.range.to {
+            return None;
         }
 
-        None
+        // Surrogates aren't chars, so the char after 0xD7FF is 0xE000
+        self.current_char = match return_char as u32 + 1 {
+            0xD800 => Some('\u{E000}'),
+            next_char => ::std::char::from_u32(next_char),
+        };
+
+        Some(return_char)
     }
 }
@@ -3,7 +3,7 @@ use automata_core::string_interning::*;
 
 /// A state definition
 /// Contains the name and the contained statements
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StateDefinition {
     pub name: InternedString,
     pub statements: Vec<Statement>,
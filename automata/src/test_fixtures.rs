@@ -0,0 +1,19 @@
+//! Helpers shared by the tests of several modules
+
+use automata_core::string_interning::*;
+use statements::*;
+use states::*;
+
+/// A state with an arm for each match kind, destination and lookahead, in order
+pub(crate) fn state(
+    name: &str,
+    statements: Vec<(StatementMatchKind, Destination, Lookahead)>,
+) -> StateDefinition {
+    let mut state_definition = StateDefinition::new(intern(name));
+
+    for (match_kind, destination, lookahead) in statements {
+        state_definition.push_statement(Statement::new(destination, match_kind, lookahead));
+    }
+
+    state_definition
+}
@@ -0,0 +1,1052 @@
+//! A text format for resolved Automata, to read them, diff them and load them back
+//!
+//! ```text
+//! version 3
+//! alphabet chars
+//! entry start
+//!
+//! state identifier {
+//!     'a'..'z' => identifier
+//!     _ => return IDENTIFIER
+//! }
+//!
+//! state start {
+//!     '#' => push comment
+//!     '-' => mark $start_context_1
+//!     'a'..'z' => identifier
+//!     'f' => $start_sequence_f
+//!     eof => return END
+//!     fallback common
+//! }
+//!
+//! state $start_context_1 {
+//!     context check "" in start
+//!     '>' => rollback $start_context_1_applies
+//!     _ => rollback $start_context_1_does_not_apply
+//!     eof => rollback $start_context_1_does_not_apply
+//! }
+//!
+//! state $start_sequence_f {
+//!     after "f" in start
+//!     arm 4 at 12:8 130..135
+//!     'n' =>> return KEYWORD_FN
+//!     fallback identifier
+//! }
+//!
+//! keywords identifier {
+//!     "if" => KEYWORD_IF
+//! }
+//! ```
+//!
+//! Arms are written as in automata files, with `Self` resolved, sequences already split into
+//! states and trailing contexts compiled into states reached with `mark` and left with
+//! `rollback`. States and keywords are sorted by name. In a state, runs of chars leading to the
+//! same place are written as ranges, followed by the default, end of input and fallback. `=>>`
+//! marks the returns and errors that consume the char they match, the other arms always do.
+//!
+//! Names that aren't identifiers or that are words of the format, such as `pop`, are quoted.
+//! Generated names start with `$`, and generated states begin with their provenance: `after`
+//! and the arms going through them for sequences, `context` and what the state does for trailing
+//! contexts, `utf8 continuation` and `utf8 rollback` for UTF-8 lowering.
+//! Chars and strings are escaped as in Rust. Reading the text back gives an equal machine.
+
+use super::Automata;
+use classes::CharClasses;
+use definitions::Alphabet;
+use keywords::KeywordTable;
+use machine::TransitionTable;
+use provenance::*;
+use statements::*;
+use automata_core::string_interning::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write;
+
+/// Version of the format, bumped whenever the way Automata are written changes
+pub const TEXT_FORMAT_VERSION: u32 = 3;
+
+/// Words with a meaning in the format, quoted when they are names
+const RESERVED_WORDS: &[&str] = &[
+    "version", "alphabet", "entry", "state", "keywords", "return", "error", "push", "pop", "mark",
+    "rollback", "eof", "fallback", "after", "arm", "context", "utf8",
+];
+
+/// Why some text could not be read as Automata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextFormatError {
+    /// Line of the error, from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl Automata {
+    /// Write the automata in the text format
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        writeln!(text, "version {}", TEXT_FORMAT_VERSION).unwrap();
+        match self.alphabet {
+            Alphabet::Chars => writeln!(text, "alphabet chars").unwrap(),
+            Alphabet::Bytes => writeln!(text, "alphabet bytes").unwrap(),
+        }
+
+        let interner = &self.interner;
+
+        for &entry_point in &self.entry_points {
+            writeln!(text, "entry {}", name_text(interner, entry_point)).unwrap();
+        }
+
+        for state in self.states() {
+            writeln!(text, "\nstate {} {{", name_text(interner, state)).unwrap();
+            if let Some(provenance) = self.generated_states.get(&state) {
+                write_provenance(&mut text, interner, provenance);
+            }
+            write_transition_table(&mut text, interner, &self.state_table[&state]);
+            writeln!(text, "}}").unwrap();
+        }
+
+        let mut keyword_states = self.keyword_tables.keys().cloned().collect::<Vec<_>>();
+        self.sort_by_name(&mut keyword_states);
+
+        for state in keyword_states {
+            writeln!(text, "\nkeywords {} {{", name_text(interner, state)).unwrap();
+
+            for &(ref keyword, token) in self.keyword_tables[&state].slots().iter().flatten() {
+                writeln!(text, "    {:?} => {}", keyword, name_text(interner, token)).unwrap();
+            }
+
+            writeln!(text, "}}").unwrap();
+        }
+
+        text
+    }
+
+    /// Read automata written by `to_text`, interning their names in an interner
+    pub fn from_text(text: &str, interner: &Interner) -> Result<Automata, TextFormatError> {
+        let mut reader = Reader {
+            automata: Automata {
+                state_table: BTreeMap::new(),
+                entry_points: Vec::new(),
+                keyword_tables: BTreeMap::new(),
+                alphabet: Alphabet::Chars,
+                char_classes: CharClasses::default(),
+                generated_states: BTreeMap::new(),
+                interner: interner.clone(),
+            },
+            block: None,
+            has_version: false,
+        };
+
+        let mut line_count = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            line_count = index + 1;
+
+            let tokens = tokenize(line).map_err(|message| TextFormatError {
+                line: line_count,
+                message,
+            })?;
+
+            if tokens.is_empty() {
+                continue;
+            }
+
+            reader
+                .read_line(Line {
+                    tokens,
+                    position: 0,
+                    interner,
+                })
+                .map_err(|message| TextFormatError {
+                    line: line_count,
+                    message,
+                })?;
+        }
+
+        let error = if !reader.has_version {
+            Some("The text has no version")
+        } else if reader.block.is_some() {
+            Some("The last state or keywords are not closed")
+        } else {
+            None
+        };
+
+        if let Some(message) = error {
+            return Err(TextFormatError {
+                line: line_count,
+                message: message.to_string(),
+            });
+        }
+
+        let mut automata = reader.automata;
+        automata.char_classes = CharClasses::from_states(&automata.state_table);
+        Ok(automata)
+    }
+}
+
+fn write_provenance(text: &mut String, interner: &Interner, provenance: &Provenance) {
+    match *provenance {
+        Provenance::Sequence {
+            state,
+            ref prefix,
+            ref arms,
+        } => {
+            writeln!(
+                text,
+                "    after {:?} in {}",
+                prefix,
+                name_text(interner, state)
+            )
+            .unwrap();
+
+            for arm in arms {
+                match arm.span {
+                    Some(span) => writeln!(
+                        text,
+                        "    arm {} at {}:{} {}..{}",
+                        arm.index, span.line, span.column, span.start, span.end
+                    )
+                    .unwrap(),
+                    None => writeln!(text, "    arm {}", arm.index).unwrap(),
+                }
+            }
+        }
+        Provenance::Context { state, ref part } => {
+            let part = match *part {
+                ContextPart::Check { ref prefix } => format!("check {:?}", prefix),
+                ContextPart::Applies => "applies".to_string(),
+                ContextPart::DoesNotApply => "does_not_apply".to_string(),
+            };
+
+            writeln!(
+                text,
+                "    context {} in {}",
+                part,
+                name_text(interner, state)
+            )
+            .unwrap();
+        }
+        Provenance::Utf8Continuation => writeln!(text, "    utf8 continuation").unwrap(),
+        Provenance::Utf8Rollback { state } => {
+            writeln!(text, "    utf8 rollback in {}", name_text(interner, state)).unwrap()
+        }
+    }
+}
+
+fn write_transition_table(
+    text: &mut String,
+    interner: &Interner,
+    transition_table: &TransitionTable,
+) {
+    let inputs = transition_table.explicit_inputs();
+    let mut runs: Vec<(char, char, Destination, Lookahead)> = Vec::new();
+
+    for input in inputs {
+        if let Some((destination, lookahead)) = transition_table.explicit_destination_for(input) {
+            match runs.last_mut() {
+                Some(run)
+                    if run.2 == destination
+                        && run.3 == lookahead
+                        && next_char(run.1) == Some(input) =>
+                {
+                    run.1 = input
+                }
+                _ => runs.push((input, input, destination, lookahead)),
+            }
+        }
+    }
+
+    for (from, to, destination, lookahead) in runs {
+        let input = if from == to {
+            format!("{:?}", from)
+        } else {
+            format!("{:?}..{:?}", from, to)
+        };
+
+        let arrow = match destination {
+            Destination::Return(_) | Destination::Error(_) => arrow(lookahead),
+            _ => "=>",
+        };
+
+        writeln!(
+            text,
+            "    {} {} {}",
+            input,
+            arrow,
+            destination_text(interner, destination)
+        )
+        .unwrap();
+    }
+
+    if let Some((destination, lookahead)) = transition_table.default_transition() {
+        writeln!(
+            text,
+            "    _ {} {}",
+            arrow(lookahead),
+            destination_text(interner, destination)
+        )
+        .unwrap();
+    }
+
+    if let Some(destination) = transition_table.end_of_input_transition() {
+        writeln!(
+            text,
+            "    eof => {}",
+            destination_text(interner, destination)
+        )
+        .unwrap();
+    }
+
+    if let Some(fallback) = transition_table.fallback() {
+        writeln!(text, "    fallback {}", name_text(interner, fallback)).unwrap();
+    }
+}
+
+/// The char after another, skipping surrogates as `CharRange` does
+fn next_char(chr: char) -> Option<char> {
+    match chr as u32 + 1 {
+        0xD800 => Some('\u{E000}'),
+        next_char => ::std::char::from_u32(next_char),
+    }
+}
+
+fn arrow(lookahead: Lookahead) -> &'static str {
+    match lookahead {
+        Lookahead::Keep => "=>",
+        Lookahead::Consume => "=>>",
+    }
+}
+
+fn name_text(interner: &Interner, interned_name: InternedString) -> String {
+    let marker = if interner.is_generated(interned_name) {
+        "$"
+    } else {
+        ""
+    };
+
+    interner
+        .with_str(interned_name, |name| {
+            let is_identifier = name
+                .chars()
+                .all(|chr| chr.is_ascii_alphanumeric() || chr == '_')
+                && name != "_"
+                && !name.is_empty();
+
+            if is_identifier && !RESERVED_WORDS.contains(&name) {
+                format!("{}{}", marker, name)
+            } else {
+                format!("{}{:?}", marker, name)
+            }
+        })
+        .unwrap_or_default()
+}
+
+fn destination_text(interner: &Interner, destination: Destination) -> String {
+    match destination {
+        Destination::State(state) => name_text(interner, state),
+        Destination::Return(token) => format!("return {}", name_text(interner, token)),
+        Destination::Error(message) => interner
+            .with_str(message, |message| format!("error {:?}", message))
+            .unwrap_or_default(),
+        Destination::Push(state) => format!("push {}", name_text(interner, state)),
+        Destination::Pop => "pop".to_string(),
+        Destination::Mark(state) => format!("mark {}", name_text(interner, state)),
+        Destination::Rollback(state) => format!("rollback {}", name_text(interner, state)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    Str(String),
+    Word(String),
+    Range,
+    Colon,
+    Generated,
+    Arrow(Lookahead),
+    Open,
+    Close,
+}
+
+/// Split a line in tokens
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(chr) = chars.next() {
+        let token = match chr {
+            ' ' | '\t' => continue,
+            '\'' => {
+                let literal = match chars.next() {
+                    Some('\\') => unescape(&mut chars)?,
+                    Some(literal) => literal,
+                    None => return Err("Char is not closed".to_string()),
+                };
+
+                if chars.next() != Some('\'') {
+                    return Err("Char is not closed".to_string());
+                }
+
+                Token::Char(literal)
+            }
+            '"' => {
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.push(unescape(&mut chars)?),
+                        Some(chr) => string.push(chr),
+                        None => return Err("String is not closed".to_string()),
+                    }
+                }
+
+                Token::Str(string)
+            }
+            '.' if chars.next() == Some('.') => Token::Range,
+            ':' => Token::Colon,
+            '$' => Token::Generated,
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '=' if chars.next() == Some('>') => {
+                if chars.peek() == Some(&'>') {
+                    chars.next();
+                    Token::Arrow(Lookahead::Consume)
+                } else {
+                    Token::Arrow(Lookahead::Keep)
+                }
+            }
+            chr if chr.is_ascii_alphanumeric() || chr == '_' => {
+                let mut word = chr.to_string();
+
+                while let Some(&chr) = chars.peek() {
+                    if !chr.is_ascii_alphanumeric() && chr != '_' {
+                        break;
+                    }
+
+                    word.push(chr);
+                    chars.next();
+                }
+
+                Token::Word(word)
+            }
+            chr => return Err(format!("Did not expect {:?}", chr)),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Read the char of an escape, after its `\`
+fn unescape<I: Iterator<Item = char>>(chars: &mut I) -> Result<char, String> {
+    let chr = match chars.next() {
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('0') => '\0',
+        Some('u') => {
+            if chars.next() != Some('{') {
+                return Err("Expected '{' after \\u".to_string());
+            }
+
+            let hex = chars.take_while(|&chr| chr != '}').collect::<String>();
+
+            return u32::from_str_radix(&hex, 16)
+                .ok()
+                .and_then(::std::char::from_u32)
+                .ok_or_else(|| format!("Invalid escape \\u{{{}}}", hex));
+        }
+        Some(chr @ '\\') | Some(chr @ '\'') | Some(chr @ '"') => chr,
+        Some(chr) => return Err(format!("Invalid escape \\{}", chr)),
+        None => return Err("Expected an escape after \\".to_string()),
+    };
+
+    Ok(chr)
+}
+
+/// The tokens of a line, read one after the other
+struct Line<'interner> {
+    tokens: Vec<Token>,
+    position: usize,
+    /// Where the names read are interned
+    interner: &'interner Interner,
+}
+
+impl<'interner> Line<'interner> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            _ => Err(format!("Expected {}", what)),
+        }
+    }
+
+    /// Read a name, generated if it starts with `$`
+    fn expect_name(&mut self) -> Result<InternedString, String> {
+        let generated = self.peek() == Some(&Token::Generated);
+        if generated {
+            self.next();
+        }
+
+        let name = match self.next() {
+            Some(Token::Word(ref word)) if word != "_" && !RESERVED_WORDS.contains(&&word[..]) => {
+                word.clone()
+            }
+            Some(Token::Str(name)) => name,
+            _ => return Err("Expected a name".to_string()),
+        };
+
+        if generated {
+            Ok(self.interner.intern_generated(name))
+        } else {
+            Ok(self.interner.intern(name))
+        }
+    }
+
+    fn expect_number(&mut self) -> Result<usize, String> {
+        match self.next() {
+            Some(Token::Word(ref word)) => word
+                .parse::<usize>()
+                .map_err(|_| format!("Expected a number, found `{}`", word)),
+            _ => Err("Expected a number".to_string()),
+        }
+    }
+
+    /// Read a span written `line:column start..end`
+    fn expect_span(&mut self) -> Result<Span, String> {
+        let line = self.expect_number()?;
+        self.expect(Token::Colon, "':' between the line and the column")?;
+        let column = self.expect_number()?;
+        let start = self.expect_number()?;
+        self.expect(Token::Range, "'..' between the start and the end")?;
+        let end = self.expect_number()?;
+
+        Ok(Span::new(start, end, line, column))
+    }
+
+    fn expect_end(&self) -> Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("Did not expect {:?} at the end of the line", token)),
+        }
+    }
+
+    /// Read a char, or a range of chars
+    fn expect_chars(&mut self) -> Result<CharRange, String> {
+        let from = match self.next() {
+            Some(Token::Char(from)) => from,
+            _ => return Err("Expected a char".to_string()),
+        };
+
+        if self.peek() != Some(&Token::Range) {
+            return Ok(CharRange::new(from, from));
+        }
+
+        self.next();
+        match self.next() {
+            Some(Token::Char(to)) => Ok(CharRange::new(from, to)),
+            _ => Err("Expected a char to end the range".to_string()),
+        }
+    }
+
+    fn expect_destination(&mut self) -> Result<Destination, String> {
+        let destination = match self.peek() {
+            Some(Token::Word(ref word)) if word == "return" => {
+                self.next();
+                Destination::Return(self.expect_name()?)
+            }
+            Some(Token::Word(ref word)) if word == "push" => {
+                self.next();
+                Destination::Push(self.expect_name()?)
+            }
+            Some(Token::Word(ref word)) if word == "pop" => {
+                self.next();
+                Destination::Pop
+            }
+            Some(Token::Word(ref word)) if word == "mark" => {
+                self.next();
+                Destination::Mark(self.expect_name()?)
+            }
+            Some(Token::Word(ref word)) if word == "rollback" => {
+                self.next();
+                Destination::Rollback(self.expect_name()?)
+            }
+            Some(Token::Word(ref word)) if word == "error" => {
+                self.next();
+                match self.next() {
+                    Some(Token::Str(message)) => Destination::Error(self.interner.intern(message)),
+                    _ => return Err("Expected a message after error".to_string()),
+                }
+            }
+            _ => Destination::State(self.expect_name()?),
+        };
+
+        self.expect_end()?;
+        Ok(destination)
+    }
+
+    fn expect_arrow(&mut self) -> Result<Lookahead, String> {
+        match self.next() {
+            Some(Token::Arrow(lookahead)) => Ok(lookahead),
+            _ => Err("Expected '=>' or '=>>'".to_string()),
+        }
+    }
+}
+
+/// A state or keywords being read
+enum Block {
+    State(InternedString, Box<TransitionTable>),
+    Keywords(InternedString, Vec<(String, InternedString)>),
+}
+
+struct Reader {
+    automata: Automata,
+    block: Option<Block>,
+    has_version: bool,
+}
+
+impl Reader {
+    fn read_line(&mut self, mut line: Line) -> Result<(), String> {
+        if line.peek() == Some(&Token::Close) {
+            line.next();
+            line.expect_end()?;
+            return self.close_block();
+        }
+
+        if !self.has_version {
+            return self.read_version(line);
+        }
+
+        match self.block {
+            Some(Block::State(state, _)) if is_provenance(&line) => {
+                self.read_provenance(state, line)
+            }
+            Some(Block::State(_, ref mut transition_table)) => read_arm(transition_table, line),
+            Some(Block::Keywords(_, ref mut keywords)) => {
+                let keyword = match line.next() {
+                    Some(Token::Str(keyword)) => keyword,
+                    _ => return Err("Expected a keyword".to_string()),
+                };
+
+                line.expect(Token::Arrow(Lookahead::Keep), "'=>' after the keyword")?;
+                keywords.push((keyword, line.expect_name()?));
+                line.expect_end()
+            }
+            None => self.read_declaration(line),
+        }
+    }
+
+    fn read_version(&mut self, mut line: Line) -> Result<(), String> {
+        line.expect(Token::Word("version".to_string()), "the version first")?;
+
+        let version = match line.next() {
+            Some(Token::Word(version)) => version.parse::<u32>().ok(),
+            _ => None,
+        };
+
+        match version {
+            Some(TEXT_FORMAT_VERSION) => {
+                self.has_version = true;
+                line.expect_end()
+            }
+            Some(version) => Err(format!(
+                "Automata were written in format version {}, only version {} can be read",
+                version, TEXT_FORMAT_VERSION
+            )),
+            None => Err("Expected a version number".to_string()),
+        }
+    }
+
+    fn read_declaration(&mut self, mut line: Line) -> Result<(), String> {
+        let word = match line.next() {
+            Some(Token::Word(word)) => word,
+            _ => return Err("Expected a declaration".to_string()),
+        };
+
+        match &word[..] {
+            "alphabet" => {
+                self.automata.alphabet = match line.next() {
+                    Some(Token::Word(ref alphabet)) if alphabet == "chars" => Alphabet::Chars,
+                    Some(Token::Word(ref alphabet)) if alphabet == "bytes" => Alphabet::Bytes,
+                    _ => return Err("Expected `chars` or `bytes`".to_string()),
+                };
+            }
+            "entry" => {
+                let entry_point = line.expect_name()?;
+                self.automata.entry_points.push(entry_point);
+            }
+            "state" => {
+                let state = line.expect_name()?;
+                if self.automata.state_table.contains_key(&state) {
+                    return Err(format!("State {} is written twice", self.name_text(state)));
+                }
+
+                line.expect(Token::Open, "'{' after the state")?;
+                self.block = Some(Block::State(state, Box::new(TransitionTable::new())));
+            }
+            "keywords" => {
+                let state = line.expect_name()?;
+                if self.automata.keyword_tables.contains_key(&state) {
+                    return Err(format!(
+                        "Keywords of {} are written twice",
+                        self.name_text(state)
+                    ));
+                }
+
+                line.expect(Token::Open, "'{' after the state")?;
+                self.block = Some(Block::Keywords(state, Vec::new()));
+            }
+            _ => return Err(format!("Unknown declaration `{}`", word)),
+        }
+
+        line.expect_end()
+    }
+
+    /// Read a line of the provenance of a generated state
+    fn read_provenance(&mut self, state: InternedString, mut line: Line) -> Result<(), String> {
+        if !self.automata.interner.is_generated(state) {
+            return Err(format!("State {} is not generated", self.name_text(state)));
+        }
+
+        let word = match line.next() {
+            Some(Token::Word(word)) => word,
+            _ => unreachable!(),
+        };
+
+        match &word[..] {
+            "after" => {
+                let prefix = match line.next() {
+                    Some(Token::Str(prefix)) => prefix,
+                    _ => return Err("Expected the chars read after `after`".to_string()),
+                };
+                line.expect(Token::Word("in".to_string()), "`in` after the chars read")?;
+
+                let provenance = Provenance::Sequence {
+                    state: line.expect_name()?,
+                    prefix,
+                    arms: Vec::new(),
+                };
+                self.set_provenance(state, provenance)?;
+            }
+            "arm" => {
+                let index = line.expect_number()?;
+                let span = if line.peek().is_some() {
+                    line.expect(Token::Word("at".to_string()), "`at` before the span")?;
+                    Some(line.expect_span()?)
+                } else {
+                    None
+                };
+
+                match self.automata.generated_states.get_mut(&state) {
+                    Some(Provenance::Sequence { ref mut arms, .. }) => {
+                        arms.push(ArmOrigin { index, span })
+                    }
+                    _ => return Err("Arms can only follow `after`".to_string()),
+                }
+            }
+            "context" => {
+                let part = match line.next() {
+                    Some(Token::Word(ref part)) if part == "check" => match line.next() {
+                        Some(Token::Str(prefix)) => ContextPart::Check { prefix },
+                        _ => return Err("Expected the chars read after `check`".to_string()),
+                    },
+                    Some(Token::Word(ref part)) if part == "applies" => ContextPart::Applies,
+                    Some(Token::Word(ref part)) if part == "does_not_apply" => {
+                        ContextPart::DoesNotApply
+                    }
+                    _ => {
+                        return Err(
+                            "Expected `check`, `applies` or `does_not_apply` after `context`"
+                                .to_string(),
+                        )
+                    }
+                };
+                line.expect(Token::Word("in".to_string()), "`in` before the state")?;
+
+                let provenance = Provenance::Context {
+                    state: line.expect_name()?,
+                    part,
+                };
+                self.set_provenance(state, provenance)?;
+            }
+            _ => {
+                let provenance = match line.next() {
+                    Some(Token::Word(ref word)) if word == "continuation" => {
+                        Provenance::Utf8Continuation
+                    }
+                    Some(Token::Word(ref word)) if word == "rollback" => {
+                        line.expect(Token::Word("in".to_string()), "`in` before the state")?;
+                        Provenance::Utf8Rollback {
+                            state: line.expect_name()?,
+                        }
+                    }
+                    _ => {
+                        return Err("Expected `continuation` or `rollback` after `utf8`".to_string())
+                    }
+                };
+                self.set_provenance(state, provenance)?;
+            }
+        }
+
+        line.expect_end()
+    }
+
+    fn set_provenance(
+        &mut self,
+        state: InternedString,
+        provenance: Provenance,
+    ) -> Result<(), String> {
+        if self.automata.generated_states.contains_key(&state) {
+            return Err(format!(
+                "State {} has two provenances",
+                self.name_text(state)
+            ));
+        }
+
+        self.automata.generated_states.insert(state, provenance);
+        Ok(())
+    }
+
+    fn name_text(&self, state: InternedString) -> String {
+        name_text(&self.automata.interner, state)
+    }
+
+    fn close_block(&mut self) -> Result<(), String> {
+        match self.block.take() {
+            Some(Block::State(state, _))
+                if self.automata.interner.is_generated(state)
+                    && !self.automata.generated_states.contains_key(&state) =>
+            {
+                return Err(format!(
+                    "Generated state {} has no provenance",
+                    self.name_text(state)
+                ));
+            }
+            Some(Block::State(state, transition_table)) => {
+                self.automata.state_table.insert(state, *transition_table);
+            }
+            Some(Block::Keywords(state, keywords)) => {
+                self.automata
+                    .keyword_tables
+                    .insert(state, KeywordTable::new(keywords));
+            }
+            None => return Err("Nothing to close".to_string()),
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a line of a state is part of its provenance
+fn is_provenance(line: &Line) -> bool {
+    match line.peek() {
+        Some(Token::Word(ref word)) => {
+            word == "after" || word == "arm" || word == "context" || word == "utf8"
+        }
+        _ => false,
+    }
+}
+
+/// Read an arm of a state into its transition table
+fn read_arm(transition_table: &mut TransitionTable, mut line: Line) -> Result<(), String> {
+    match line.peek() {
+        Some(Token::Word(ref word)) if word == "fallback" => {
+            line.next();
+            transition_table.set_fallback(line.expect_name()?);
+            line.expect_end()
+        }
+        Some(Token::Word(ref word)) if word == "_" => {
+            line.next();
+            let lookahead = line.expect_arrow()?;
+            transition_table.set_default_transition(line.expect_destination()?, lookahead);
+            Ok(())
+        }
+        Some(Token::Word(ref word)) if word == "eof" => {
+            line.next();
+            line.expect_arrow()?;
+            transition_table.set_end_of_input_transition(line.expect_destination()?);
+            Ok(())
+        }
+        _ => {
+            let inputs = line.expect_chars()?;
+            let lookahead = line.expect_arrow()?;
+            let destination = line.expect_destination()?;
+
+            for input in inputs {
+                transition_table.add_destination(input, destination, lookahead);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for TextFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for TextFormatError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::*;
+    use states::*;
+
+    #[test]
+    fn text_format_test() {
+        let mut start = StateDefinition::new(intern("start"));
+        start.push_statement(Statement::new(
+            Destination::State(intern("identifier")),
+            StatementMatchKind::Range(CharRange::new('a', 'z')),
+            Lookahead::Keep,
+        ));
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("ARROW")),
+                StatementMatchKind::Literal('-'),
+                Lookahead::Consume,
+            )
+            .with_context(TrailingContext::new(
+                true,
+                vec![
+                    StatementMatchKind::Range(CharRange::new('0', '9')),
+                    StatementMatchKind::Sequence("\"'".chars().collect()),
+                ],
+            )),
+        );
+        start.push_statement(Statement::new(
+            Destination::Push(intern("comment")),
+            StatementMatchKind::Literal('#'),
+            Lookahead::Keep,
+        ));
+        start.push_statement(
+            Statement::new(
+                Destination::Return(intern("ASSIGN")),
+                StatementMatchKind::Sequence(":=".chars().collect()),
+                Lookahead::Consume,
+            )
+            .with_span(Span::new(40, 44, 3, 8)),
+        );
+        start.push_statement(Statement::new(
+            Destination::Return(intern("pop")),
+            StatementMatchKind::Literal('\u{301}'),
+            Lookahead::Keep,
+        ));
+
+        let mut identifier = StateDefinition::new(intern("identifier"));
+        identifier.push_statement(Statement::new(
+            Destination::State(intern("Self")),
+            StatementMatchKind::Range(CharRange::new('a', 'z')),
+            Lookahead::Keep,
+        ));
+        identifier.push_statement(Statement::new(
+            Destination::Return(intern("IDENTIFIER")),
+            StatementMatchKind::Default,
+            Lookahead::Keep,
+        ));
+
+        let mut comment = StateDefinition::new(intern("comment"));
+        comment.push_statement(Statement::new(
+            Destination::Pop,
+            StatementMatchKind::Literal('\n'),
+            Lookahead::Keep,
+        ));
+        comment.push_statement(Statement::new(
+            Destination::Error(intern("unfinished \"comment\"")),
+            StatementMatchKind::EndOfInput,
+            Lookahead::Keep,
+        ));
+        comment.push_statement(Statement::new(
+            Destination::State(intern("Self")),
+            StatementMatchKind::Default,
+            Lookahead::Consume,
+        ));
+
+        let mut keywords = KeywordDefinition::new(intern("identifier"));
+        keywords.push_keyword("if".to_string(), intern("KEYWORD_IF"));
+        keywords.push_keyword("else".to_string(), intern("KEYWORD_ELSE"));
+
+        let mut definitions = Definitions::new();
+        definitions.push_state(start);
+        definitions.push_state(identifier);
+        definitions.push_state(comment);
+        definitions.push_keywords(keywords);
+        definitions.push_entry_point(intern("start"));
+
+        let automata = Automata::resolve_from(definitions);
+        let text = automata.to_text();
+
+        assert!(text.starts_with("version 3\nalphabet chars\nentry start\n"));
+        assert!(text.contains(
+            "\nstate identifier {\n    'a'..'z' => identifier\n    _ => return IDENTIFIER\n}\n"
+        ));
+        assert!(text.contains("    '-' => mark $start_context_1\n"));
+        assert!(text.contains(
+            "\nstate $start_context_1 {\n    context check \"\" in start\n    \
+             '\"' => $\"start_context_1_\\\"\"\n    \
+             '0'..'9' => rollback $start_context_1_does_not_apply\n    \
+             _ => rollback $start_context_1_applies\n    \
+             eof => rollback $start_context_1_applies\n}\n"
+        ));
+        assert!(text.contains(
+            "\nstate $start_context_1_applies {\n    context applies in start\n    \
+             '-' =>> return ARROW\n}\n"
+        ));
+        assert!(text.contains("    '\\u{301}' => return \"pop\"\n"));
+        assert!(text.contains("    eof => error \"unfinished \\\"comment\\\"\"\n"));
+        assert!(text.contains(
+            "\nstate $\"start_sequence_:\" {\n    after \":\" in start\n    arm 3 at 3:8 40..44\n"
+        ));
+        assert!(text.contains("    ':' => $\"start_sequence_:\"\n"));
+
+        let interner = automata.interner();
+        assert_eq!(Automata::from_text(&text, interner).unwrap(), automata);
+
+        let bytes = automata.to_utf8_bytes().unwrap();
+        assert_eq!(
+            Automata::from_text(&bytes.to_text(), interner).unwrap(),
+            bytes
+        );
+
+        let text =
+            "version 3\nalphabet bytes\n\nstate other {\n    '\\0'..'\\u{7f}' => start\n}\n\n\
+                    state start {\n    'x' =>> return X\n    fallback other\n}\n";
+        let interner = Interner::new();
+        assert_eq!(
+            Automata::from_text(text, &interner).unwrap().to_text(),
+            text
+        );
+
+        assert_eq!(
+            Automata::from_text(
+                "version 3\nstate start {\n    'a' => pop push\n}\n",
+                &interner
+            ),
+            Err(TextFormatError {
+                line: 3,
+                message: "Did not expect Word(\"push\") at the end of the line".to_string(),
+            })
+        );
+        assert_eq!(
+            Automata::from_text("version 2\n", &interner)
+                .unwrap_err()
+                .to_string(),
+            "line 1: Automata were written in format version 2, only version 3 can be read"
+        );
+    }
+}
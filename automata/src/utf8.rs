@@ -0,0 +1,627 @@
+//! Lowering of char machines to UTF-8 byte machines
+//!
+//! `Automata::to_utf8_bytes` rewrites every state so the machine reads the UTF-8 encoding of its
+//! input one byte at a time, the way regex-automata compiles char classes. A char encoded with
+//! several bytes goes through generated states named `utf8_continuation_n`, which are shared
+//! by every state whose remaining bytes lead to the same places, so `_ => Self` only costs a
+//! few of them.
+//!
+//! A return or error leaving its char in place (`=>`) ends the token before the first byte of the
+//! char. When the chars starting with that byte don't all agree, the byte is marked, and once the
+//! other bytes decided, the machine rolls back to it and continues in a state generated to leave
+//! it in place, named `utf8_rollback_n`. Marks of chars checking a trailing context are on their
+//! first byte too.
+//!
+//! A char a state has no transition for fails on the first byte no char with a transition
+//! starts with, which may come after the first byte of the char. Keywords are looked up with
+//! their UTF-8 bytes.
+
+use super::Automata;
+use classes::CharClasses;
+use definitions::Alphabet;
+use keywords::KeywordTable;
+use machine::TransitionTable;
+use provenance::Provenance;
+use statements::*;
+use automata_core::string_interning::*;
+use std::char;
+use std::collections::{BTreeMap, HashMap};
+
+/// What a state does with a char, `None` when it has no transition for it
+type Action = Option<(Destination, Lookahead)>;
+
+/// Why a machine can't be lowered
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Utf8LoweringError {
+    /// The machine already reads bytes
+    NotACharMachine,
+}
+
+/// Number of bytes of the chars starting with a byte, if it can start a multi-byte char
+fn encoded_len(lead: u8) -> Option<usize> {
+    match lead {
+        0xC2..=0xDF => Some(2),
+        0xE0..=0xEF => Some(3),
+        0xF0..=0xF4 => Some(4),
+        _ => None,
+    }
+}
+
+/// The chars whose encoding with `len` bytes starts with some bytes, if there are any
+fn char_range(bytes: &[u8], len: usize) -> Option<(u32, u32)> {
+    let lead_bits = u32::from(bytes[0] & (0x7F >> len));
+    let (mut from, mut to) = (lead_bits, lead_bits);
+
+    for &byte in &bytes[1..] {
+        from = (from << 6) | u32::from(byte & 0x3F);
+        to = (to << 6) | u32::from(byte & 0x3F);
+    }
+
+    for _ in bytes.len()..len {
+        from <<= 6;
+        to = (to << 6) | 0x3F;
+    }
+
+    let shortest_encoding = [0x80, 0x800, 0x10000][len - 2];
+    let mut from = from.max(shortest_encoding);
+    let mut to = to.min(char::MAX as u32);
+
+    if (0xD800..=0xDFFF).contains(&from) {
+        from = 0xE000;
+    }
+    if (0xD800..=0xDFFF).contains(&to) {
+        to = 0xD7FF;
+    }
+
+    if from <= to {
+        Some((from, to))
+    } else {
+        None
+    }
+}
+
+/// The bytes of some text, each one as the char of the same value
+fn byte_chars(text: &str) -> Vec<char> {
+    text.bytes().map(char::from).collect()
+}
+
+/// Whether an action leaves its char in place
+fn keeps_input(action: Action) -> bool {
+    matches!(
+        action,
+        Some((Destination::Return(_), Lookahead::Keep))
+            | Some((Destination::Error(_), Lookahead::Keep))
+    )
+}
+
+/// Whether the first byte of the chars with an action has to be marked
+fn needs_mark(action: Action) -> bool {
+    match action {
+        Some((Destination::Mark(_), _)) => true,
+        action => keeps_input(action),
+    }
+}
+
+/// Transitions to a state, pushes and pops always consume their input, whatever they were
+/// declared with
+fn normalize(action: Action) -> Action {
+    action.map(|(destination, lookahead)| match destination {
+        Destination::Return(_) | Destination::Error(_) => (destination, lookahead),
+        _ => (destination, Lookahead::Consume),
+    })
+}
+
+/// What a state does with every char, its fallbacks included
+struct CharActions {
+    explicit: BTreeMap<char, Action>,
+    default: Action,
+}
+
+impl CharActions {
+    fn action(&self, input: char) -> Action {
+        self.explicit.get(&input).cloned().unwrap_or(self.default)
+    }
+
+    /// Whether some char from `from` to `to` has an action
+    fn any<F: Fn(Action) -> bool>(&self, from: u32, to: u32, predicate: F) -> bool {
+        let range = char::from_u32(from).unwrap()..=char::from_u32(to).unwrap();
+        let explicit_count = self.explicit.range(range.clone()).count() as u32;
+
+        self.explicit
+            .range(range)
+            .any(|(_, &action)| predicate(action))
+            || explicit_count < to - from + 1 && predicate(self.default)
+    }
+
+    /// The action of every char from `from` to `to`, if they all have the same
+    fn uniform(&self, from: u32, to: u32) -> Option<Action> {
+        let range = char::from_u32(from).unwrap()..=char::from_u32(to).unwrap();
+        let mut shared = None;
+        let mut count = 0;
+
+        for (_, &action) in self.explicit.range(range) {
+            count += 1;
+
+            match shared {
+                None => shared = Some(action),
+                Some(shared_action) if shared_action == action => {}
+                _ => return None,
+            }
+        }
+
+        match shared {
+            None => Some(self.default),
+            Some(action) if count == to - from + 1 || action == self.default => Some(action),
+            _ => None,
+        }
+    }
+}
+
+/// States of the byte machine being built
+struct Lowering {
+    /// Where the names of the intermediate states are interned
+    interner: Interner,
+    state_table: BTreeMap<InternedString, TransitionTable>,
+    /// Intermediate states by their transitions
+    shared_states: HashMap<Vec<(u8, Destination)>, InternedString>,
+    /// States leaving a char in place, by the state that read it and their destination
+    rollback_states: HashMap<(InternedString, Destination), InternedString>,
+}
+
+impl Lowering {
+    /// The intermediate state with some consuming transitions, created if needed
+    fn share(&mut self, transitions: Vec<(u8, Destination)>) -> InternedString {
+        if let Some(&name) = self.shared_states.get(&transitions) {
+            return name;
+        }
+
+        let name = self
+            .interner
+            .intern_generated(format!("utf8_continuation_{}", self.shared_states.len()));
+        let mut transition_table = TransitionTable::new();
+
+        for &(byte, destination) in &transitions {
+            transition_table.add_destination(char::from(byte), destination, Lookahead::Consume);
+        }
+
+        self.state_table.insert(name, transition_table);
+        self.shared_states.insert(transitions, name);
+        name
+    }
+
+    /// The state reading the first byte of a char again to leave it in place, for a state
+    fn rollback_state(
+        &mut self,
+        state: InternedString,
+        destination: Destination,
+    ) -> InternedString {
+        if let Some(&name) = self.rollback_states.get(&(state, destination)) {
+            return name;
+        }
+
+        let name = self
+            .interner
+            .intern_generated(format!("utf8_rollback_{}", self.rollback_states.len()));
+        let mut transition_table = TransitionTable::new();
+        transition_table.set_default_transition(destination, Lookahead::Keep);
+
+        self.state_table.insert(name, transition_table);
+        self.rollback_states.insert((state, destination), name);
+        name
+    }
+
+    /// Where a char of a state leads once its first byte is consumed, that byte being marked
+    /// when the char is left in place or marked itself
+    fn after_first_byte(&mut self, state: InternedString, action: Action) -> Option<Destination> {
+        match action? {
+            (Destination::Mark(next_state), _) => Some(Destination::State(next_state)),
+            action if keeps_input(Some(action)) => {
+                Some(Destination::Rollback(self.rollback_state(state, action.0)))
+            }
+            (destination, _) => Some(destination),
+        }
+    }
+
+    /// The state reading `count` more continuation bytes before going to a destination
+    fn skip_state(&mut self, count: usize, destination: Destination) -> InternedString {
+        let next = if count == 1 {
+            destination
+        } else {
+            Destination::State(self.skip_state(count - 1, destination))
+        };
+
+        self.share((0x80..=0xBF).map(|byte| (byte, next)).collect())
+    }
+
+    /// The state reached once some bytes of a char encoded with `len` bytes are consumed
+    fn continuation_state(
+        &mut self,
+        state: InternedString,
+        actions: &CharActions,
+        bytes: &[u8],
+        len: usize,
+    ) -> InternedString {
+        let mut transitions = Vec::new();
+
+        for byte in 0x80..=0xBF {
+            let mut next_bytes = bytes.to_vec();
+            next_bytes.push(byte);
+
+            let (from, to) = match char_range(&next_bytes, len) {
+                Some(range) => range,
+                None => continue,
+            };
+
+            let destination = if next_bytes.len() == len {
+                self.after_first_byte(state, actions.action(char::from_u32(from).unwrap()))
+            } else {
+                match actions.uniform(from, to) {
+                    Some(action) => self.after_first_byte(state, action).map(|destination| {
+                        Destination::State(self.skip_state(len - next_bytes.len(), destination))
+                    }),
+                    None => Some(Destination::State(self.continuation_state(
+                        state,
+                        actions,
+                        &next_bytes,
+                        len,
+                    ))),
+                }
+            };
+
+            if let Some(destination) = destination {
+                transitions.push((byte, destination));
+            }
+        }
+
+        self.share(transitions)
+    }
+
+    /// Add the transitions of the first byte of the chars encoded with several bytes
+    fn add_lead_byte(
+        &mut self,
+        state: InternedString,
+        transition_table: &mut TransitionTable,
+        actions: &CharActions,
+        lead: u8,
+        len: usize,
+    ) {
+        let input = char::from(lead);
+        let (from, to) = match char_range(&[lead], len) {
+            Some(range) => range,
+            None => return,
+        };
+
+        let destination = match actions.uniform(from, to) {
+            Some(None) => return,
+            Some(Some((destination, lookahead))) if keeps_input(Some((destination, lookahead))) => {
+                transition_table.add_destination(input, destination, lookahead);
+                return;
+            }
+            Some(Some((Destination::Mark(next_state), _))) => {
+                Destination::Mark(self.skip_state(len - 1, Destination::State(next_state)))
+            }
+            Some(Some((destination, _))) => {
+                Destination::State(self.skip_state(len - 1, destination))
+            }
+            None => {
+                let continuation = self.continuation_state(state, actions, &[lead], len);
+
+                if actions.any(from, to, needs_mark) {
+                    Destination::Mark(continuation)
+                } else {
+                    Destination::State(continuation)
+                }
+            }
+        };
+
+        transition_table.add_destination(input, destination, Lookahead::Consume);
+    }
+}
+
+impl Automata {
+    /// An equivalent machine reading the UTF-8 encoding of the input one byte at a time
+    /// Both return the same tokens with the same spans for any text
+    pub fn to_utf8_bytes(&self) -> Result<Automata, Utf8LoweringError> {
+        if self.alphabet != Alphabet::Chars {
+            return Err(Utf8LoweringError::NotACharMachine);
+        }
+
+        let mut lowering = Lowering {
+            interner: self.interner.clone(),
+            state_table: BTreeMap::new(),
+            shared_states: HashMap::new(),
+            rollback_states: HashMap::new(),
+        };
+
+        for name in self.states() {
+            let transition_table = self.lower_state(name, &mut lowering);
+            lowering.state_table.insert(name, transition_table);
+        }
+
+        let keyword_tables = self
+            .keyword_tables
+            .iter()
+            .map(|(&state, keyword_table)| {
+                let keywords = keyword_table
+                    .slots()
+                    .iter()
+                    .filter_map(|slot| slot.clone())
+                    .map(|(text, token)| (byte_chars(&text).into_iter().collect(), token))
+                    .collect();
+
+                (state, KeywordTable::new(keywords))
+            })
+            .collect();
+
+        let mut generated_states = self.generated_states.clone();
+        for &name in lowering.shared_states.values() {
+            generated_states.insert(name, Provenance::Utf8Continuation);
+        }
+        for (&(state, _), &name) in &lowering.rollback_states {
+            generated_states.insert(name, Provenance::Utf8Rollback { state });
+        }
+
+        Ok(Automata {
+            char_classes: CharClasses::from_states(&lowering.state_table),
+            state_table: lowering.state_table,
+            entry_points: self.entry_points.clone(),
+            keyword_tables,
+            alphabet: Alphabet::Bytes,
+            generated_states,
+            interner: self.interner.clone(),
+        })
+    }
+
+    /// The byte transitions of a state, adding the intermediate states it needs
+    fn lower_state(&self, name: InternedString, lowering: &mut Lowering) -> TransitionTable {
+        let chain = self.fallback_chain(name);
+
+        let mut inputs = chain
+            .iter()
+            .flat_map(|transition_table| transition_table.explicit_inputs())
+            .collect::<Vec<_>>();
+        inputs.sort();
+        inputs.dedup();
+
+        let actions = CharActions {
+            explicit: inputs
+                .iter()
+                .map(|&input| {
+                    (
+                        input,
+                        normalize(self.unguarded_transition_for(name, &[input])),
+                    )
+                })
+                .collect(),
+            default: normalize(
+                chain
+                    .iter()
+                    .filter_map(|transition_table| transition_table.default_transition())
+                    .next(),
+            ),
+        };
+
+        let mut transition_table = TransitionTable::new();
+
+        if let Some(destination) = self.end_of_input_transition_for(name) {
+            transition_table.set_end_of_input_transition(destination);
+        }
+
+        for byte in 0..0x80u8 {
+            if let Some((destination, lookahead)) = actions.action(char::from(byte)) {
+                transition_table.add_destination(char::from(byte), destination, lookahead);
+            }
+        }
+
+        for lead in 0xC2..=0xF4 {
+            let len = encoded_len(lead).unwrap();
+            lowering.add_lead_byte(name, &mut transition_table, &actions, lead, len);
+        }
+
+        transition_table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definitions::*;
+    use interpreter::*;
+    use test_fixtures::state;
+
+    fn unicode_automata() -> Automata {
+        let letters = || {
+            vec![
+                StatementMatchKind::Range(CharRange::new('a', 'z')),
+                StatementMatchKind::Literal('é'),
+                StatementMatchKind::Range(CharRange::new('α', 'ω')),
+                StatementMatchKind::Range(CharRange::new('\u{4E00}', '\u{4E0F}')),
+            ]
+        };
+
+        let mut start = vec![
+            (
+                StatementMatchKind::Sequence("αβ".chars().collect()),
+                Destination::Return(intern("ALPHA_BETA")),
+                Lookahead::Consume,
+            ),
+            (
+                StatementMatchKind::Literal('"'),
+                Destination::State(intern("string")),
+                Lookahead::Keep,
+            ),
+            (
+                StatementMatchKind::Literal(' '),
+                Destination::Return(intern("SPACE")),
+                Lookahead::Consume,
+            ),
+            (
+                StatementMatchKind::Literal('→'),
+                Destination::Return(intern("ARROW")),
+                Lookahead::Consume,
+            ),
+            (
+                StatementMatchKind::Literal('-'),
+                Destination::Return(intern("MINUS")),
+                Lookahead::Consume,
+            ),
+        ];
+        for letter in letters() {
+            start.push((
+                letter,
+                Destination::State(intern("identifier")),
+                Lookahead::Keep,
+            ));
+        }
+
+        let mut identifier = vec![
+            (
+                StatementMatchKind::Default,
+                Destination::Return(intern("IDENTIFIER")),
+                Lookahead::Keep,
+            ),
+            (
+                StatementMatchKind::EndOfInput,
+                Destination::Return(intern("IDENTIFIER")),
+                Lookahead::Keep,
+            ),
+            (
+                StatementMatchKind::Literal('😀'),
+                Destination::Return(intern("SMILING_IDENTIFIER")),
+                Lookahead::Consume,
+            ),
+        ];
+        for letter in letters() {
+            identifier.push((letter, Destination::State(intern("Self")), Lookahead::Keep));
+        }
+
+        let mut definitions = Definitions::new();
+        definitions.push_state(state("start", start));
+        definitions.push_state(state("identifier", identifier));
+        definitions.push_state(state(
+            "string",
+            vec![
+                (
+                    StatementMatchKind::Literal('"'),
+                    Destination::Return(intern("STRING")),
+                    Lookahead::Consume,
+                ),
+                (
+                    StatementMatchKind::Literal('\u{10FFFF}'),
+                    Destination::Error(intern("noncharacter")),
+                    Lookahead::Keep,
+                ),
+                (
+                    StatementMatchKind::Default,
+                    Destination::State(intern("Self")),
+                    Lookahead::Keep,
+                ),
+            ],
+        ));
+
+        let mut minus = Statement::new(
+            Destination::Return(intern("MINUS_GREEK")),
+            StatementMatchKind::Literal('-'),
+            Lookahead::Consume,
+        );
+        minus.context = Some(TrailingContext::new(
+            false,
+            vec![StatementMatchKind::Range(CharRange::new('x', 'β'))],
+        ));
+        definitions.states[0].push_statement(minus);
+
+        let arrow = Statement::new(
+            Destination::Return(intern("ARROW_GREEK")),
+            StatementMatchKind::Literal('→'),
+            Lookahead::Consume,
+        )
+        .with_context(TrailingContext::new(
+            true,
+            vec![StatementMatchKind::Sequence("αβ".chars().collect())],
+        ));
+        definitions.states[0].push_statement(arrow);
+
+        let mut keywords = KeywordDefinition::new(intern("identifier"));
+        keywords.push_keyword("été".to_string(), intern("KW_ETE"));
+        definitions.push_keywords(keywords);
+
+        Automata::resolve_from(definitions)
+    }
+
+    #[test]
+    fn utf8_bytes_test() {
+        let automata = unicode_automata();
+        let byte_automata = automata.to_utf8_bytes().unwrap();
+
+        assert_eq!(byte_automata.alphabet(), Alphabet::Bytes);
+        assert_eq!(
+            byte_automata.to_utf8_bytes().unwrap_err(),
+            Utf8LoweringError::NotACharMachine
+        );
+
+        let chars = [
+            'a', 'z', 'é', 'è', 't', 'α', 'β', 'ω', '中', '一', '丏', '0', ' ', '"', '-', '→',
+            '😀', '\u{7F}', '\u{80}', '\u{7FF}', '\u{800}', '\u{FFFF}', '\u{10000}', '\u{10FFFF}',
+        ];
+
+        let char_interpreter = Interpreter::new(&automata);
+        let byte_interpreter = Interpreter::new(&byte_automata);
+        let mut seed = 0x2545_F491u32;
+
+        for _ in 0..5000 {
+            let mut text = String::new();
+
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+
+            for _ in 0..seed % 8 {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                text.push(chars[seed as usize % chars.len()]);
+            }
+
+            let accepted = |results: Vec<Result<Lexeme, LexError>>| {
+                results
+                    .into_iter()
+                    .map(|result| result.map_err(|_| ()))
+                    .collect::<Vec<_>>()
+            };
+
+            let char_results = char_interpreter
+                .lex(intern("start"), &text)
+                .unwrap()
+                .collect();
+            let byte_results = byte_interpreter
+                .lex_bytes(intern("start"), text.as_bytes())
+                .unwrap()
+                .collect();
+
+            assert_eq!(accepted(char_results), accepted(byte_results), "{:?}", text);
+        }
+
+        let results = byte_interpreter
+            .lex_bytes(intern("start"), "été-β".as_bytes())
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(Lexeme {
+                    token: intern("KW_ETE"),
+                    span: (0, 5),
+                }),
+                Ok(Lexeme {
+                    token: intern("MINUS_GREEK"),
+                    span: (5, 6),
+                }),
+                Ok(Lexeme {
+                    token: intern("IDENTIFIER"),
+                    span: (6, 8),
+                }),
+            ]
+        );
+    }
+}
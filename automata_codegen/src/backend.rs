@@ -0,0 +1,364 @@
+//! Backends writing a resolved machine out
+//!
+//! A backend implements `Backend` and is added to a `BackendRegistry`, where the command line
+//! looks it up by name. A backend maintained outside of this repository doesn't need any change
+//! here: its own binary registers it next to the built in ones and hands the registry to
+//! `snow_automata::cli::run`.
+//!
+//! Backends read a `LexerIr`, the resolved `Automata` along with the tokens it returns, and give
+//! back the files they wrote out. Writing the files is left to the caller, see
+//! `BackendOutput::write_to`. Names are read back with `Automata::interner`, and written in the
+//! order of `Interner::compare` so the output doesn't depend on the order they were interned in.
+
+use automata::statements::*;
+use automata::Automata;
+use automata_core::string_interning::*;
+use rust::RustBackend;
+use serialized::SerializedBackend;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What backends are given
+#[derive(Debug)]
+pub struct LexerIr<'automata> {
+    /// Name of the lexer, the name of its file without the extension
+    pub name: String,
+    pub automata: &'automata Automata,
+    /// Every token the machine returns, sorted by name
+    pub tokens: Vec<TokenInfo>,
+}
+
+/// A token returned by a machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub name: InternedString,
+    /// States with a transition returning the token, sorted by name
+    pub returned_by: Vec<InternedString>,
+    /// Keywords the token is given to, with the state they belong to, sorted
+    pub keywords: Vec<(InternedString, String)>,
+}
+
+/// A file written by a backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFile {
+    /// Path of the file, relative to the output directory
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+/// What a backend wrote out
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackendOutput {
+    pub files: Vec<OutputFile>,
+    /// Things to tell the user about the output, such as the size of tables
+    pub notes: Vec<String>,
+}
+
+/// Why a backend could not write a machine out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendError {
+    /// The backend has no option of this name
+    UnknownOption(String),
+    /// An option was given a value the backend doesn't take
+    InvalidOption {
+        name: String,
+        value: String,
+        /// What the option takes
+        expected: String,
+    },
+    /// The backend can't write this machine out
+    Unsupported(String),
+}
+
+/// A way to write a machine out
+pub trait Backend {
+    /// Name the backend is picked with, as in `--backend rust`
+    fn name(&self) -> &str;
+
+    /// One line describing what the backend writes
+    fn description(&self) -> &str;
+
+    /// Options of the backend, as `(name, description)`
+    fn options(&self) -> &[(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// Set an option given as `name=value`, see `parse_option`
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), BackendError> {
+        let _ = value;
+        Err(BackendError::UnknownOption(name.to_string()))
+    }
+
+    /// Write a machine out
+    fn generate(&self, ir: &LexerIr) -> Result<BackendOutput, BackendError>;
+}
+
+/// Backends by name
+#[derive(Default)]
+pub struct BackendRegistry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl<'automata> LexerIr<'automata> {
+    /// Gather the tokens of a machine
+    pub fn new<S: Into<String>>(name: S, automata: &'automata Automata) -> Self {
+        let mut tokens: Vec<TokenInfo> = Vec::new();
+        let representatives = automata.char_classes().representatives();
+
+        for state in automata.states() {
+            let mut destinations = Vec::new();
+
+            for &representative in &representatives {
+                destinations.extend(
+                    automata
+                        .unguarded_transition(state, representative)
+                        .map(|(destination, _)| destination),
+                );
+            }
+
+            destinations.extend(automata.end_of_input_transition(state));
+
+            for destination in destinations {
+                if let Destination::Return(token) = destination {
+                    let returned_by = &mut token_info(&mut tokens, token).returned_by;
+                    if !returned_by.contains(&state) {
+                        returned_by.push(state);
+                    }
+                }
+            }
+
+            if let Some(keyword_table) = automata.keyword_table(state) {
+                for &(ref keyword, token) in keyword_table.slots().iter().flatten() {
+                    token_info(&mut tokens, token)
+                        .keywords
+                        .push((state, keyword.clone()));
+                }
+            }
+        }
+
+        for token_info in &mut tokens {
+            token_info
+                .keywords
+                .sort_by(|first, second| first.1.cmp(&second.1));
+        }
+
+        let interner = automata.interner();
+        tokens.sort_by(|first, second| interner.compare(first.name, second.name));
+
+        Self {
+            name: name.into(),
+            automata,
+            tokens,
+        }
+    }
+}
+
+impl BackendOutput {
+    /// Write the files below a directory, leaving the ones that wouldn't change alone
+    /// Returns the paths of the files written
+    pub fn write_to<P: AsRef<Path>>(&self, dir: P) -> io::Result<Vec<PathBuf>> {
+        let mut written = Vec::new();
+
+        for file in &self.files {
+            let path = dir.as_ref().join(&file.path);
+
+            if fs::read(&path).ok().as_ref() == Some(&file.contents) {
+                continue;
+            }
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            fs::write(&path, &file.contents)?;
+            written.push(path);
+        }
+
+        Ok(written)
+    }
+}
+
+impl BackendRegistry {
+    /// Create a registry without any backend
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry with the backends of this crate
+    pub fn with_builtin_backends() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(RustBackend::new()));
+        registry.register(Box::new(SerializedBackend::new()));
+        registry
+    }
+
+    /// Add a backend, replacing the one of the same name if there is one
+    pub fn register(&mut self, backend: Box<dyn Backend>) {
+        match self
+            .backends
+            .iter()
+            .position(|registered| registered.name() == backend.name())
+        {
+            Some(index) => self.backends[index] = backend,
+            None => self.backends.push(backend),
+        }
+    }
+
+    /// The backend of a name
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut (dyn Backend + 'static)> {
+        self.backends
+            .iter_mut()
+            .find(|backend| backend.name() == name)
+            .map(|backend| &mut **backend)
+    }
+
+    /// The backends, in the order they were registered
+    pub fn backends(&self) -> impl Iterator<Item = &dyn Backend> {
+        self.backends.iter().map(|backend| &**backend)
+    }
+}
+
+impl fmt::Debug for BackendRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.backends().map(|backend| backend.name()))
+            .finish()
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackendError::UnknownOption(ref name) => write!(f, "Unknown option `{}`", name),
+            BackendError::InvalidOption {
+                ref name,
+                ref value,
+                ref expected,
+            } => write!(
+                f,
+                "Option `{}` can't be `{}`, it takes {}",
+                name, value, expected
+            ),
+            BackendError::Unsupported(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for BackendError {}
+
+/// The info of a token, added if it isn't there yet
+fn token_info(tokens: &mut Vec<TokenInfo>, token: InternedString) -> &mut TokenInfo {
+    let index = match tokens
+        .iter()
+        .position(|token_info| token_info.name == token)
+    {
+        Some(index) => index,
+        None => {
+            tokens.push(TokenInfo {
+                name: token,
+                returned_by: Vec::new(),
+                keywords: Vec::new(),
+            });
+            tokens.len() - 1
+        }
+    };
+
+    &mut tokens[index]
+}
+
+/// Split an option written `name=value`, a lone `name` being `name=true`
+pub fn parse_option(option: &str) -> (&str, &str) {
+    match option.find('=') {
+        Some(index) => (&option[..index], &option[index + 1..]),
+        None => (option, "true"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use automata_syntax::SyntaxParser;
+
+    #[test]
+    fn backend_registry_test() {
+        let mut parser = SyntaxParser::new(
+            r#"
+            keywords identifier {
+                "if" => KEYWORD_IF
+            }
+
+            state start {
+                'a'..'z' => identifier
+                ' ' =>> return SPACE
+            }
+
+            state identifier {
+                'a'..'z' => Self
+                _ => return IDENTIFIER
+                eof => return IDENTIFIER
+            }
+            "#,
+        );
+        let automata = Automata::resolve_from(parser.parse());
+        let ir = LexerIr::new("words", &automata);
+
+        assert_eq!(
+            ir.tokens,
+            vec![
+                TokenInfo {
+                    name: intern("IDENTIFIER"),
+                    returned_by: vec![intern("identifier")],
+                    keywords: Vec::new(),
+                },
+                TokenInfo {
+                    name: intern("KEYWORD_IF"),
+                    returned_by: Vec::new(),
+                    keywords: vec![(intern("identifier"), "if".to_string())],
+                },
+                TokenInfo {
+                    name: intern("SPACE"),
+                    returned_by: vec![intern("start")],
+                    keywords: Vec::new(),
+                },
+            ]
+        );
+
+        let mut registry = BackendRegistry::with_builtin_backends();
+        assert_eq!(parse_option("output=table"), ("output", "table"));
+
+        {
+            let rust = registry.get_mut("rust").unwrap();
+            rust.set_option("output", "table").unwrap();
+            assert_eq!(
+                rust.set_option("output", "tree"),
+                Err(BackendError::InvalidOption {
+                    name: "output".to_string(),
+                    value: "tree".to_string(),
+                    expected: "`match` or `table`".to_string(),
+                })
+            );
+            assert_eq!(
+                rust.set_option("indent", "4"),
+                Err(BackendError::UnknownOption("indent".to_string()))
+            );
+
+            let output = rust.generate(&ir).unwrap();
+            assert_eq!(output.files.len(), 1);
+            assert_eq!(output.files[0].path, PathBuf::from("words.rs"));
+            assert!(String::from_utf8_lossy(&output.files[0].contents).contains("static NEXT"));
+            assert_eq!(output.notes.len(), 1);
+        }
+
+        assert!(registry.get_mut("dsl").is_none());
+        assert_eq!(
+            registry
+                .backends()
+                .map(|backend| backend.name())
+                .collect::<Vec<_>>(),
+            vec!["rust", "serialized"]
+        );
+    }
+}
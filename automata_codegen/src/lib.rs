@@ -0,0 +1,11 @@
+//! Code generation from resolved Automata
+
+extern crate automata;
+extern crate automata_core;
+
+#[cfg(test)]
+extern crate automata_syntax;
+
+pub mod backend;
+pub mod rust;
+pub mod serialized;
@@ -0,0 +1,435 @@
+//! Parts of the generated lexer that don't depend on the machine
+
+/// Types and lexing loop, which behave like `automata::interpreter`
+pub(crate) const DRIVER: &str = r#"
+/// Maximum number of modes on the stack, entry point included, unless configured otherwise
+pub const DEFAULT_MAX_STACK_DEPTH: usize = 64;
+
+/// What the lexer does after an error
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorRecovery {
+    /// Stop lexing
+    Stop,
+    /// Keep lexing after the error
+    Resume,
+}
+
+/// A token read from the input
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Lexeme {
+    pub token: Token,
+    /// Byte range of the token in the input
+    pub span: (usize, usize),
+}
+
+/// An error found by the lexer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    /// Byte range from the start of the token to the error
+    pub span: (usize, usize),
+}
+
+/// A kind of lexing error
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LexErrorKind {
+    /// No transition for this char
+    UnexpectedChar(char),
+    /// No transition for this byte
+    UnexpectedByte(u8),
+    /// The input ended in the middle of a token
+    UnexpectedEndOfInput,
+    /// A token would have been returned without reading anything
+    EmptyToken(Token),
+    /// A transition goes to a state that isn't defined
+    UndefinedState(&'static str),
+    /// A push went over the maximum stack depth
+    StackOverflow,
+    /// A pop was attempted with only the entry point on the stack
+    StackUnderflow,
+    /// An `=> error "message"` arm was reached
+    Custom(&'static str),
+}
+
+/// What a state does with an input, the booleans telling whether the input is consumed
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+enum Action {
+    Goto(usize),
+    Return(Token, bool),
+    Error(&'static str, bool),
+    Push(usize),
+    Pop,
+    Mark(usize),
+    Rollback(usize),
+}
+
+/// Iterator over the tokens of some input
+#[derive(Debug)]
+pub struct Lexer<'input> {
+    input: &'input Input,
+    position: usize,
+    /// Position before the char of the last mark
+    mark: usize,
+    mode_stack: Vec<usize>,
+    max_stack_depth: usize,
+    error_recovery: ErrorRecovery,
+    finished: bool,
+}
+
+impl<'input> Lexer<'input> {
+    /// Lex some input starting from an entry point
+    pub fn new(entry_point: EntryPoint, input: &'input Input) -> Self {
+        Lexer {
+            input,
+            position: 0,
+            mark: 0,
+            mode_stack: vec![entry_point.state()],
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
+            error_recovery: ErrorRecovery::Stop,
+            finished: false,
+        }
+    }
+
+    /// Set the maximum number of modes on the stack, entry point included
+    pub fn with_max_stack_depth(mut self, max_stack_depth: usize) -> Self {
+        self.max_stack_depth = max_stack_depth;
+        self
+    }
+
+    /// Set what the lexer does after an error, stopping by default
+    pub fn with_error_recovery(mut self, error_recovery: ErrorRecovery) -> Self {
+        self.error_recovery = error_recovery;
+        self
+    }
+
+    /// The names of the modes on the stack, entry point first
+    pub fn mode_stack(&self) -> Vec<&'static str> {
+        self.mode_stack.iter().map(|&state| STATE_NAMES[state]).collect()
+    }
+
+    /// Scan the next token
+    fn scan_token(&mut self) -> Option<Result<Lexeme, LexError>> {
+        let start = self.position;
+        let state = *self.mode_stack.last()?;
+
+        self.scan(start, state)
+    }
+
+    /// Scan from a state, the token having started at `start`
+    fn scan(&mut self, mut start: usize, mut state: usize) -> Option<Result<Lexeme, LexError>> {
+        macro_rules! lex_err {
+            ($kind: expr) => {
+                return Some(Err(LexError {
+                    kind: $kind,
+                    span: (start, self.position),
+                }));
+            };
+        }
+
+        loop {
+            if state >= DEFINED_STATES {
+                lex_err!(LexErrorKind::UndefinedState(STATE_NAMES[state]));
+            }
+
+            let chr = match chars_from(self.input, self.position).next() {
+                Some(chr) => chr,
+                None => return self.end_of_input(start, state),
+            };
+
+            let action = match transition(state, chr) {
+                Some(action) => action,
+                None => {
+                    lex_err!(unexpected(chr));
+                }
+            };
+
+            let next_position = self.position + char_len(chr);
+
+            match action {
+                Action::Goto(next_state) => {
+                    self.position = next_position;
+                    state = next_state;
+                }
+                Action::Return(token, consume) => {
+                    if consume {
+                        self.position = next_position;
+                    }
+
+                    if self.position == start {
+                        lex_err!(LexErrorKind::EmptyToken(token));
+                    }
+
+                    return Some(Ok(Lexeme {
+                        token: self.reclassify(state, token, start),
+                        span: (start, self.position),
+                    }));
+                }
+                Action::Error(message, consume) => {
+                    if consume {
+                        self.position = next_position;
+                    }
+
+                    lex_err!(LexErrorKind::Custom(message));
+                }
+                Action::Push(mode) => {
+                    if self.mode_stack.len() >= self.max_stack_depth {
+                        lex_err!(LexErrorKind::StackOverflow);
+                    }
+
+                    self.mode_stack.push(mode);
+                    self.position = next_position;
+                    start = next_position;
+                    state = mode;
+                }
+                Action::Pop => {
+                    if self.mode_stack.len() <= 1 {
+                        lex_err!(LexErrorKind::StackUnderflow);
+                    }
+
+                    let _ = self.mode_stack.pop();
+                    self.position = next_position;
+                    start = next_position;
+                    state = *self.mode_stack.last().unwrap();
+                }
+                Action::Mark(next_state) => {
+                    self.mark = self.position;
+                    self.position = next_position;
+                    state = next_state;
+                }
+                Action::Rollback(next_state) => {
+                    self.position = self.mark;
+                    state = next_state;
+                }
+            }
+        }
+    }
+
+    /// Replace a token returned by `state` if its text is one of the state's keywords
+    fn reclassify(&self, state: usize, token: Token, start: usize) -> Token {
+        keyword(state, &self.input[start..self.position]).unwrap_or(token)
+    }
+
+    /// Get ready to scan the next token after an error
+    fn recover_from(&mut self, error: LexError) {
+        match self.error_recovery {
+            ErrorRecovery::Stop => self.finished = true,
+            ErrorRecovery::Resume => {
+                if error.span.0 != error.span.1 {
+                    return;
+                }
+
+                match chars_from(self.input, self.position).next() {
+                    Some(chr) => self.position += char_len(chr),
+                    None => self.finished = true,
+                }
+            }
+        }
+    }
+
+    /// Follow the `eof` arms once the input has ended in `state`
+    fn end_of_input(&mut self, start: usize, mut state: usize) -> Option<Result<Lexeme, LexError>> {
+        let mut visited_states = Vec::new();
+
+        macro_rules! lex_err {
+            ($kind: expr) => {
+                return Some(Err(LexError {
+                    kind: $kind,
+                    span: (start, self.position),
+                }));
+            };
+        }
+
+        loop {
+            if state >= DEFINED_STATES {
+                lex_err!(LexErrorKind::UndefinedState(STATE_NAMES[state]));
+            }
+
+            let action = match end_of_input(state) {
+                Some(action) => action,
+                None if self.position == start && visited_states.is_empty() => return None,
+                None => {
+                    lex_err!(LexErrorKind::UnexpectedEndOfInput);
+                }
+            };
+
+            if let Action::Rollback(next_state) = action {
+                self.position = self.mark;
+                return self.scan(start, next_state);
+            }
+
+            if self.position == start {
+                self.finished = true;
+            }
+
+            match action {
+                Action::Goto(next_state) | Action::Mark(next_state) => {
+                    visited_states.push(state);
+
+                    if visited_states.contains(&next_state) {
+                        lex_err!(LexErrorKind::UnexpectedEndOfInput);
+                    }
+
+                    state = next_state;
+                }
+                Action::Return(token, _) => {
+                    return Some(Ok(Lexeme {
+                        token: self.reclassify(state, token, start),
+                        span: (start, self.position),
+                    }));
+                }
+                Action::Error(message, _) => {
+                    lex_err!(LexErrorKind::Custom(message));
+                }
+                // The chars read since `start` can't be returned, they are reported, and the
+                // push or pop is done once the input is read again from where they end
+                Action::Push(_) | Action::Pop if self.position != start => {
+                    lex_err!(LexErrorKind::UnexpectedEndOfInput);
+                }
+                Action::Push(mode) => {
+                    if self.mode_stack.len() >= self.max_stack_depth {
+                        lex_err!(LexErrorKind::StackOverflow);
+                    }
+
+                    self.mode_stack.push(mode);
+                    return None;
+                }
+                Action::Pop => {
+                    if self.mode_stack.len() <= 1 {
+                        lex_err!(LexErrorKind::StackUnderflow);
+                    }
+
+                    let _ = self.mode_stack.pop();
+                    return None;
+                }
+                Action::Rollback(_) => unreachable!(),
+            }
+        }
+    }
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Result<Lexeme, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.scan_token();
+
+        match result {
+            Some(Ok(_)) => {}
+            Some(Err(error)) => self.recover_from(error),
+            None => self.finished = true,
+        }
+
+        result
+    }
+}
+"#;
+
+/// Reading text
+pub(crate) const CHAR_INPUT: &str = r#"
+/// What the lexer reads
+pub type Input = str;
+
+fn chars_from<'input>(input: &'input Input, position: usize) -> ::std::str::Chars<'input> {
+    input[position..].chars()
+}
+
+fn char_len(chr: char) -> usize {
+    chr.len_utf8()
+}
+
+fn unexpected(chr: char) -> LexErrorKind {
+    LexErrorKind::UnexpectedChar(chr)
+}
+"#;
+
+/// Reading bytes, each one as the char of the same value
+pub(crate) const BYTE_INPUT: &str = r#"
+/// What the lexer reads
+pub type Input = [u8];
+
+fn byte_char(byte: &u8) -> char {
+    char::from(*byte)
+}
+
+fn chars_from<'input>(
+    input: &'input Input,
+    position: usize,
+) -> ::std::iter::Map<::std::slice::Iter<'input, u8>, fn(&u8) -> char> {
+    input[position..].iter().map(byte_char as fn(&u8) -> char)
+}
+
+fn char_len(_: char) -> usize {
+    1
+}
+
+fn unexpected(chr: char) -> LexErrorKind {
+    LexErrorKind::UnexpectedByte(chr as u8)
+}
+"#;
+
+/// Looking keywords up in the perfect hash tables built by `automata::keywords`
+pub(crate) const KEYWORD_LOOKUP: &str = r#"
+/// Same hash as `automata::keywords::keyword_hash`
+fn keyword_hash<I: Iterator<Item = u8>>(bytes: I, seed: u32) -> u32 {
+    let mut hash = 0x811c_9dc5 ^ seed;
+
+    for byte in bytes {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+/// Find the token of a keyword in a table
+fn keyword_in(
+    displacements: &[u32],
+    slots: &[Option<(&'static Input, Token)>],
+    text: &Input,
+) -> Option<Token> {
+    let bucket = keyword_hash(hashed_bytes(text), 0) as usize % displacements.len();
+    let seed = displacements[bucket];
+    let slot = keyword_hash(hashed_bytes(text), seed) as usize % slots.len();
+
+    match slots[slot] {
+        Some((keyword, token)) if keyword == text => Some(token),
+        _ => None,
+    }
+}
+"#;
+
+/// Hashing keywords read as text
+pub(crate) const CHAR_KEYWORD_BYTES: &str = r#"
+/// The bytes a keyword is hashed with
+fn hashed_bytes(text: &Input) -> ::std::str::Bytes<'_> {
+    text.bytes()
+}
+"#;
+
+/// Hashing keywords read as bytes, the same way as the text of their chars
+pub(crate) const BYTE_KEYWORD_BYTES: &str = r#"
+/// The bytes a keyword is hashed with, the UTF-8 encoding of the chars of the same values
+fn hashed_bytes<'text>(text: &'text Input) -> impl Iterator<Item = u8> + 'text {
+    text.iter().flat_map(|&byte| {
+        let (first, second, len) = if byte < 0x80 {
+            (byte, 0, 1)
+        } else {
+            (0xC0 | byte >> 6, 0x80 | byte & 0x3F, 2)
+        };
+
+        ::std::iter::once(first)
+            .chain(::std::iter::once(second))
+            .take(len)
+    })
+}
+"#;
@@ -0,0 +1,110 @@
+//! Transitions written as `match` expressions
+//!
+//! Each state matches the char against the char ranges of its classes, grouping the ranges
+//! leading to the same action in a single arm, and class 0 ends up in the `_` arm.
+
+use super::{Action, Machine};
+use std::fmt::Write;
+
+/// An action with the char ranges leading to it
+type Arm = (Option<Action>, Vec<(char, char)>);
+
+/// Char ranges of the classes of a machine
+fn class_ranges(machine: &Machine) -> Vec<Vec<(char, char)>> {
+    let mut ranges = vec![Vec::new(); machine.class_count];
+
+    for &(first, last, class) in &machine.class_ranges {
+        ranges[class].push((first, last));
+    }
+
+    ranges
+}
+
+/// A pattern matching some char ranges, merging the ones that follow each other
+fn pattern(ranges: &[(char, char)]) -> String {
+    let mut ranges = ranges.to_vec();
+    ranges.sort();
+
+    let mut merged: Vec<(char, char)> = Vec::new();
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some(&mut (_, ref mut merged_last)) if *merged_last as u32 + 1 == first as u32 => {
+                *merged_last = last
+            }
+            _ => merged.push((first, last)),
+        }
+    }
+
+    merged
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                format!("{:?}", first)
+            } else {
+                format!("{:?}..={:?}", first, last)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Write the `transition` function
+pub(crate) fn write_transitions(machine: &Machine, code: &mut String) {
+    let ranges = class_ranges(machine);
+
+    code.push_str("\n/// What a state does with a char\n");
+    code.push_str("fn transition(state: usize, chr: char) -> Option<Action> {\n");
+    code.push_str("    match state {\n");
+
+    for state in 0..machine.defined_states {
+        // Generated states get a comment saying where they come from
+        if let Some(provenance) = machine.automata.provenance(machine.state_names[state]) {
+            let provenance = provenance.describe(machine.automata.interner());
+            let _ = writeln!(code, "        // {}", provenance.escape_debug());
+        }
+
+        let _ = writeln!(code, "        {} => match chr {{", state);
+
+        let default_action = machine.actions[state][0];
+        let mut arms: Vec<Arm> = Vec::new();
+
+        for (class_ranges, &action) in ranges.iter().zip(&machine.actions[state]).skip(1) {
+            if action == default_action {
+                continue;
+            }
+
+            match arms
+                .iter()
+                .position(|&(arm_action, _)| arm_action == action)
+            {
+                Some(index) => arms[index].1.extend(class_ranges.iter().cloned()),
+                None => arms.push((action, class_ranges.clone())),
+            }
+        }
+
+        for (action, arm_ranges) in arms {
+            let _ = writeln!(
+                code,
+                "            {} => {},",
+                pattern(&arm_ranges),
+                option_code(machine, action)
+            );
+        }
+
+        let _ = writeln!(
+            code,
+            "            _ => {},",
+            option_code(machine, default_action)
+        );
+        code.push_str("        },\n");
+    }
+
+    code.push_str("        _ => None,\n    }\n}\n");
+}
+
+fn option_code(machine: &Machine, action: Option<Action>) -> String {
+    match action {
+        Some(action) => format!("Some({})", machine.action_code(action)),
+        None => "None".to_string(),
+    }
+}
@@ -0,0 +1,775 @@
+//! Rust backend
+//!
+//! Writes a lexer module that behaves like `automata::interpreter`, with a `Token` enum holding
+//! every token of the machine, an `EntryPoint` enum, a `Lexer` iterator and a `lex_<entry>`
+//! function for each entry point. The module doesn't depend on anything, it can be written to a
+//! file and pulled in with `include!`.
+//!
+//! The transitions are written out in one of two ways:
+//!
+//! * `RustOutput::Match` writes a `match` on the state, then on the char
+//! * `RustOutput::Table` writes static tables read by a small loop. Chars are first mapped to
+//!   their class, see `automata::classes`, then the class is looked up in a transition table
+//!   compressed with row displacement, see `tables`. This keeps rustc fast on large machines
+//!
+//! States are numbered in the order of their names, followed by the states that are used without
+//! being defined.
+
+mod driver;
+mod matches;
+mod tables;
+
+pub use self::tables::TableSizes;
+
+use backend::{Backend, BackendError, BackendOutput, LexerIr, OutputFile};
+use automata::definitions::Alphabet;
+use automata::statements::*;
+use automata::Automata;
+use automata_core::string_interning::*;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// Words that can't name a token or an entry point as they are
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+    "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "abstract", "async", "await", "become", "box", "do", "dyn", "final", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// How the transitions are written out
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RustOutput {
+    /// A `match` on the state, then on the char
+    Match,
+    /// Compressed static tables read by a small loop
+    Table,
+}
+
+/// Generated Rust code
+#[derive(Debug, Clone)]
+pub struct RustCode {
+    pub code: String,
+    /// Sizes of the tables, for table output
+    pub table_sizes: Option<TableSizes>,
+}
+
+/// Generates a lexer module in Rust from an Automata
+#[derive(Debug)]
+pub struct RustGenerator<'automata> {
+    automata: &'automata Automata,
+    output: RustOutput,
+}
+
+impl<'automata> RustGenerator<'automata> {
+    /// Create a new RustGenerator, writing `match` expressions by default
+    pub fn new(automata: &'automata Automata) -> Self {
+        Self {
+            automata,
+            output: RustOutput::Match,
+        }
+    }
+
+    /// Set how the transitions are written out
+    pub fn with_output(mut self, output: RustOutput) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Write the lexer module
+    pub fn generate(&self) -> RustCode {
+        let machine = Machine::new(self.automata);
+        let mut code = String::new();
+
+        code.push_str("// Generated by snow_automata, do not edit\n");
+        machine.write_declarations(&mut code);
+
+        code.push_str(match self.automata.alphabet() {
+            Alphabet::Chars => driver::CHAR_INPUT,
+            Alphabet::Bytes => driver::BYTE_INPUT,
+        });
+        code.push_str(driver::DRIVER);
+
+        machine.write_keywords(&mut code);
+        machine.write_end_of_input(&mut code);
+
+        let table_sizes = match self.output {
+            RustOutput::Match => {
+                matches::write_transitions(&machine, &mut code);
+                None
+            }
+            RustOutput::Table => Some(tables::write_transitions(&machine, &mut code)),
+        };
+
+        RustCode { code, table_sizes }
+    }
+}
+
+/// `RustGenerator` as a backend, writing `<name>.rs`
+#[derive(Debug, Clone)]
+pub struct RustBackend {
+    output: RustOutput,
+}
+
+impl RustBackend {
+    /// Create a new RustBackend, writing `match` expressions by default
+    pub fn new() -> Self {
+        Self {
+            output: RustOutput::Match,
+        }
+    }
+
+    /// Set how the transitions are written out
+    pub fn with_output(mut self, output: RustOutput) -> Self {
+        self.output = output;
+        self
+    }
+}
+
+impl Default for RustBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for RustBackend {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn description(&self) -> &str {
+        "A lexer module in Rust"
+    }
+
+    fn options(&self) -> &[(&'static str, &'static str)] {
+        &[(
+            "output",
+            "`match` expressions or compressed `table`s, `match` by default",
+        )]
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), BackendError> {
+        if name != "output" {
+            return Err(BackendError::UnknownOption(name.to_string()));
+        }
+
+        self.output = match value {
+            "match" => RustOutput::Match,
+            "table" => RustOutput::Table,
+            _ => {
+                return Err(BackendError::InvalidOption {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    expected: "`match` or `table`".to_string(),
+                })
+            }
+        };
+
+        Ok(())
+    }
+
+    fn generate(&self, ir: &LexerIr) -> Result<BackendOutput, BackendError> {
+        let generated = RustGenerator::new(ir.automata)
+            .with_output(self.output)
+            .generate();
+
+        Ok(BackendOutput {
+            files: vec![OutputFile {
+                path: PathBuf::from(format!("{}.rs", ir.name)),
+                contents: generated.code.into_bytes(),
+            }],
+            notes: generated
+                .table_sizes
+                .iter()
+                .map(|table_sizes| table_sizes.to_string())
+                .collect(),
+        })
+    }
+}
+
+/// What a state does with an input, with states as numbers
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Action {
+    Goto(usize),
+    Return(InternedString, bool),
+    Error(InternedString, bool),
+    Push(usize),
+    Pop,
+    Mark(usize),
+    Rollback(usize),
+}
+
+/// The transitions of a machine by state and char class
+#[derive(Debug)]
+struct Machine<'automata> {
+    automata: &'automata Automata,
+    /// Defined states, then the ones used without being defined
+    state_names: Vec<InternedString>,
+    defined_states: usize,
+    /// Every token that can be returned, sorted by name
+    tokens: Vec<InternedString>,
+    class_count: usize,
+    /// Runs of chars in a class other than 0
+    class_ranges: Vec<(char, char, usize)>,
+    /// Action of each class in each state
+    actions: Vec<Vec<Option<Action>>>,
+    end_of_input: Vec<Option<Action>>,
+}
+
+impl<'automata> Machine<'automata> {
+    fn new(automata: &'automata Automata) -> Self {
+        let classes = automata.char_classes();
+        let representatives = classes.representatives();
+        let state_names = automata.states();
+
+        let mut machine = Machine {
+            automata,
+            defined_states: state_names.len(),
+            state_names,
+            tokens: Vec::new(),
+            class_count: classes.class_count() as usize,
+            class_ranges: classes
+                .ranges()
+                .into_iter()
+                .map(|(first, last, class)| (first, last, class as usize))
+                .collect(),
+            actions: Vec::new(),
+            end_of_input: Vec::new(),
+        };
+
+        for index in 0..machine.defined_states {
+            let state = machine.state_names[index];
+            let mut actions = Vec::new();
+
+            for &representative in &representatives {
+                let action = automata
+                    .unguarded_transition(state, representative)
+                    .map(|(destination, lookahead)| machine.action(destination, lookahead));
+                actions.push(action);
+            }
+
+            let end_of_input = automata
+                .end_of_input_transition(state)
+                .map(|destination| machine.action(destination, Lookahead::Keep));
+
+            machine.actions.push(actions);
+            machine.end_of_input.push(end_of_input);
+
+            if let Some(keyword_table) = automata.keyword_table(state) {
+                for &(_, token) in keyword_table.slots().iter().flatten() {
+                    machine.tokens.push(token);
+                }
+            }
+        }
+
+        let interner = automata.interner();
+        machine
+            .tokens
+            .sort_by(|&first, &second| interner.compare(first, second));
+        machine.tokens.dedup();
+        machine
+    }
+
+    /// The action for a destination, numbering the states it uses and collecting its tokens
+    fn action(&mut self, destination: Destination, lookahead: Lookahead) -> Action {
+        let consume = lookahead == Lookahead::Consume;
+
+        match destination {
+            Destination::State(state) => Action::Goto(self.state_number(state)),
+            Destination::Return(token) => {
+                self.tokens.push(token);
+                Action::Return(token, consume)
+            }
+            Destination::Error(message) => Action::Error(message, consume),
+            Destination::Push(mode) => Action::Push(self.state_number(mode)),
+            Destination::Pop => Action::Pop,
+            Destination::Mark(state) => Action::Mark(self.state_number(state)),
+            Destination::Rollback(state) => Action::Rollback(self.state_number(state)),
+        }
+    }
+
+    fn state_number(&mut self, state: InternedString) -> usize {
+        match self.state_names.iter().position(|&name| name == state) {
+            Some(number) => number,
+            None => {
+                self.state_names.push(state);
+                self.state_names.len() - 1
+            }
+        }
+    }
+
+    /// The code building an action
+    fn action_code(&self, action: Action) -> String {
+        match action {
+            Action::Goto(state) => format!("Action::Goto({})", state),
+            Action::Return(token, consume) => {
+                format!(
+                    "Action::Return(Token::{}, {})",
+                    self.identifier(token),
+                    consume
+                )
+            }
+            Action::Error(message, consume) => self
+                .automata
+                .interner()
+                .with_str(message, |message| {
+                    format!("Action::Error({:?}, {})", message, consume)
+                })
+                .unwrap(),
+            Action::Push(mode) => format!("Action::Push({})", mode),
+            Action::Pop => "Action::Pop".to_string(),
+            Action::Mark(state) => format!("Action::Mark({})", state),
+            Action::Rollback(state) => format!("Action::Rollback({})", state),
+        }
+    }
+
+    /// Tokens, entry points with their lexing functions, and state names
+    fn write_declarations(&self, code: &mut String) {
+        code.push_str("\n/// The tokens of the machine\n");
+        code.push_str("#[allow(non_camel_case_types)]\n");
+        code.push_str("#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]\n");
+        code.push_str("pub enum Token {\n");
+        for &token in &self.tokens {
+            let _ = writeln!(code, "    {},", self.identifier(token));
+        }
+        code.push_str("}\n");
+
+        let entry_points = self.automata.entry_points();
+
+        code.push_str("\n/// The states a lexer can start from\n");
+        code.push_str("#[allow(non_camel_case_types)]\n");
+        code.push_str("#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]\n");
+        code.push_str("pub enum EntryPoint {\n");
+        for &entry_point in entry_points {
+            let _ = writeln!(code, "    {},", self.identifier(entry_point));
+        }
+        code.push_str("}\n\n");
+
+        code.push_str("impl EntryPoint {\n");
+        code.push_str("    fn state(self) -> usize {\n");
+        code.push_str("        match self {\n");
+        for &entry_point in entry_points {
+            let number = self
+                .state_names
+                .iter()
+                .position(|&name| name == entry_point)
+                .unwrap();
+            let _ = writeln!(
+                code,
+                "            EntryPoint::{} => {},",
+                self.identifier(entry_point),
+                number
+            );
+        }
+        code.push_str("        }\n    }\n}\n");
+
+        for &entry_point in entry_points {
+            self.automata.interner().with_str(entry_point, |name| {
+                let _ = writeln!(
+                    code,
+                    "\n/// Lex some input starting from `{}`\n\
+                     pub fn lex_{}<'input>(input: &'input Input) -> Lexer<'input> {{\n    \
+                     Lexer::new(EntryPoint::{}, input)\n}}",
+                    name,
+                    name,
+                    self.identifier(entry_point)
+                );
+            });
+        }
+
+        code.push_str("\n/// Name of each state, defined states first\n");
+        let _ = writeln!(
+            code,
+            "const STATE_NAMES: [&str; {}] = [{}];",
+            self.state_names.len(),
+            self.state_names
+                .iter()
+                .map(|&name| {
+                    self.automata
+                        .interner()
+                        .with_str(name, |name| format!("{:?}", name))
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let _ = writeln!(
+            code,
+            "const DEFINED_STATES: usize = {};",
+            self.defined_states
+        );
+    }
+
+    /// The keyword tables of the states with keywords and their lookup
+    fn write_keywords(&self, code: &mut String) {
+        let keyword_states = (0..self.defined_states)
+            .filter(|&state| {
+                self.automata
+                    .keyword_table(self.state_names[state])
+                    .is_some()
+            })
+            .collect::<Vec<_>>();
+
+        if !keyword_states.is_empty() {
+            code.push_str(driver::KEYWORD_LOOKUP);
+            code.push_str(match self.automata.alphabet() {
+                Alphabet::Chars => driver::CHAR_KEYWORD_BYTES,
+                Alphabet::Bytes => driver::BYTE_KEYWORD_BYTES,
+            });
+        }
+
+        for &state in &keyword_states {
+            let keyword_table = self
+                .automata
+                .keyword_table(self.state_names[state])
+                .unwrap();
+
+            tables::write_array(
+                code,
+                &format!(
+                    "Seed of the second hash for each bucket of the keywords of state {}",
+                    state
+                ),
+                &format!("KEYWORD_DISPLACEMENTS_{}", state),
+                "u32",
+                &keyword_table
+                    .displacements()
+                    .iter()
+                    .map(|displacement| displacement.to_string())
+                    .collect::<Vec<_>>(),
+            );
+
+            let _ = writeln!(
+                code,
+                "\n/// Keyword text and token for each slot of state {}",
+                state
+            );
+            let _ = writeln!(
+                code,
+                "static KEYWORD_SLOTS_{}: [Option<(&Input, Token)>; {}] = [",
+                state,
+                keyword_table.slots().len()
+            );
+
+            for slot in keyword_table.slots() {
+                match *slot {
+                    Some((ref text, token)) => {
+                        let text = match self.automata.alphabet() {
+                            Alphabet::Chars => format!("{:?}", text),
+                            Alphabet::Bytes => byte_string(text),
+                        };
+
+                        let _ = writeln!(
+                            code,
+                            "    Some(({}, Token::{})),",
+                            text,
+                            self.identifier(token)
+                        );
+                    }
+                    None => code.push_str("    None,\n"),
+                }
+            }
+
+            code.push_str("];\n");
+        }
+
+        code.push_str("\n/// The keyword token for the text of a token returned by a state\n");
+        code.push_str("#[allow(unused_variables)]\n");
+        code.push_str("fn keyword(state: usize, text: &Input) -> Option<Token> {\n");
+        code.push_str("    match state {\n");
+
+        for &state in &keyword_states {
+            let name = self.state_names[state];
+
+            // States checking trailing contexts return tokens for the state the arm belongs to
+            let states = (0..self.defined_states)
+                .filter(|&other| self.automata.keyword_state(self.state_names[other]) == name)
+                .map(|other| other.to_string())
+                .collect::<Vec<_>>()
+                .join(" | ");
+
+            let _ = writeln!(
+                code,
+                "        {} => keyword_in(&KEYWORD_DISPLACEMENTS_{}, &KEYWORD_SLOTS_{}, text),",
+                states, state, state
+            );
+        }
+
+        code.push_str("        _ => None,\n    }\n}\n");
+    }
+
+    /// A Rust identifier for a token or state name
+    fn identifier(&self, name: InternedString) -> String {
+        self.automata
+            .interner()
+            .with_str(name, |name| {
+                if RUST_KEYWORDS.contains(&name) {
+                    format!("{}_", name)
+                } else {
+                    name.to_string()
+                }
+            })
+            .unwrap()
+    }
+
+    /// The `eof` arm of each state
+    fn write_end_of_input(&self, code: &mut String) {
+        code.push_str("\n/// What a state does once the input has ended\n");
+        code.push_str("fn end_of_input(state: usize) -> Option<Action> {\n");
+        code.push_str("    match state {\n");
+
+        for (state, action) in self.end_of_input.iter().enumerate() {
+            if let Some(action) = *action {
+                let _ = writeln!(
+                    code,
+                    "        {} => Some({}),",
+                    state,
+                    self.action_code(action)
+                );
+            }
+        }
+
+        code.push_str("        _ => None,\n    }\n}\n");
+    }
+}
+
+/// A byte string literal for text whose chars are bytes
+fn byte_string(text: &str) -> String {
+    let mut literal = String::from("b\"");
+
+    for chr in text.chars() {
+        let byte = chr as u32 as u8;
+
+        if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+            literal.push(chr);
+        } else {
+            let _ = write!(literal, "\\x{:02X}", byte);
+        }
+    }
+
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use automata::interpreter::*;
+    use automata_syntax::SyntaxParser;
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+
+    const MACHINE: &str = r#"
+        entry start;
+        entry braces;
+
+        state start {
+            'a'..'z' | 'é' | '_' => identifier
+            '0'..'9' => number
+            '"' => string
+            ' ' =>> return SPACE
+            '-' / '>' => arrow
+            '-' =>> return MINUS
+            '<' / "<-" =>> return LESS_BEFORE_ARROW
+            '<' =>> return LESS
+            '{' => push braces
+            "=>" =>> return FAT_ARROW
+            '=' =>> return EQUALS
+            '?' => missing
+        }
+
+        state arrow {
+            '>' =>> return ARROW
+        }
+
+        state braces {
+            '}' => pop
+            'a'..'z' =>> return INNER
+            '{' => push braces
+        }
+
+        state identifier {
+            'a'..'z' | 'é' | '_' | '0'..'9' => Self
+            _ => return IDENTIFIER
+            eof => return IDENTIFIER
+        }
+
+        state number {
+            '0'..'9' => Self
+            _ => return NUMBER
+            eof => return NUMBER
+        }
+
+        state string {
+            '"' =>> return STRING
+            '!' => error "bang in string"
+            _ => Self
+            eof => error "unterminated string"
+        }
+
+        keywords identifier {
+            "fn" => KW_FN,
+            "été" => KW_ETE
+        }
+    "#;
+
+    const INPUTS: &[&str] = &[
+        "",
+        "fn été étés x_1 42",
+        "a->b =>= = - >",
+        "\"text\" \"a!b\" \"open",
+        "{ab{c}d} }",
+        "{ab",
+        "? 12 ab",
+        "ab#",
+        "中",
+        "é-é",
+        "<<<-< <<",
+    ];
+
+    /// Lex every input with the interpreter, one line per input
+    fn interpret(automata: &Automata, bytes: bool) -> Vec<String> {
+        let interpreter = Interpreter::new(automata);
+
+        INPUTS
+            .iter()
+            .map(|input| {
+                let results: Vec<Result<Lexeme, LexError>> = if bytes {
+                    interpreter
+                        .lex_bytes(intern("start"), input.as_bytes())
+                        .unwrap()
+                        .collect()
+                } else {
+                    interpreter.lex(intern("start"), input).unwrap().collect()
+                };
+
+                results
+                    .into_iter()
+                    .map(|result| match result {
+                        Ok(lexeme) => intern_with_str(lexeme.token, |token| {
+                            format!("{}@{}..{}", token, lexeme.span.0, lexeme.span.1)
+                        })
+                        .unwrap(),
+                        Err(error) => {
+                            let kind = match error.kind {
+                                LexErrorKind::EmptyToken(token) => {
+                                    intern_with_str(token, |token| format!("EmptyToken({})", token))
+                                        .unwrap()
+                                }
+                                LexErrorKind::UndefinedState(state) => {
+                                    intern_with_str(state, |state| {
+                                        format!("UndefinedState({:?})", state)
+                                    })
+                                    .unwrap()
+                                }
+                                LexErrorKind::Custom(message) => {
+                                    intern_with_str(message, |message| {
+                                        format!("Custom({:?})", message)
+                                    })
+                                    .unwrap()
+                                }
+                                kind => format!("{:?}", kind),
+                            };
+
+                            format!("{}@{}..{}", kind, error.span.0, error.span.1)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    /// Compile generated code into a program lexing every input, and run it
+    fn run_generated(code: &str, name: &str, bytes: bool) -> Vec<String> {
+        let directory =
+            env::temp_dir().join(format!("snow_automata_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("lexer.rs"), code).unwrap();
+
+        let main = format!(
+            r#"
+            #[allow(dead_code)]
+            mod lexer {{
+                include!("lexer.rs");
+            }}
+
+            fn main() {{
+                let inputs: &[&str] = &{:?};
+
+                for input in inputs {{
+                    let results = lexer::lex_start(input{})
+                        .map(|result| match result {{
+                            Ok(lexeme) => format!("{{:?}}@{{}}..{{}}", lexeme.token, lexeme.span.0, lexeme.span.1),
+                            Err(error) => format!("{{:?}}@{{}}..{{}}", error.kind, error.span.0, error.span.1),
+                        }})
+                        .collect::<Vec<_>>();
+                    println!("{{}}", results.join(" "));
+                }}
+            }}
+            "#,
+            INPUTS,
+            if bytes { ".as_bytes()" } else { "" }
+        );
+        fs::write(directory.join("main.rs"), main).unwrap();
+
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = Command::new(rustc)
+            .current_dir(&directory)
+            .args([
+                "--edition",
+                "2018",
+                "-D",
+                "warnings",
+                "-o",
+                "lexer",
+                "main.rs",
+            ])
+            .status()
+            .unwrap();
+        assert!(status.success(), "generated code doesn't compile");
+
+        let output = Command::new(directory.join("lexer")).output().unwrap();
+        let _ = fs::remove_dir_all(&directory);
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn generated_lexer_test() {
+        let mut parser = SyntaxParser::new(MACHINE);
+        let automata = Automata::resolve_from(parser.parse());
+        let expected = interpret(&automata, false);
+
+        for &(output, name) in &[(RustOutput::Match, "match"), (RustOutput::Table, "table")] {
+            let generated = RustGenerator::new(&automata).with_output(output).generate();
+            assert_eq!(run_generated(&generated.code, name, false), expected);
+            assert_eq!(generated.table_sizes.is_some(), output == RustOutput::Table);
+
+            assert!(generated.code.contains("pub fn lex_start<'input>"));
+            assert!(generated.code.contains("pub fn lex_braces<'input>"));
+            assert!(generated.code.contains("static KEYWORD_DISPLACEMENTS_"));
+
+            if output == RustOutput::Match {
+                assert!(generated
+                    .code
+                    .contains("        // after reading `=` in state start\n"));
+            }
+        }
+
+        let byte_automata = automata.to_utf8_bytes().unwrap();
+        let generated = RustGenerator::new(&byte_automata)
+            .with_output(RustOutput::Table)
+            .generate();
+        assert_eq!(
+            run_generated(&generated.code, "bytes", true),
+            interpret(&byte_automata, true)
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! Transitions written as compressed static tables
+//!
+//! Every state has a row holding an entry per char class. An entry is the index of an action
+//! in `ENTRIES`, 0 meaning there is no transition. The rows are compressed with row
+//! displacement, as yacc and flex do: the most common entry of a row becomes its `DEFAULT`, and
+//! the other entries are laid out in `NEXT` starting at the row's `BASE`, rows sharing `NEXT` as
+//! long as they don't use the same slots. `CHECK` tells which row owns a slot:
+//!
+//! ```text
+//! index = BASE[state] + class
+//! entry = if CHECK[index] == state { NEXT[index] } else { DEFAULT[state] }
+//! ```
+
+use super::{Action, Machine};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+
+/// Size of an `ENTRIES` element on 64 bit targets
+const ENTRY_SIZE: usize = 24;
+
+/// Size of a `WIDE_CLASSES` element
+const WIDE_CLASS_SIZE: usize = 12;
+
+/// Sizes of the tables of a lexer, in bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableSizes {
+    /// Class of each char below 256
+    pub classes: usize,
+    /// Classes of the chars above 255
+    pub wide_classes: usize,
+    pub base: usize,
+    pub default: usize,
+    pub next: usize,
+    pub check: usize,
+    /// Actions the rows point to, counted at 24 bytes each
+    pub entries: usize,
+    /// Size of the rows without compression
+    pub uncompressed_rows: usize,
+}
+
+impl TableSizes {
+    /// Size of all the tables
+    pub fn total(&self) -> usize {
+        self.classes
+            + self.wide_classes
+            + self.base
+            + self.default
+            + self.next
+            + self.check
+            + self.entries
+    }
+}
+
+impl fmt::Display for TableSizes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "CLASSES: {} bytes", self.classes)?;
+        writeln!(f, "WIDE_CLASSES: {} bytes", self.wide_classes)?;
+        writeln!(f, "BASE: {} bytes", self.base)?;
+        writeln!(f, "DEFAULT: {} bytes", self.default)?;
+        writeln!(f, "NEXT: {} bytes", self.next)?;
+        writeln!(f, "CHECK: {} bytes", self.check)?;
+        writeln!(f, "ENTRIES: {} bytes", self.entries)?;
+        write!(
+            f,
+            "total: {} bytes, rows take {} bytes instead of {} uncompressed",
+            self.total(),
+            self.base + self.default + self.next + self.check,
+            self.uncompressed_rows
+        )
+    }
+}
+
+/// Rows laid out with row displacement
+#[derive(Debug, Default)]
+struct CompressedRows {
+    base: Vec<usize>,
+    default: Vec<usize>,
+    next: Vec<usize>,
+    /// Row owning each slot, `None` for free slots
+    check: Vec<Option<usize>>,
+}
+
+/// Lay rows of the same length out in shared arrays
+fn compress(rows: &[Vec<usize>]) -> CompressedRows {
+    let row_len = rows.first().map_or(0, |row| row.len());
+    let mut compressed = CompressedRows::default();
+    let mut sparse_rows = Vec::new();
+
+    for row in rows {
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for &entry in row {
+            *counts.entry(entry).or_insert(0) += 1;
+        }
+
+        let default = counts
+            .iter()
+            .max_by_key(|&(&entry, &count)| (count, usize::MAX - entry))
+            .map_or(0, |(&entry, _)| entry);
+
+        compressed.default.push(default);
+        sparse_rows.push(
+            row.iter()
+                .cloned()
+                .enumerate()
+                .filter(|&(_, entry)| entry != default)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    let mut order = (0..rows.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&row| usize::MAX - sparse_rows[row].len());
+
+    compressed.base = vec![0; rows.len()];
+
+    for row in order {
+        let sparse_row = &sparse_rows[row];
+        if sparse_row.is_empty() {
+            continue;
+        }
+
+        let base = (0..)
+            .find(|&base| {
+                sparse_row
+                    .iter()
+                    .all(|&(class, _)| !matches!(compressed.check.get(base + class), Some(Some(_))))
+            })
+            .unwrap();
+
+        let needed_len = base + row_len;
+        if compressed.check.len() < needed_len {
+            compressed.check.resize(needed_len, None);
+            compressed.next.resize(needed_len, 0);
+        }
+
+        for &(class, entry) in sparse_row {
+            compressed.check[base + class] = Some(row);
+            compressed.next[base + class] = entry;
+        }
+
+        compressed.base[row] = base;
+    }
+
+    let needed_len = compressed
+        .base
+        .iter()
+        .max()
+        .map_or(0, |&base| base + row_len);
+    if compressed.check.len() < needed_len {
+        compressed.check.resize(needed_len, None);
+        compressed.next.resize(needed_len, 0);
+    }
+
+    compressed
+}
+
+/// The smallest unsigned type holding a value, with its size
+fn unsigned_type(max_value: usize) -> (&'static str, usize) {
+    if max_value <= 0xFF {
+        ("u8", 1)
+    } else if max_value <= 0xFFFF {
+        ("u16", 2)
+    } else {
+        ("u32", 4)
+    }
+}
+
+pub(crate) fn write_array(
+    code: &mut String,
+    doc: &str,
+    name: &str,
+    element_type: &str,
+    values: &[String],
+) {
+    let _ = writeln!(code, "\n/// {}", doc);
+    let _ = write!(
+        code,
+        "static {}: [{}; {}] = [",
+        name,
+        element_type,
+        values.len()
+    );
+
+    for (index, value) in values.iter().enumerate() {
+        if index % 16 == 0 {
+            code.push_str("\n    ");
+        } else {
+            code.push(' ');
+        }
+
+        code.push_str(value);
+        code.push(',');
+    }
+
+    code.push_str("\n];\n");
+}
+
+/// Write the tables and the `transition` function reading them
+pub(crate) fn write_transitions(machine: &Machine, code: &mut String) -> TableSizes {
+    let mut entries = Vec::new();
+    let mut action_entries: HashMap<Action, usize> = HashMap::new();
+    let mut rows = Vec::new();
+
+    for state in 0..machine.defined_states {
+        let mut row = Vec::new();
+
+        for class in 0..machine.class_count {
+            let entry = match machine.actions[state][class] {
+                Some(action) => *action_entries.entry(action).or_insert_with(|| {
+                    entries.push(machine.action_code(action));
+                    entries.len()
+                }),
+                None => 0,
+            };
+
+            row.push(entry);
+        }
+
+        rows.push(row);
+    }
+
+    let compressed = compress(&rows);
+
+    let (class_type, class_size) = unsigned_type(machine.class_count);
+    let (entry_type, entry_size) = unsigned_type(entries.len());
+    let (base_type, base_size) = unsigned_type(compressed.base.iter().cloned().max().unwrap_or(0));
+    let (check_type, check_size) = unsigned_type(machine.defined_states);
+
+    let mut byte_classes = vec![0; 256];
+    let mut wide_classes = Vec::new();
+
+    for &(first, last, class) in &machine.class_ranges {
+        for chr in first as u32..=last as u32 {
+            if chr < 256 {
+                byte_classes[chr as usize] = class;
+            }
+        }
+
+        if last as u32 >= 256 {
+            let first = ::std::cmp::max(first, '\u{100}');
+            wide_classes.push(format!("({:?}, {:?}, {})", first, last, class));
+        }
+    }
+
+    write_array(
+        code,
+        "Class of each char below 256",
+        "CLASSES",
+        class_type,
+        &byte_classes
+            .iter()
+            .map(|class| class.to_string())
+            .collect::<Vec<_>>(),
+    );
+    write_array(
+        code,
+        "Classes of the chars above 255 that aren't in class 0, as sorted runs",
+        "WIDE_CLASSES",
+        &format!("(char, char, {})", class_type),
+        &wide_classes,
+    );
+    write_array(
+        code,
+        "Start of the row of each state in NEXT and CHECK",
+        "BASE",
+        base_type,
+        &compressed
+            .base
+            .iter()
+            .map(|base| base.to_string())
+            .collect::<Vec<_>>(),
+    );
+    write_array(
+        code,
+        "Entry of the classes a state has no slot for",
+        "DEFAULT",
+        entry_type,
+        &compressed
+            .default
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect::<Vec<_>>(),
+    );
+    write_array(
+        code,
+        "Entries of the rows",
+        "NEXT",
+        entry_type,
+        &compressed
+            .next
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect::<Vec<_>>(),
+    );
+    write_array(
+        code,
+        "State owning each slot of NEXT",
+        "CHECK",
+        check_type,
+        &compressed
+            .check
+            .iter()
+            .map(|owner| owner.unwrap_or(machine.defined_states).to_string())
+            .collect::<Vec<_>>(),
+    );
+    write_array(
+        code,
+        "Actions, entry n being at n - 1",
+        "ENTRIES",
+        "Action",
+        &entries,
+    );
+
+    code.push_str(TABLE_LOOKUP);
+
+    TableSizes {
+        classes: 256 * class_size,
+        wide_classes: wide_classes.len() * WIDE_CLASS_SIZE,
+        base: compressed.base.len() * base_size,
+        default: compressed.default.len() * entry_size,
+        next: compressed.next.len() * entry_size,
+        check: compressed.check.len() * check_size,
+        entries: entries.len() * ENTRY_SIZE,
+        uncompressed_rows: machine.defined_states * machine.class_count * entry_size,
+    }
+}
+
+/// Reading the tables
+const TABLE_LOOKUP: &str = r#"
+fn class_of(chr: char) -> usize {
+    if (chr as u32) < 256 {
+        return CLASSES[chr as usize] as usize;
+    }
+
+    let found = WIDE_CLASSES.binary_search_by(|&(first, last, _)| {
+        if last < chr {
+            ::std::cmp::Ordering::Less
+        } else if first > chr {
+            ::std::cmp::Ordering::Greater
+        } else {
+            ::std::cmp::Ordering::Equal
+        }
+    });
+
+    match found {
+        Ok(index) => WIDE_CLASSES[index].2 as usize,
+        Err(_) => 0,
+    }
+}
+
+/// What a state does with a char
+fn transition(state: usize, chr: char) -> Option<Action> {
+    let index = BASE[state] as usize + class_of(chr);
+
+    let entry = if CHECK[index] as usize == state {
+        NEXT[index]
+    } else {
+        DEFAULT[state]
+    };
+
+    match entry {
+        0 => None,
+        entry => Some(ENTRIES[entry as usize - 1]),
+    }
+}
+"#;
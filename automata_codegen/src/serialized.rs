@@ -0,0 +1,93 @@
+//! Saved automata, for the interpreter to load at runtime, see `automata::serialization`
+
+use backend::{Backend, BackendError, BackendOutput, LexerIr, OutputFile};
+use std::path::PathBuf;
+
+/// How a machine is saved
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SerializedFormat {
+    /// `<name>.json`
+    Json,
+    /// `<name>.bin`, smaller and faster to load
+    Binary,
+    /// `<name>.txt`, see `automata::text_format`
+    Text,
+}
+
+/// Writes the resolved machine itself instead of code running it
+#[derive(Debug, Clone)]
+pub struct SerializedBackend {
+    format: SerializedFormat,
+}
+
+impl SerializedBackend {
+    /// Create a new SerializedBackend, writing JSON by default
+    pub fn new() -> Self {
+        Self {
+            format: SerializedFormat::Json,
+        }
+    }
+
+    /// Set how the machine is saved
+    pub fn with_format(mut self, format: SerializedFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Default for SerializedBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for SerializedBackend {
+    fn name(&self) -> &str {
+        "serialized"
+    }
+
+    fn description(&self) -> &str {
+        "The resolved automata, for the interpreter to load"
+    }
+
+    fn options(&self) -> &[(&'static str, &'static str)] {
+        &[("format", "`json`, `binary` or `text`, `json` by default")]
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), BackendError> {
+        if name != "format" {
+            return Err(BackendError::UnknownOption(name.to_string()));
+        }
+
+        self.format = match value {
+            "json" => SerializedFormat::Json,
+            "binary" => SerializedFormat::Binary,
+            "text" => SerializedFormat::Text,
+            _ => {
+                return Err(BackendError::InvalidOption {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                    expected: "`json`, `binary` or `text`".to_string(),
+                })
+            }
+        };
+
+        Ok(())
+    }
+
+    fn generate(&self, ir: &LexerIr) -> Result<BackendOutput, BackendError> {
+        let (extension, contents) = match self.format {
+            SerializedFormat::Json => ("json", ir.automata.to_json().map(String::into_bytes)),
+            SerializedFormat::Binary => ("bin", ir.automata.to_binary()),
+            SerializedFormat::Text => ("txt", Ok(ir.automata.to_text().into_bytes())),
+        };
+
+        Ok(BackendOutput {
+            files: vec![OutputFile {
+                path: PathBuf::from(format!("{}.{}", ir.name, extension)),
+                contents: contents.map_err(|error| BackendError::Unsupported(error.to_string()))?,
+            }],
+            notes: Vec::new(),
+        })
+    }
+}
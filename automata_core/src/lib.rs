@@ -1,4 +1,5 @@
 #[macro_use]
 extern crate lazy_static;
+extern crate serde;
 
 pub mod string_interning;
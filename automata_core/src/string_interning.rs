@@ -1,94 +1,488 @@
-use std::collections::HashMap;
+//! Interning of state names, tokens and other strings of a machine
+//!
+//! Strings are interned in an `Interner`, which owns them and frees them once it is dropped. The
+//! parser, the builder and the resolved Automata are given the interner of a pipeline and carry it
+//! along, so the writers read names back from it. `Interner::global` is kept for the whole
+//! process, which is convenient for tools that only ever read a few files, and is what they use
+//! unless given another one.
+//!
+//! An `InternedString` is a single `u32`, the index of its string, so the maps and tables of a
+//! machine stay small. It doesn't record the interner it comes from: read from another interner,
+//! it stands for the string at the same index there, or for nothing past its end. This is why the
+//! interner travels with the definitions and the Automata holding its InternedStrings.
+//! InternedStrings are ordered by index, which is cheap and the same on every thread but follows
+//! the order strings were interned in: output is sorted by name with `Interner::compare` instead.
+//! The words of the automata language are predefined: every interner gives them the first
+//! indexes, available as constants such as `KEYWORD_SELF`.
+//!
+//! `intern`, `intern_with_str`, `intern_compare`, and the `Debug` and serde traits of
+//! `InternedString` have no interner to be given, and use the one entered on the thread with
+//! `Interner::scope`, or the global one outside of any scope.
+//!
+//! Interned strings are kept in chunks that are never moved or freed before the interner, and are
+//! found through hash tables of atomic indexes. Interning a string that is already known, which is
+//! what parsers mostly do, reading a string back or comparing two take no lock and allocate
+//! nothing. Only adding a string takes a lock, which the threads adding strings share.
+
+use std::collections::hash_map::RandomState;
 use std::cell::RefCell;
-use std::sync::Mutex;
+use std::convert::TryFrom;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering as AtomicOrdering};
 use std::fmt;
+use std::cmp::Ordering;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::Error;
+
+/// Words interned the same way by every interner, in the order of their constants
+const PREDEFINED: &[&str] = &[
+    "Self",
+    "start",
+    "return",
+    "state",
+    "entry",
+    "push",
+    "pop",
+    "eof",
+    "error",
+    "keywords",
+    "priority",
+    "case_insensitive",
+];
+
+/// `Self`, the state an arm is declared in
+pub const KEYWORD_SELF: InternedString = InternedString::predefined(0);
+/// `start`, the entry point when none is declared
+pub const KEYWORD_START: InternedString = InternedString::predefined(1);
+pub const KEYWORD_RETURN: InternedString = InternedString::predefined(2);
+pub const KEYWORD_STATE: InternedString = InternedString::predefined(3);
+pub const KEYWORD_ENTRY: InternedString = InternedString::predefined(4);
+pub const KEYWORD_PUSH: InternedString = InternedString::predefined(5);
+pub const KEYWORD_POP: InternedString = InternedString::predefined(6);
+pub const KEYWORD_EOF: InternedString = InternedString::predefined(7);
+pub const KEYWORD_ERROR: InternedString = InternedString::predefined(8);
+pub const KEYWORD_KEYWORDS: InternedString = InternedString::predefined(9);
+pub const KEYWORD_PRIORITY: InternedString = InternedString::predefined(10);
+pub const KEYWORD_CASE_INSENSITIVE: InternedString = InternedString::predefined(11);
+
+/// The InternedString of a predefined word
+fn predefined_word(raw_str: &str) -> Option<InternedString> {
+    Some(match raw_str {
+        "Self" => KEYWORD_SELF,
+        "start" => KEYWORD_START,
+        "return" => KEYWORD_RETURN,
+        "state" => KEYWORD_STATE,
+        "entry" => KEYWORD_ENTRY,
+        "push" => KEYWORD_PUSH,
+        "pop" => KEYWORD_POP,
+        "eof" => KEYWORD_EOF,
+        "error" => KEYWORD_ERROR,
+        "keywords" => KEYWORD_KEYWORDS,
+        "priority" => KEYWORD_PRIORITY,
+        "case_insensitive" => KEYWORD_CASE_INSENSITIVE,
+        _ => return None,
+    })
+}
+
+/// Number of strings in the first chunk of an interner, each chunk being twice as large as the
+/// one before
+const FIRST_CHUNK_SIZE: usize = 32;
+
+/// Number of slots in the first hash table of an interner, each table being twice as large as the
+/// one before
+const FIRST_TABLE_SIZE: usize = 64;
+
+/// Chunks and tables an interner can have, enough for every `u32` index
+const MAX_CHUNKS: usize = 32;
+
+/// Ids are never reused, so interners that aren't clones of each other are never equal
+static NEXT_INTERNER_ID: AtomicU32 = AtomicU32::new(0);
 
 lazy_static! {
     static ref GLOBAL_INTERNER: Interner = { Interner::new() };
 }
 
+thread_local! {
+    /// The interner entered on this thread, if any
+    static CURRENT_INTERNER: RefCell<Option<Interner>> = const { RefCell::new(None) };
+}
+
 /// Intern a string, giving back a InternedString
 pub fn intern<T: AsRef<str>>(string_to_intern: T) -> InternedString {
-    GLOBAL_INTERNER.intern(string_to_intern)
+    with_current_interner(|interner| interner.intern(string_to_intern))
+}
+
+/// Intern the name of something generated, such as an intermediate state
+/// It never equals the InternedString of an ordinary string with the same text
+pub fn intern_generated<T: AsRef<str>>(string_to_intern: T) -> InternedString {
+    with_current_interner(|interner| interner.intern_generated(string_to_intern))
+}
+
+/// Run something with the str of an InternedString, without copying it
+/// None if it comes from an interner that isn't the current one
+pub fn intern_with_str<R, F: FnOnce(&str) -> R>(interned_str: InternedString, f: F) -> Option<R> {
+    with_current_interner(|interner| interner.with_str(interned_str, f))
+}
+
+/// Compare the Strings of two InternedStrings, as `Interner::compare` does in the current interner
+pub fn intern_compare(first: InternedString, second: InternedString) -> Ordering {
+    with_current_interner(|interner| interner.compare(first, second))
+}
+
+/// Take the next id of a counter, panicking instead of wrapping around to the ids given before
+fn take_id(counter: &AtomicU32) -> u32 {
+    counter
+        .fetch_update(AtomicOrdering::Relaxed, AtomicOrdering::Relaxed, |id| {
+            id.checked_add(1)
+        })
+        .expect("Every interner id is taken")
 }
 
-/// get the String for an InternedString
-pub fn intern_get_str(interned_str: InternedString) -> Option<String> {
-    GLOBAL_INTERNER.get(interned_str)
+/// Run something with the interner entered on this thread, or the global one
+fn with_current_interner<R, F: FnOnce(&Interner) -> R>(f: F) -> R {
+    CURRENT_INTERNER.with(|current| match *current.borrow() {
+        Some(ref interner) => f(interner),
+        None => f(&GLOBAL_INTERNER),
+    })
 }
 
 /// Interns strings, transforming them from String's to InternedStrings and back the other way around
-struct Interner {
-    intern_map: Mutex<RefCell<HashMap<String, InternedString>>>,
-    strings: Mutex<RefCell<Vec<String>>>,
+/// Clones share the same strings, which are freed when the last clone is dropped
+#[derive(Clone)]
+pub struct Interner {
+    id: u32,
+    strings: Arc<InternedStrings>,
+}
+
+/// Strings of a chunk, each written once along with whether it was interned as a generated one
+type Chunk = Box<[OnceLock<(Box<str>, bool)>]>;
+
+/// Strings are written once and never moved, so they are read without a lock
+#[derive(Default)]
+struct InternedStrings {
+    /// Each string by index past the predefined words. Chunk `n` has `FIRST_CHUNK_SIZE << n`
+    chunks: [OnceLock<Chunk>; MAX_CHUNKS],
+    /// Number of strings, counted once they are written
+    len: AtomicUsize,
+    /// Open addressing tables of the values of the strings, 0 being an empty slot. Table `n` has
+    /// `FIRST_TABLE_SIZE << n` slots and is at most half full. The older tables are kept for the
+    /// threads still looking into them
+    tables: [OnceLock<Box<[AtomicU32]>>; MAX_CHUNKS],
+    /// The table strings are added to
+    table: AtomicUsize,
+    hasher: RandomState,
+    /// Taken to add a string
+    writer: Mutex<()>,
 }
 
-/// An Interned String
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+/// An Interned String, the index of a string in the interner it comes from
+/// Ordered by the order strings were interned in, predefined words first
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct InternedString {
-    value: usize,
+    value: u32,
+}
+
+impl InternedString {
+    const fn predefined(value: u32) -> Self {
+        InternedString { value }
+    }
+
+    /// Whether it was interned with `intern_generated`, in the interner entered on this thread
+    pub fn is_generated(&self) -> bool {
+        with_current_interner(|interner| interner.is_generated(*self))
+    }
 }
 
 impl fmt::Debug for InternedString {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(string) = intern_get_str(*self) {
-            write!(f, "{:?}", string)
-        } else {
-            write!(f, "Invalid intern string {:?}", *self)
+        let written = with_current_interner(|interner| {
+            interner.with_entry(*self, |string, generated| {
+                if generated {
+                    write!(f, "${:?}", string)
+                } else {
+                    write!(f, "{:?}", string)
+                }
+            })
+        });
+
+        match written {
+            Some(result) => result,
+            None => write!(f, "Invalid intern string {}", self.value),
         }
     }
 }
 
+/// Written as the string it stands for, as the numbers depend on the order strings are interned in
+/// Generated strings start with `$`, and ordinary strings starting with `$` get a second one
+impl Serialize for InternedString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let serialized = with_current_interner(|interner| {
+            interner.with_entry(*self, |string, generated| {
+                if generated || string.starts_with('$') {
+                    serializer.serialize_str(&format!("${}", string))
+                } else {
+                    serializer.serialize_str(string)
+                }
+            })
+        });
+
+        serialized.unwrap_or_else(|| Err(S::Error::custom(format!("{:?}", self))))
+    }
+}
+
+impl<'de> Deserialize<'de> for InternedString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|string| match string.strip_prefix('$') {
+            Some(name) if name.starts_with('$') => intern(name),
+            Some(name) => intern_generated(name),
+            None => intern(&string),
+        })
+    }
+}
+
 impl Interner {
-    /// Create a new interned String
-    fn new() -> Self {
+    /// Create a new interner, with only the predefined words
+    ///
+    /// # Panics
+    ///
+    /// If every interner id was taken, after about four billion interners
+    pub fn new() -> Self {
         Interner {
-            intern_map: Mutex::new(RefCell::new(HashMap::new())),
-            strings: Mutex::new(RefCell::new(Vec::new())),
+            id: take_id(&NEXT_INTERNER_ID),
+            strings: Arc::new(InternedStrings::default()),
         }
     }
 
-    /// Intern a String / str, giving back an InternedString
-    fn intern<T: AsRef<str>>(&self, raw_str: T) -> InternedString {
-        let raw_str = raw_str.as_ref().to_string();
+    /// The interner kept for the whole process, used unless another one is given
+    pub fn global() -> Self {
+        GLOBAL_INTERNER.clone()
+    }
+
+    /// Make this interner the one `intern`, `intern_with_str` and InternedStrings use on this
+    /// thread while running `f`. The interner entered before is restored afterwards
+    pub fn scope<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        /// Restores the previous interner, even when `f` panics
+        struct Restore(Option<Interner>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                CURRENT_INTERNER.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+
+        let previous = CURRENT_INTERNER.with(|current| current.borrow_mut().replace(self.clone()));
+        let _restore = Restore(previous);
+
+        f()
+    }
 
-        let intern_map_lock = self.intern_map.lock().unwrap();
-        let mut intern_map = intern_map_lock.borrow_mut();
+    /// Intern a String / str, giving back an InternedString
+    pub fn intern<T: AsRef<str>>(&self, raw_str: T) -> InternedString {
+        let raw_str = raw_str.as_ref();
 
-        if let Some(&interned_string) = intern_map.get(&raw_str) {
-            return interned_string;
+        if let Some(word) = predefined_word(raw_str) {
+            return word;
         }
 
-        let strings_lock = self.strings.lock().unwrap();
-        let mut strings = strings_lock.borrow_mut();
+        self.intern_kind(raw_str, false)
+    }
+
+    /// Intern a generated String / str, apart from the ordinary ones
+    pub fn intern_generated<T: AsRef<str>>(&self, raw_str: T) -> InternedString {
+        self.intern_kind(raw_str.as_ref(), true)
+    }
 
-        let interned_string = InternedString {
-            value: strings.len(),
+    /// Look the string up without a lock, only taking the writer's lock when it's new
+    fn intern_kind(&self, raw_str: &str, generated: bool) -> InternedString {
+        let strings = &*self.strings;
+        let hash = strings.hasher.hash_one((raw_str, generated));
+
+        let value = match strings.find(raw_str, generated, hash) {
+            Some(value) => value,
+            None => strings.add(raw_str, generated, hash),
         };
 
-        intern_map.insert(raw_str.clone(), interned_string);
+        InternedString { value }
+    }
+
+    /// Run something with the str of an InternedString, without copying it
+    /// None if this interner has no string at its index
+    pub fn with_str<R, F: FnOnce(&str) -> R>(
+        &self,
+        interned_string: InternedString,
+        f: F,
+    ) -> Option<R> {
+        self.with_entry(interned_string, |string, _| f(string))
+    }
+
+    /// Whether an InternedString was interned as a generated one
+    pub fn is_generated(&self, interned_string: InternedString) -> bool {
+        self.with_entry(interned_string, |_, generated| generated)
+            .unwrap_or(false)
+    }
+
+    /// Number of strings interned, predefined words aside
+    pub fn len(&self) -> usize {
+        self.strings.len.load(AtomicOrdering::Acquire)
+    }
+
+    /// Whether nothing was interned besides the predefined words
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run something with the string of an InternedString and whether it is generated
+    fn with_entry<R, F: FnOnce(&str, bool) -> R>(
+        &self,
+        interned_string: InternedString,
+        f: F,
+    ) -> Option<R> {
+        self.strings
+            .entry(interned_string.value)
+            .map(|(string, generated)| f(string, generated))
+    }
+
+    /// Compare the Strings of two InternedStrings, then their kinds, ordinary strings coming
+    /// before generated ones with the same text. This is how output is sorted, so it doesn't
+    /// depend on the order strings were interned in
+    pub fn compare(&self, first: InternedString, second: InternedString) -> Ordering {
+        self.strings
+            .entry(first.value)
+            .cmp(&self.strings.entry(second.value))
+    }
+}
+
+impl InternedStrings {
+    /// The string of a value and whether it is generated
+    /// None until the string is written
+    fn entry(&self, value: u32) -> Option<(&str, bool)> {
+        let index = value as usize;
+
+        if let Some(&word) = PREDEFINED.get(index) {
+            return Some((word, false));
+        }
+
+        let (chunk, offset) = chunk_of(index - PREDEFINED.len());
+        self.chunks
+            .get(chunk)?
+            .get()?
+            .get(offset)?
+            .get()
+            .map(|&(ref string, generated)| (&**string, generated))
+    }
 
-        strings.push(raw_str);
+    /// The value of a string, if it was interned
+    /// A string added while looking may be missed, adding it again finds it under the lock
+    fn find(&self, raw_str: &str, generated: bool, hash: u64) -> Option<u32> {
+        let table = self.tables[self.table.load(AtomicOrdering::Acquire)].get()?;
+        let mut slot = hash as usize & (table.len() - 1);
 
-        interned_string
+        loop {
+            match table[slot].load(AtomicOrdering::Acquire) {
+                0 => return None,
+                value if self.entry(value) == Some((raw_str, generated)) => return Some(value),
+                _ => slot = (slot + 1) & (table.len() - 1),
+            }
+        }
     }
 
-    /// Get a String back from an InternedString
-    fn get(&self, interned_string: InternedString) -> Option<String> {
-        let strings_lock = self.strings.lock().unwrap();
-        let strings = strings_lock.borrow();
+    /// Add a string, unless another thread added it first, and give back its value
+    ///
+    /// # Panics
+    ///
+    /// If every `u32` value is taken
+    fn add(&self, raw_str: &str, generated: bool, hash: u64) -> u32 {
+        let _writer = self.writer.lock().unwrap();
+
+        if let Some(value) = self.find(raw_str, generated, hash) {
+            return value;
+        }
+
+        let index = self.len.load(AtomicOrdering::Relaxed);
+        let value =
+            u32::try_from(PREDEFINED.len() + index).expect("Every interned string index is taken");
+
+        let (chunk, offset) = chunk_of(index);
+        let strings = self.chunks[chunk].get_or_init(|| {
+            (0..FIRST_CHUNK_SIZE << chunk)
+                .map(|_| OnceLock::new())
+                .collect()
+        });
+        let _ = strings[offset].set((raw_str.into(), generated));
+        self.len.store(index + 1, AtomicOrdering::Release);
+
+        let table_index = self.table.load(AtomicOrdering::Relaxed);
+        let table = self.tables[table_index].get_or_init(|| empty_table(FIRST_TABLE_SIZE));
+
+        if (index + 1) * 2 > table.len() {
+            let larger = empty_table(table.len() * 2);
 
-        if let Some(string) = strings.get(interned_string.value) {
-            return Some(string.clone());
+            for value in PREDEFINED.len() as u32..=value {
+                let (string, generated) = self.entry(value).unwrap();
+                insert(&larger, self.hasher.hash_one((string, generated)), value);
+            }
+
+            let _ = self.tables[table_index + 1].set(larger);
+            self.table.store(table_index + 1, AtomicOrdering::Release);
+        } else {
+            insert(table, hash, value);
         }
 
-        None
+        value
+    }
+}
+
+/// The chunk of a string index, and the string's offset in it
+fn chunk_of(index: usize) -> (usize, usize) {
+    let chunk = (index / FIRST_CHUNK_SIZE + 1).ilog2() as usize;
+    (chunk, index - FIRST_CHUNK_SIZE * ((1 << chunk) - 1))
+}
+
+fn empty_table(size: usize) -> Box<[AtomicU32]> {
+    (0..size).map(|_| AtomicU32::new(0)).collect()
+}
+
+/// Put a value in the first empty slot from its hash
+fn insert(table: &[AtomicU32], hash: u64, value: u32) {
+    let mut slot = hash as usize & (table.len() - 1);
+
+    while table[slot].load(AtomicOrdering::Relaxed) != 0 {
+        slot = (slot + 1) & (table.len() - 1);
+    }
+
+    table[slot].store(value, AtomicOrdering::Release);
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Interners are the same when they are clones of each other
+impl PartialEq for Interner {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Interner {}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("id", &self.id)
+            .field("len", &self.len())
+            .finish()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::thread;
 
     #[test]
     fn intern_test() {
@@ -100,7 +494,144 @@ mod tests {
         assert_ne!(str1, str2);
         assert_ne!(str2, str1_copy);
 
-        assert_eq!(intern_get_str(str1).unwrap(), "test1")
+        assert_eq!(intern_with_str(str1, str::to_string).unwrap(), "test1");
+
+        let interner = Interner::global();
+        let mut strings = vec![intern("test2"), str2, str1];
+        strings.sort_by(|&first, &second| interner.compare(first, second));
+        assert_eq!(strings, vec![str1, str2, intern("test2")]);
+
+        let generated = intern_generated("test1");
+        assert_ne!(generated, str1);
+        assert_eq!(generated, intern_generated("test1"));
+        assert!(generated.is_generated() && !str1.is_generated());
+        assert_eq!(intern_with_str(generated, str::to_string).unwrap(), "test1");
+        assert_eq!(format!("{:?}", generated), "$\"test1\"");
+        assert_eq!(interner.compare(str1, generated), Ordering::Less);
+        assert_eq!(interner.compare(generated, str2), Ordering::Less);
+        assert_eq!(intern_with_str(str2, |string| string.len()), Some(6));
+        assert_eq!(intern_compare(str2, str1), Ordering::Greater);
+    }
+
+    #[test]
+    fn predefined_test() {
+        let keywords = [
+            KEYWORD_SELF,
+            KEYWORD_START,
+            KEYWORD_RETURN,
+            KEYWORD_STATE,
+            KEYWORD_ENTRY,
+            KEYWORD_PUSH,
+            KEYWORD_POP,
+            KEYWORD_EOF,
+            KEYWORD_ERROR,
+            KEYWORD_KEYWORDS,
+            KEYWORD_PRIORITY,
+            KEYWORD_CASE_INSENSITIVE,
+        ];
+
+        for (&keyword, &word) in keywords.iter().zip(PREDEFINED) {
+            assert_eq!(intern(word), keyword);
+            assert_eq!(Interner::new().intern(word), keyword);
+            assert_eq!(intern_with_str(keyword, str::to_string).unwrap(), word);
+        }
+
+        assert_eq!(keywords.len(), PREDEFINED.len());
+        assert_eq!(::std::mem::size_of::<InternedString>(), 4);
+    }
+
+    #[test]
+    fn interner_growth_test() {
+        assert_eq!(chunk_of(0), (0, 0));
+        assert_eq!(chunk_of(FIRST_CHUNK_SIZE - 1), (0, FIRST_CHUNK_SIZE - 1));
+        assert_eq!(chunk_of(FIRST_CHUNK_SIZE), (1, 0));
+        assert_eq!(chunk_of(FIRST_CHUNK_SIZE * 3), (2, 0));
+
+        let interner = Interner::new();
+        let names = (0..5000)
+            .map(|index| format!("name_{}", index))
+            .collect::<Vec<_>>();
+        let interned = names
+            .iter()
+            .map(|name| interner.intern(name))
+            .collect::<Vec<_>>();
+
+        assert_eq!(interner.len(), names.len());
+        for (name, &interned_string) in names.iter().zip(&interned) {
+            assert_eq!(interner.intern(name), interned_string);
+            assert_eq!(
+                interner.with_str(interned_string, str::to_string).unwrap(),
+                *name
+            );
+        }
+        assert_ne!(interner.intern_generated(&names[0]), interned[0]);
     }
 
+    #[test]
+    fn interner_threads_test() {
+        let interner = Interner::new();
+        let threads = (0..4)
+            .map(|_| {
+                let interner = interner.clone();
+                thread::spawn(move || {
+                    (0..1000)
+                        .map(|index| interner.intern(format!("name_{}", index)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let interned = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(interned.iter().all(|strings| *strings == interned[0]));
+        assert_eq!(interner.len(), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Every interner id is taken")]
+    fn interner_id_test() {
+        let counter = AtomicU32::new(u32::MAX - 1);
+        assert_eq!(take_id(&counter), u32::MAX - 1);
+
+        take_id(&counter);
+    }
+
+    #[test]
+    fn interner_scope_test() {
+        let global = intern("scoped");
+        let interner = Interner::new();
+
+        let scoped = interner.scope(|| {
+            let scoped = intern("scoped");
+            assert_eq!(intern_with_str(scoped, str::to_string).unwrap(), "scoped");
+            assert_eq!(intern("Self"), interner.intern("Self"));
+
+            let inner = Interner::new();
+            inner.scope(|| {
+                assert_eq!(intern("inner"), scoped);
+                assert_eq!(intern_with_str(scoped, str::to_string).unwrap(), "inner");
+            });
+            assert_eq!(inner.len(), 1);
+
+            scoped
+        });
+
+        assert_eq!(interner.len(), 1);
+        assert_eq!(interner.with_str(scoped, str::to_string).unwrap(), "scoped");
+        assert_eq!(intern_with_str(global, str::to_string).unwrap(), "scoped");
+        assert_eq!(intern("Self"), interner.intern("Self"));
+        assert_eq!(
+            intern_with_str(intern("Self"), str::to_string).unwrap(),
+            "Self"
+        );
+
+        // A fresh interner has no string past the predefined words
+        let empty = Interner::new();
+        assert_eq!(empty.with_str(scoped, str::to_string), None);
+        assert!(empty
+            .scope(|| format!("{:?}", scoped))
+            .starts_with("Invalid intern string"));
+    }
 }
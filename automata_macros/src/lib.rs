@@ -0,0 +1,154 @@
+//! `automata!`, a lexer generated at compile time from automata written inline
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate automata_macros;
+//!
+//! mod lexer {
+//!     automata! {
+//!         state start {
+//!             'a'..'z' => identifier
+//!             ' ' =>> return SPACE
+//!         }
+//!
+//!         state identifier {
+//!             'a'..'z' => Self
+//!             _ => return IDENTIFIER
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! The macro expands to the module written by `automata_codegen::rust` with `match` output,
+//! so `lexer::lex_start("abc")` iterates over the tokens of `abc`.
+//! Syntax errors are reported on the tokens of the macro input they are about.
+//!
+//! Rust 2021 reserves prefixes on literals, so case insensitive literals are written with a
+//! space there, as in `i "select"` or `i 'a'..'z'`.
+
+extern crate automata;
+extern crate automata_codegen;
+extern crate automata_core;
+extern crate automata_syntax;
+extern crate proc_macro;
+
+mod source;
+
+use automata_codegen::rust::RustGenerator;
+use automata_syntax::SyntaxParser;
+use automata::Automata;
+use automata_core::string_interning::Interner;
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use source::Source;
+
+/// Generate a lexer from inline automata
+#[proc_macro]
+pub fn automata(input: TokenStream) -> TokenStream {
+    let mut source = Source::new();
+    push_tokens(&mut source, input);
+
+    // The compiler keeps running after the macro, so its names are freed with the interner
+    generate(&source, &Interner::new())
+}
+
+/// Generate a lexer from the automata syntax of the macro's tokens
+fn generate(source: &Source<Span>, interner: &Interner) -> TokenStream {
+    let mut parser = SyntaxParser::new(source.text())
+        .with_printed_errors(false)
+        .with_interner(interner);
+    let definitions = parser.parse();
+
+    let errors = parser.errors();
+    if !errors.is_empty() {
+        return errors
+            .iter()
+            .map(|error| {
+                let span = source
+                    .span_at(error.debug_info.index_location.0)
+                    .unwrap_or_else(Span::call_site);
+
+                compile_error(&error.message, span)
+            })
+            .collect();
+    }
+
+    let automata = Automata::resolve_from(definitions);
+
+    match RustGenerator::new(&automata).generate().code.parse() {
+        Ok(lexer) => lexer,
+        Err(error) => compile_error(
+            &format!("Generated lexer is not valid Rust: {}", error),
+            Span::call_site(),
+        ),
+    }
+}
+
+/// Write tokens out as automata syntax
+fn push_tokens(source: &mut Source<Span>, tokens: TokenStream) {
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::None => ("", ""),
+                };
+
+                source.push(open, group.span_open());
+                push_tokens(source, group.stream());
+                source.push(close, group.span_close());
+            }
+            TokenTree::Ident(ident) => {
+                let text = ident.to_string();
+                source.push(&text, ident.span());
+
+                // `i "select"`, which can't be written `i"select"` in Rust 2021
+                if text == "i" {
+                    if let Some(&TokenTree::Literal(_)) = tokens.peek() {
+                        source.join_next();
+                    }
+                }
+            }
+            TokenTree::Punct(punct) => {
+                source.push(&punct.as_char().to_string(), punct.span());
+
+                if punct.spacing() == Spacing::Joint {
+                    source.join_next();
+                }
+            }
+            TokenTree::Literal(literal) => {
+                source.push(&literal.to_string(), literal.span());
+            }
+        }
+    }
+}
+
+/// `compile_error!("message");` pointing at a span
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+
+    let mut message = Literal::string(message);
+    message.set_span(span);
+
+    let mut arguments = Group::new(
+        Delimiter::Parenthesis,
+        TokenStream::from(TokenTree::Literal(message)),
+    );
+    arguments.set_span(span);
+
+    let mut semi_column = Punct::new(';', Spacing::Alone);
+    semi_column.set_span(span);
+
+    vec![
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(arguments),
+        TokenTree::Punct(semi_column),
+    ]
+    .into_iter()
+    .collect()
+}
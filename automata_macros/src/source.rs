@@ -0,0 +1,117 @@
+//! Macro input written back as text
+//!
+//! The tokens of the macro are written out separated by spaces, except where they were joined
+//! in the input, and each one remembers its span. An error found in the text can then be put on
+//! the token it is about.
+
+/// Text made of tokens, each with a span
+#[derive(Debug)]
+pub(crate) struct Source<S> {
+    text: String,
+    /// Number of chars in `text`, as token locations are counted in chars
+    char_count: usize,
+    /// The char index each token starts at, with its span
+    tokens: Vec<(usize, S)>,
+    /// Whether the next token is written right after the last one
+    joined: bool,
+}
+
+impl<S: Copy> Source<S> {
+    pub(crate) fn new() -> Self {
+        Self {
+            text: String::new(),
+            char_count: 0,
+            tokens: Vec::new(),
+            joined: true,
+        }
+    }
+
+    /// Write a token out
+    pub(crate) fn push(&mut self, text: &str, span: S) {
+        if text.is_empty() {
+            return;
+        }
+
+        if !self.joined {
+            self.text.push(' ');
+            self.char_count += 1;
+        }
+
+        self.tokens.push((self.char_count, span));
+        self.text.push_str(text);
+        self.char_count += text.chars().count();
+        self.joined = false;
+    }
+
+    /// Write the next token right after the last one
+    pub(crate) fn join_next(&mut self) {
+        self.joined = true;
+    }
+
+    pub(crate) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The span of the token at a char index, or of the last one before it
+    pub(crate) fn span_at(&self, index: usize) -> Option<S> {
+        let following = self
+            .tokens
+            .iter()
+            .position(|&(start, _)| start > index)
+            .unwrap_or(self.tokens.len());
+
+        following.checked_sub(1).map(|token| self.tokens[token].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use automata_syntax::SyntaxParser;
+
+    #[test]
+    fn source_span_test() {
+        let mut source = Source::new();
+        let tokens: &[(&str, bool)] = &[
+            ("state", false),
+            ("start", false),
+            ("{", false),
+            ("'a'", false),
+            ("=", true),
+            (">", true),
+            (">", false),
+            ("return", false),
+            ("ACCEPT", false),
+            ("'b'", false),
+            ("=", true),
+            (">", false),
+            ("return", false),
+            ("'c'", false),
+            ("}", false),
+        ];
+
+        for (span, &(text, joint)) in tokens.iter().enumerate() {
+            source.push(text, span);
+
+            if joint {
+                source.join_next();
+            }
+        }
+
+        assert_eq!(
+            source.text(),
+            "state start { 'a' =>> return ACCEPT 'b' => return 'c' }"
+        );
+
+        let mut parser = SyntaxParser::new(source.text()).with_printed_errors(false);
+        let _ = parser.parse();
+
+        let spans = parser
+            .errors()
+            .iter()
+            .map(|error| source.span_at(error.debug_info.index_location.0))
+            .collect::<Vec<_>>();
+
+        assert_eq!(spans, vec![Some(10), Some(13)]);
+    }
+}
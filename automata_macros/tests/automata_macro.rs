@@ -0,0 +1,60 @@
+#[macro_use]
+extern crate automata_macros;
+
+#[allow(dead_code)]
+mod lexer {
+    automata! {
+        keywords identifier {
+            "fn" => KEYWORD_FN
+        }
+
+        state start {
+            'a'..'z' => identifier
+            '0'..'9' => number
+            ' ' =>> return SPACE
+            i"select" =>> return SELECT
+            #[priority(1)] i 'x' =>> return X
+            '-' / '>' =>> return ARROW_START
+        }
+
+        state identifier {
+            'a'..'z' | '_' => Self
+            _ => return IDENTIFIER
+        }
+
+        state number {
+            '0'..'9' => Self
+            _ => return NUMBER
+        }
+    }
+}
+
+use lexer::{LexErrorKind, Token};
+
+#[test]
+fn automata_macro_test() {
+    let tokens = lexer::lex_start("fn SeLeCt X abc 42 ->")
+        .map(|result| result.map(|lexeme| (lexeme.token, lexeme.span)))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Ok((Token::KEYWORD_FN, (0, 2))),
+            Ok((Token::SPACE, (2, 3))),
+            Ok((Token::SELECT, (3, 9))),
+            Ok((Token::SPACE, (9, 10))),
+            Ok((Token::X, (10, 11))),
+            Ok((Token::SPACE, (11, 12))),
+            Ok((Token::IDENTIFIER, (12, 15))),
+            Ok((Token::SPACE, (15, 16))),
+            Ok((Token::NUMBER, (16, 18))),
+            Ok((Token::SPACE, (18, 19))),
+            Ok((Token::ARROW_START, (19, 20))),
+            Err(lexer::LexError {
+                kind: LexErrorKind::UnexpectedChar('>'),
+                span: (20, 20),
+            }),
+        ]
+    );
+}
@@ -0,0 +1,110 @@
+//! Errors in the input of `automata!`, found by compiling code that uses it with rustc
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+/// The macro library cargo built for the tests, the latest one if older builds are around
+fn macro_library() -> PathBuf {
+    let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+
+    fs::read_dir(deps)
+        .unwrap()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            let is_library = matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("so") | Some("dylib") | Some("dll")
+            );
+
+            name.contains("automata_macros-") && is_library
+        })
+        .max_by_key(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .expect("automata_macros isn't built")
+}
+
+/// Compile a library made of `source`, giving back the errors as `line:column: message`
+fn compile_errors(name: &str, source: &str) -> Vec<String> {
+    let directory = env::temp_dir().join(format!("automata_macros_{}_{}", name, process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("lib.rs"), source).unwrap();
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc)
+        .current_dir(&directory)
+        .args([
+            "--edition",
+            "2018",
+            "--crate-type",
+            "lib",
+            "--error-format",
+            "short",
+        ])
+        .arg("--extern")
+        .arg(format!("automata_macros={}", macro_library().display()))
+        .arg("lib.rs")
+        .output()
+        .unwrap();
+    let _ = fs::remove_dir_all(&directory);
+
+    assert!(!output.status.success(), "{} compiles", name);
+
+    String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.strip_prefix("lib.rs:"))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn syntax_error_test() {
+    let errors = compile_errors(
+        "syntax_error",
+        r#"
+automata_macros::automata! {
+    state start {
+        'a' => error 42
+        ' ' =>> return SPACE
+    }
+}
+"#,
+    );
+
+    assert_eq!(
+        errors,
+        vec![
+            "4:13: error: Could find valid destination after ".to_string(),
+            "4:22: error: Expected a message after error".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn conflict_test() {
+    let errors = compile_errors(
+        "conflict",
+        r#"
+automata_macros::automata! {
+    state start {
+        'a'..'z' =>> return LETTER
+        'x' =>> return X
+    }
+}
+"#,
+    );
+
+    assert_eq!(
+        errors,
+        vec![
+            "5:13: error: Arm overlaps an earlier arm on \"x\" and no priority decides between them"
+                .to_string()
+        ]
+    );
+}
@@ -19,6 +19,18 @@ pub struct AutomataParser<'input> {
     line: usize,
     column: usize,
     index: usize,
+    /// Errors found so far, in the order they were found
+    errors: Vec<ParseError>,
+    print_errors: bool,
+    /// Where identifiers are interned
+    interner: Interner,
+}
+
+/// An input that could not be turned into a token
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub debug_info: TokenDebugInfo,
 }
 
 impl<'input> AutomataParser<'input> {
@@ -33,9 +45,29 @@ impl<'input> AutomataParser<'input> {
             column: 0,
             index: 0,
             buffered_input: VecDeque::new(),
+            errors: Vec::new(),
+            print_errors: true,
+            interner: Interner::global(),
         }
     }
 
+    /// Set the interner identifiers are interned in, the global one by default
+    pub fn with_interner(mut self, interner: &Interner) -> Self {
+        self.interner = interner.clone();
+        self
+    }
+
+    /// Set whether errors are printed to stderr as they are found, which they are by default
+    pub fn with_printed_errors(mut self, print_errors: bool) -> Self {
+        self.print_errors = print_errors;
+        self
+    }
+
+    /// The errors found so far
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
     /// Goes through every token and prints it. Can be used to check input validity
     pub fn check(&mut self) {
         while let Some(token) = self.get_next_token() {
@@ -97,6 +129,69 @@ impl<'input> AutomataParser<'input> {
         (start, self.index)
     }
 
+    /// Read the rest of a char literal, after its opening quote
+    fn read_char_literal(&mut self) -> Option<char> {
+        let middle_char = self.get_next_char()?;
+
+        match self.get_next_char()? {
+            '\'' => Some(middle_char),
+            _ => None,
+        }
+    }
+
+    /// Read the rest of a char sequence, after its opening quote
+    fn read_char_sequence(&mut self) -> Option<Vec<char>> {
+        let mut sequence = Vec::new();
+        let mut escaped = false;
+
+        while let Some(chr) = self.get_next_char() {
+            match chr {
+                '\\' => {
+                    escaped = true;
+                }
+                '"' if !escaped => {
+                    return Some(sequence);
+                }
+                chr => {
+                    sequence.push(chr);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Read the rest of a byte literal or byte string up to the closing `quote`, then decode it
+    /// Bytes are written as ASCII chars or as `\xHH`, `\n`, `\r`, `\t`, `\0`, `\\`, `\'` and `\"`
+    fn read_bytes(&mut self, quote: char) -> Option<Vec<u8>> {
+        let mut bytes = Vec::new();
+
+        loop {
+            let chr = self.get_next_char()?;
+
+            let byte = match chr {
+                _ if chr == quote => return Some(bytes),
+                '\\' => match self.get_next_char()? {
+                    'x' => {
+                        let high = self.get_next_char()?.to_digit(16)?;
+                        let low = self.get_next_char()?.to_digit(16)?;
+                        (high * 16 + low) as u8
+                    }
+                    'n' => b'\n',
+                    'r' => b'\r',
+                    't' => b'\t',
+                    '0' => 0,
+                    escaped @ '\\' | escaped @ '\'' | escaped @ '"' => escaped as u8,
+                    _ => return None,
+                },
+                _ if chr.is_ascii() => chr as u8,
+                _ => return None,
+            };
+
+            bytes.push(byte);
+        }
+    }
+
     /// Get the next token from the input
     pub fn get_next_token(&mut self) -> Option<Token> {
         let mut chr = self.get_next_char()?;
@@ -127,14 +222,27 @@ impl<'input> AutomataParser<'input> {
         macro_rules! parse_err {
             //TODO: Make formatting on par with the syntax errors
             ($err: expr) => {
-                let error_source = &self.raw[index_start..self.index];
+                let message = $err.to_string();
+
+                if self.print_errors {
+                    let error_source = &self.raw[index_start..self.index];
 
-                let error_message = format!(
-                    " starting at line: {}, Col: {}\n\tCurrent: {}",
-                    line_start, column_start, error_source
-                );
+                    let error_message = format!(
+                        " starting at line: {}, Col: {}\n\tCurrent: {}",
+                        line_start, column_start, error_source
+                    );
 
-                eprintln!("{}{}", $err, error_message);
+                    eprintln!("{}{}", message, error_message);
+                }
+
+                self.errors.push(ParseError {
+                    message,
+                    debug_info: TokenDebugInfo::new(
+                        self.get_column_location_from(column_start),
+                        self.get_line_location_from(line_start),
+                        self.get_index_location_from(index_start),
+                    ),
+                });
             };
         }
 
@@ -149,14 +257,83 @@ impl<'input> AutomataParser<'input> {
                         'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => {
                             identifier.push(chr);
                         }
+                        '\'' if identifier == "i" => match self.read_char_literal() {
+                            Some(chr) => {
+                                return_token!(InsensitiveChar(chr));
+                            }
+                            None => {
+                                parse_err!("Could not parse char literal");
+                                return None;
+                            }
+                        },
+                        '"' if identifier == "i" => {
+                            if let Some(sequence) = self.read_char_sequence() {
+                                return_token!(InsensitiveCharSequence(sequence));
+                            }
+
+                            return None;
+                        }
+                        '\'' if identifier == "b" => match self.read_bytes('\'') {
+                            Some(ref bytes) if bytes.len() == 1 => {
+                                return_token!(Byte(bytes[0]));
+                            }
+                            _ => {
+                                parse_err!("Could not parse byte literal");
+                                return None;
+                            }
+                        },
+                        '"' if identifier == "b" => match self.read_bytes('"') {
+                            Some(bytes) => {
+                                return_token!(ByteSequence(bytes));
+                            }
+                            None => {
+                                parse_err!("Could not parse byte string");
+                                return None;
+                            }
+                        },
                         chr => {
                             self.buffered_input.push_front(chr);
-                            return_token!(Identifier(intern(identifier)));
+                            return_token!(Identifier(self.interner.intern(identifier)));
                         }
                     }
                 }
             }
             | '0'...'9' => {
+                if chr == '0' {
+                    match self.get_next_char() {
+                        Some('x') => {
+                            let mut value: u32 = 0;
+                            let mut digits = 0;
+
+                            while let Some(chr) = self.get_next_char() {
+                                match chr.to_digit(16) {
+                                    Some(digit) => value = value * 16 + digit,
+                                    None => {
+                                        self.buffered_input.push_front(chr);
+                                        break;
+                                    }
+                                }
+
+                                digits += 1;
+
+                                if value > 0xFF {
+                                    parse_err!("Hexadecimal byte is larger than 0xFF");
+                                    return None;
+                                }
+                            }
+
+                            if digits == 0 {
+                                parse_err!("Expected hexadecimal digits after 0x");
+                                return None;
+                            }
+
+                            return_token!(Byte(value as u8));
+                        }
+                        Some(chr) => self.buffered_input.push_front(chr),
+                        None => {}
+                    }
+                }
+
                 let mut number: i32 = chr.to_digit(10).unwrap() as i32;
 
                 while let Some(chr) = self.get_next_char() {
@@ -165,9 +342,12 @@ impl<'input> AutomataParser<'input> {
                             number *= 10;
                             number += chr.to_digit(10).unwrap() as i32;
                         }
+                        chr if chr.is_ascii_alphabetic() || chr == '_' => {
+                            parse_err!("Digit cannot contain letter");
+                        }
                         chr => {
                             self.buffered_input.push_front(chr);
-                            parse_err!("Digit cannot contain letter");
+                            return_token!(Integer(number));
                         }
                     }
                 }
@@ -179,6 +359,16 @@ impl<'input> AutomataParser<'input> {
                 if let Some(chr) = self.get_next_char() {
                     match chr {
                         '>' => {
+                            match self.get_next_char() {
+                                Some('>') => {
+                                    return_token!(ConsumingArrow);
+                                }
+                                Some(chr) => {
+                                    self.buffered_input.push_front(chr);
+                                }
+                                None => {}
+                            }
+
                             return_token!(Arrow);
                         }
                         _ => {
@@ -214,21 +404,15 @@ impl<'input> AutomataParser<'input> {
                 return_token!(SemiColumn);
             }
             // Character literal
-            '\'' => {
-                if let Some(middle_char) = self.get_next_char() {
-                    if let Some(chr) = self.get_next_char() {
-                        match chr {
-                            '\'' => {
-                                return_token!(Char(middle_char));
-                            }
-                            _ => {
-                                parse_err!("Could not parse char literal");
-                                return None;
-                            }
-                        }
-                    }
+            '\'' => match self.read_char_literal() {
+                Some(chr) => {
+                    return_token!(Char(chr));
                 }
-            }
+                None => {
+                    parse_err!("Could not parse char literal");
+                    return None;
+                }
+            },
             // Scopes
             '{' => {
                 return_token!(Scope(Open));
@@ -243,22 +427,36 @@ impl<'input> AutomataParser<'input> {
             '|' => {
                 return_token!(Line);
             }
+            '/' => {
+                return_token!(Slash);
+            }
+            '!' => {
+                return_token!(Exclamation);
+            }
+            ',' => {
+                return_token!(Comma);
+            }
+            '#' => {
+                return_token!(Hash);
+            }
+            '[' => {
+                return_token!(Bracket(Open));
+            }
+            ']' => {
+                return_token!(Bracket(Close));
+            }
+            '(' => {
+                return_token!(Parenthesis(Open));
+            }
+            ')' => {
+                return_token!(Parenthesis(Close));
+            }
+            '>' => {
+                return_token!(GreaterThan);
+            }
             '"' => {
-                let mut sequence = Vec::new();
-                let mut escaped = false;
-
-                while let Some(chr) = self.get_next_char() {
-                    match chr {
-                        '\\' => {
-                            escaped = true;
-                        }
-                        '"' if !escaped => {
-                            return_token!(CharSequence(sequence));
-                        }
-                        chr => {
-                            sequence.push(chr);
-                        }
-                    }
+                if let Some(sequence) = self.read_char_sequence() {
+                    return_token!(CharSequence(sequence));
                 }
             }
             // Unknown
@@ -270,3 +468,78 @@ impl<'input> AutomataParser<'input> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokens::TokenKind;
+
+    /// Tokenize some input, giving back the kinds of its tokens and its errors
+    fn tokenize(input: &str) -> (Vec<TokenKind>, Vec<String>) {
+        let mut parser = AutomataParser::new(input).with_printed_errors(false);
+        let mut kinds = Vec::new();
+
+        while let Some(token) = parser.get_next_token() {
+            kinds.push(token.kind);
+        }
+
+        let errors = parser
+            .errors()
+            .iter()
+            .map(|error| error.message.clone())
+            .collect();
+
+        (kinds, errors)
+    }
+
+    #[test]
+    fn insensitive_literal_test() {
+        let (kinds, errors) = tokenize("i'x' | i\"select\" => ident;");
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(
+            kinds,
+            vec![
+                InsensitiveChar('x'),
+                Line,
+                InsensitiveCharSequence("select".chars().collect()),
+                Arrow,
+                Identifier(intern("ident")),
+                SemiColumn,
+            ]
+        );
+
+        let (kinds, _) = tokenize("i 'x'");
+        assert_eq!(kinds, vec![Identifier(intern("i")), Char('x')]);
+    }
+
+    #[test]
+    fn byte_literal_test() {
+        let (kinds, errors) = tokenize("b'\\x00' | b\"\\xDE\\xAD\" | 0x00..0xFF | b'\\n' 10");
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(
+            kinds,
+            vec![
+                Byte(0x00),
+                Line,
+                ByteSequence(vec![0xDE, 0xAD]),
+                Line,
+                Byte(0x00),
+                Range,
+                Byte(0xFF),
+                Line,
+                Byte(b'\n'),
+                Integer(10),
+            ]
+        );
+
+        for &(input, error) in &[
+            ("0x100", "Hexadecimal byte is larger than 0xFF"),
+            ("0x;", "Expected hexadecimal digits after 0x"),
+            ("b'ab'", "Could not parse byte literal"),
+            ("b\"\\xZZ\"", "Could not parse byte string"),
+        ] {
+            let (_, errors) = tokenize(input);
+            assert_eq!(errors, vec![error.to_string()]);
+        }
+    }
+}
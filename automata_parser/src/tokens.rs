@@ -10,15 +10,31 @@ pub struct Token {
 pub enum TokenKind {
     Identifier(InternedString),
     Arrow,
+    ConsumingArrow,
     Column,
     Char(char),
     CharSequence(Vec<char>),
+    /// `i'a'`, the start of a case insensitive range or literal
+    InsensitiveChar(char),
+    /// `i"select"`
+    InsensitiveCharSequence(Vec<char>),
+    /// `b'\x00'` or `0x00`
+    Byte(u8),
+    /// `b"\xDE\xAD"`
+    ByteSequence(Vec<u8>),
     Range,
     SemiColumn,
     Integer(i32),
     Scope(ScopeType),
     Line,
     UnderScore,
+    Slash,
+    Exclamation,
+    Comma,
+    Hash,
+    Bracket(ScopeType),
+    Parenthesis(ScopeType),
+    GreaterThan,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -0,0 +1,673 @@
+//! Building the definitions of a machine from code, for lexers generated from data
+//!
+//! ```ignore
+//! let definitions = AutomataBuilder::new()
+//!     .keyword("identifier", "select", "KEYWORD_SELECT")
+//!     .state("start")
+//!     .on_range('a', 'z').goto("identifier")
+//!     .on(' ').consuming().ret("SPACE")
+//!     .state("identifier")
+//!     .on_range('a', 'z').goto("Self")
+//!     .on_default().ret("IDENTIFIER")
+//!     .build()?;
+//! ```
+//!
+//! Calls read like the text they stand for: the `on_*` calls are the inputs of an arm, separated
+//! by `|` in text, and the destination ends the arm. The definitions are the ones `SyntaxParser`
+//! gives for the same text, and are checked the same way. Errors point at the call that declared
+//! what they are about, see `Declaration`. Names are interned in the global interner, or in the
+//! one given to `AutomataBuilder::with_interner`.
+
+use automata::{definitions::*, statements::*, states::*};
+use automata_core::string_interning::*;
+use std::error::Error;
+use std::fmt;
+use validation::*;
+
+/// What a `BuildError` is about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Declaration {
+    EntryPoint(InternedString),
+    /// A keyword, along with the state it belongs to
+    Keyword(InternedString, String),
+    /// A token priority, the first token wins
+    Priority(InternedString, InternedString),
+    /// An arm, by its state and the index of its first statement in the state
+    Arm {
+        state: InternedString,
+        index: usize,
+    },
+}
+
+/// An error found while building definitions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildError {
+    pub message: String,
+    pub declaration: Declaration,
+}
+
+/// Builds the definitions of a machine, see the module documentation
+pub struct AutomataBuilder {
+    definitions: Definitions,
+    declarations: Declarations<Declaration>,
+    errors: Vec<BuildError>,
+    /// Index of the state arms are added to, and whether it is case insensitive
+    current_state: Option<(usize, bool)>,
+    arm: PendingArm,
+}
+
+/// An arm whose destination isn't known yet
+#[derive(Default)]
+struct PendingArm {
+    /// Inputs, with whether they were given case insensitive
+    inputs: Vec<(StatementMatchKind, bool)>,
+    context: Option<TrailingContext>,
+    consume: bool,
+    priority: i32,
+}
+
+impl AutomataBuilder {
+    /// Create a builder without any definition, interning names in the global interner
+    pub fn new() -> Self {
+        Self::with_interner(&Interner::global())
+    }
+
+    /// Create a builder without any definition, interning names in an interner
+    pub fn with_interner(interner: &Interner) -> Self {
+        Self {
+            definitions: Definitions::with_interner(interner.clone()),
+            declarations: Declarations::new(),
+            errors: Vec::new(),
+            current_state: None,
+            arm: PendingArm::default(),
+        }
+    }
+
+    /// Declare an entry point, as `entry state;`
+    pub fn entry<S: AsRef<str>>(mut self, state: S) -> Self {
+        let state = self.intern(state.as_ref());
+        let declaration = Declaration::EntryPoint(state);
+
+        if self
+            .declarations
+            .entry_points
+            .iter()
+            .any(|&(entry_point, _)| entry_point == state)
+        {
+            self.report("Entry point is declared twice", declaration);
+        } else {
+            self.definitions.push_entry_point(state);
+            self.declarations.entry_points.push((state, declaration));
+        }
+
+        self
+    }
+
+    /// Declare a token priority, as `priority higher > lower;`
+    pub fn priority<S: AsRef<str>, T: AsRef<str>>(mut self, higher: S, lower: T) -> Self {
+        let (higher, lower) = (self.intern(higher.as_ref()), self.intern(lower.as_ref()));
+
+        self.definitions.push_priority(higher, lower);
+        self.declarations
+            .priorities
+            .push(Declaration::Priority(higher, lower));
+        self
+    }
+
+    /// Add a keyword to the keywords of a state, as `keywords state { "keyword" => TOKEN }`
+    pub fn keyword<S: AsRef<str>, T: AsRef<str>>(
+        mut self,
+        state: S,
+        keyword: &str,
+        token: T,
+    ) -> Self {
+        let (state, token) = (self.intern(state.as_ref()), self.intern(token.as_ref()));
+        let declaration = Declaration::Keyword(state, keyword.to_string());
+
+        if !self
+            .declarations
+            .check_alphabet(Alphabet::Chars, declaration.clone())
+        {
+            self.report(
+                "Byte and char literals can't be mixed in one machine",
+                declaration.clone(),
+            );
+        }
+
+        let index = match self
+            .definitions
+            .keywords
+            .iter()
+            .position(|keyword_definition| keyword_definition.state == state)
+        {
+            Some(index) => index,
+            None => {
+                self.definitions
+                    .push_keywords(KeywordDefinition::new(state));
+                self.declarations
+                    .keywords
+                    .push((state, declaration.clone()));
+                self.definitions.keywords.len() - 1
+            }
+        };
+
+        let keyword_definition = &mut self.definitions.keywords[index];
+
+        if keyword_definition
+            .keywords
+            .iter()
+            .any(|(existing_keyword, _)| existing_keyword == keyword)
+        {
+            self.report("Keyword is declared twice", declaration);
+        } else {
+            keyword_definition.push_keyword(keyword.to_string(), token);
+        }
+
+        self
+    }
+
+    /// Add the arms that follow to a state, declaring it if it isn't yet
+    pub fn state<S: AsRef<str>>(self, name: S) -> Self {
+        self.open_state(name.as_ref(), false)
+    }
+
+    /// Add the arms that follow to a state, making them case insensitive, as
+    /// `#[case_insensitive] state name { ... }`
+    pub fn case_insensitive_state<S: AsRef<str>>(self, name: S) -> Self {
+        self.open_state(name.as_ref(), true)
+    }
+
+    /// Match a char, as `'a'`
+    pub fn on(self, chr: char) -> Self {
+        self.input(
+            StatementMatchKind::Literal(chr),
+            false,
+            Some(Alphabet::Chars),
+        )
+    }
+
+    /// Match a char regardless of case, as `i'a'`
+    pub fn on_insensitive(self, chr: char) -> Self {
+        self.input(
+            StatementMatchKind::Literal(chr),
+            true,
+            Some(Alphabet::Chars),
+        )
+    }
+
+    /// Match a range of chars, as `'a'..'z'`
+    pub fn on_range(self, from: char, to: char) -> Self {
+        let range = CharRange::new(from, to);
+        self.input(
+            StatementMatchKind::Range(range),
+            false,
+            Some(Alphabet::Chars),
+        )
+    }
+
+    /// Match a sequence of chars, as `"select"`
+    pub fn on_sequence(self, sequence: &str) -> Self {
+        let sequence = sequence.chars().collect();
+        self.input(
+            StatementMatchKind::Sequence(sequence),
+            false,
+            Some(Alphabet::Chars),
+        )
+    }
+
+    /// Match a sequence of chars regardless of case, as `i"select"`
+    pub fn on_insensitive_sequence(self, sequence: &str) -> Self {
+        let sequence = sequence.chars().collect();
+        self.input(
+            StatementMatchKind::Sequence(sequence),
+            true,
+            Some(Alphabet::Chars),
+        )
+    }
+
+    /// Match a byte, as `b'a'`
+    pub fn on_byte(self, byte: u8) -> Self {
+        let literal = StatementMatchKind::Literal(char::from(byte));
+        self.input(literal, false, Some(Alphabet::Bytes))
+    }
+
+    /// Match a range of bytes, as `0x00..0x7F`
+    pub fn on_byte_range(self, from: u8, to: u8) -> Self {
+        let range = CharRange::new(char::from(from), char::from(to));
+        self.input(
+            StatementMatchKind::Range(range),
+            false,
+            Some(Alphabet::Bytes),
+        )
+    }
+
+    /// Match a sequence of bytes, as `b"\xDE\xAD"`
+    pub fn on_bytes(self, sequence: &[u8]) -> Self {
+        let sequence = sequence.iter().map(|&byte| char::from(byte)).collect();
+        self.input(
+            StatementMatchKind::Sequence(sequence),
+            false,
+            Some(Alphabet::Bytes),
+        )
+    }
+
+    /// Match the inputs no other arm matches, as `_`
+    pub fn on_default(self) -> Self {
+        self.input(StatementMatchKind::Default, false, None)
+    }
+
+    /// Match the end of the input, as `eof`
+    pub fn on_eof(self) -> Self {
+        self.input(StatementMatchKind::EndOfInput, false, None)
+    }
+
+    /// Only apply the arm when one of some inputs follows, as `/ '>' | '='`
+    pub fn followed_by(self, alternatives: Vec<StatementMatchKind>) -> Self {
+        self.context(TrailingContext::new(false, alternatives))
+    }
+
+    /// Only apply the arm when none of some inputs follows, as `/ !'>' | '='`
+    pub fn not_followed_by(self, alternatives: Vec<StatementMatchKind>) -> Self {
+        self.context(TrailingContext::new(true, alternatives))
+    }
+
+    /// Consume the matched char when the arm returns or errors, as `=>>`
+    pub fn consuming(mut self) -> Self {
+        self.arm.consume = true;
+        self
+    }
+
+    /// Give the arm a priority over the arms matching the same input, as `#[priority(n)]`
+    pub fn arm_priority(mut self, priority: i32) -> Self {
+        self.arm.priority = priority;
+        self
+    }
+
+    /// End the arm with a transition to a state, as `=> state`
+    pub fn goto<S: AsRef<str>>(self, state: S) -> Self {
+        let state = self.intern(state.as_ref());
+        self.destination(Destination::State(state))
+    }
+
+    /// End the arm by returning a token, as `=> return TOKEN`
+    pub fn ret<S: AsRef<str>>(self, token: S) -> Self {
+        let token = self.intern(token.as_ref());
+        self.destination(Destination::Return(token))
+    }
+
+    /// End the arm with an error, as `=> error "message"`
+    pub fn error<S: AsRef<str>>(self, message: S) -> Self {
+        let message = self.intern(message.as_ref());
+        self.destination(Destination::Error(message))
+    }
+
+    /// End the arm by pushing a state, as `=> push state`
+    pub fn push<S: AsRef<str>>(self, state: S) -> Self {
+        let state = self.intern(state.as_ref());
+        self.destination(Destination::Push(state))
+    }
+
+    /// End the arm by popping the state pushed last, as `=> pop`
+    pub fn pop(self) -> Self {
+        self.destination(Destination::Pop)
+    }
+
+    /// Check the definitions and return them
+    ///
+    /// # Panics
+    ///
+    /// If the last arm has no destination
+    pub fn build(mut self) -> Result<Definitions, Vec<BuildError>> {
+        self.check_no_pending_arm();
+
+        let mut errors = self.errors;
+        check_definitions(
+            &mut self.definitions,
+            &self.declarations,
+            |message, declaration| {
+                errors.push(BuildError {
+                    message: message.to_string(),
+                    declaration: declaration.clone(),
+                })
+            },
+        );
+
+        if errors.is_empty() {
+            Ok(self.definitions)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn intern(&self, string: &str) -> InternedString {
+        self.definitions.interner.intern(string)
+    }
+
+    fn open_state(mut self, name: &str, case_insensitive: bool) -> Self {
+        self.check_no_pending_arm();
+
+        let name = self.intern(name);
+        let index = match self
+            .definitions
+            .states
+            .iter()
+            .position(|state_definition| state_definition.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.definitions.push_state(StateDefinition::new(name));
+                self.declarations.arms.insert(name, Vec::new());
+                self.definitions.states.len() - 1
+            }
+        };
+
+        self.current_state = Some((index, case_insensitive));
+        self
+    }
+
+    /// Add an input to the arm, along with the alphabet of its literals if it has any
+    fn input(
+        mut self,
+        match_kind: StatementMatchKind,
+        insensitive: bool,
+        alphabet: Option<Alphabet>,
+    ) -> Self {
+        let declaration = self.arm_declaration();
+
+        if let Some(alphabet) = alphabet {
+            if !self
+                .declarations
+                .check_alphabet(alphabet, declaration.clone())
+            {
+                self.report(
+                    "Byte and char literals can't be mixed in one machine",
+                    declaration,
+                );
+            }
+        }
+
+        self.arm.inputs.push((match_kind, insensitive));
+        self
+    }
+
+    fn context(mut self, context: TrailingContext) -> Self {
+        let declaration = self.arm_declaration();
+
+        if self.arm.context.is_some() {
+            self.report("Only one trailing context is allowed", declaration);
+        } else if context.alternatives.is_empty() {
+            self.report("Expected a trailing context", declaration);
+        } else if context.alternatives.iter().any(is_default_or_end) {
+            self.report(
+                "Trailing context can only contain chars, ranges and sequences",
+                declaration,
+            );
+        } else {
+            self.arm.context = Some(context);
+        }
+
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If the arm has no input
+    fn destination(mut self, destination: Destination) -> Self {
+        let declaration = self.arm_declaration();
+        let arm = ::std::mem::take(&mut self.arm);
+        let (index, case_insensitive) = self.current_state.unwrap();
+
+        if arm.inputs.is_empty() {
+            panic!("An arm needs an input, an `on_*` call, before its destination");
+        }
+
+        if arm.context.is_some() && arm.inputs.iter().any(|input| is_default_or_end(&input.0)) {
+            self.report(
+                "Trailing context can only follow chars, ranges and sequences",
+                declaration.clone(),
+            );
+        }
+
+        if arm.consume && !ends_token(&destination) {
+            self.report(
+                "Only a return or an error can consume its char",
+                declaration,
+            );
+        }
+
+        let lookahead = if arm.consume {
+            Lookahead::Consume
+        } else {
+            Lookahead::Keep
+        };
+
+        let state_definition = &mut self.definitions.states[index];
+
+        for (match_kind, insensitive_input) in arm.inputs {
+            let mut statement =
+                Statement::new(destination, match_kind, lookahead).with_priority(arm.priority);
+            statement.context = arm.context.clone();
+
+            if case_insensitive || insensitive_input {
+                statement = statement.case_insensitive();
+            }
+
+            self.declarations
+                .arms
+                .get_mut(&state_definition.name)
+                .unwrap()
+                .push(Declaration::Arm {
+                    state: state_definition.name,
+                    index: state_definition.statements.len(),
+                });
+            state_definition.push_statement(statement);
+        }
+
+        self
+    }
+
+    /// The arm being declared
+    ///
+    /// # Panics
+    ///
+    /// If no state was declared yet
+    fn arm_declaration(&self) -> Declaration {
+        let (index, _) = self
+            .current_state
+            .expect("Arms belong to a state, `state` declares one");
+        let state_definition = &self.definitions.states[index];
+
+        Declaration::Arm {
+            state: state_definition.name,
+            index: state_definition.statements.len(),
+        }
+    }
+
+    fn check_no_pending_arm(&self) {
+        if !self.arm.inputs.is_empty() {
+            panic!(
+                "The last arm has no destination, `goto`, `ret`, `error`, `push` or `pop` ends it"
+            );
+        }
+    }
+
+    fn report<S: Into<String>>(&mut self, message: S, declaration: Declaration) {
+        self.errors.push(BuildError {
+            message: message.into(),
+            declaration,
+        });
+    }
+}
+
+/// Whether an input matches no char, so it can't be or be followed by a trailing context
+fn is_default_or_end(match_kind: &StatementMatchKind) -> bool {
+    matches!(
+        *match_kind,
+        StatementMatchKind::Default | StatementMatchKind::EndOfInput
+    )
+}
+
+impl Default for AutomataBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Declaration::EntryPoint(state) => write!(f, "entry point {:?}", state),
+            Declaration::Keyword(state, ref keyword) => {
+                write!(f, "keyword {:?} of {:?}", keyword, state)
+            }
+            Declaration::Priority(higher, lower) => {
+                write!(f, "priority {:?} > {:?}", higher, lower)
+            }
+            Declaration::Arm { state, index } => write!(f, "arm {} of {:?}", index, state),
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.declaration, self.message)
+    }
+}
+
+impl Error for BuildError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SyntaxParser;
+
+    #[test]
+    fn automata_builder_test() {
+        let mut parser = SyntaxParser::new(
+            r#"
+            entry start;
+            priority KEYWORD_SELECT > IDENTIFIER;
+
+            keywords identifier {
+                "select" => KEYWORD_SELECT
+            }
+
+            state start {
+                'a'..'z' => identifier
+                '-' / '>' =>> return ARROW
+                ' ' | ',' =>> return SPACE
+                #[priority(1)]
+                '#' => push comment
+            }
+
+            #[case_insensitive]
+            state comment {
+                "end" => pop
+                eof => error "unfinished comment"
+                _ => Self
+            }
+
+            state identifier {
+                'a'..'z' => Self
+                _ => return IDENTIFIER
+            }
+            "#,
+        )
+        .with_printed_errors(false);
+        let mut parsed = parser.parse();
+        assert_eq!(parser.errors(), Vec::new());
+
+        // The builder has no source to point into
+        let sequence_span = parsed.states[1].statements[0].span;
+        assert_eq!(
+            sequence_span.map(|span| (span.line, span.end - span.start)),
+            Some((18, 5))
+        );
+
+        for state in &mut parsed.states {
+            for statement in &mut state.statements {
+                statement.span = None;
+            }
+        }
+
+        let built = AutomataBuilder::new()
+            .entry("start")
+            .priority("KEYWORD_SELECT", "IDENTIFIER")
+            .keyword("identifier", "select", "KEYWORD_SELECT")
+            .state("start")
+            .on_range('a', 'z')
+            .goto("identifier")
+            .on('-')
+            .followed_by(vec![StatementMatchKind::Literal('>')])
+            .consuming()
+            .ret("ARROW")
+            .on(' ')
+            .on(',')
+            .consuming()
+            .ret("SPACE")
+            .on('#')
+            .arm_priority(1)
+            .push("comment")
+            .case_insensitive_state("comment")
+            .on_sequence("end")
+            .pop()
+            .on_eof()
+            .error("unfinished comment")
+            .on_default()
+            .goto("Self")
+            .state("identifier")
+            .on_range('a', 'z')
+            .goto("Self")
+            .on_default()
+            .ret("IDENTIFIER")
+            .build()
+            .unwrap();
+
+        assert_eq!(built, parsed);
+
+        let errors = AutomataBuilder::new()
+            .entry("start")
+            .entry("main")
+            .entry("start")
+            .priority("KEYWORD_FROM", "IDENTIFIER")
+            .state("start")
+            .on('a')
+            .ret("A")
+            .on_byte(b'b')
+            .ret("B")
+            .on_range('a', 'z')
+            .ret("IDENTIFIER")
+            .on(' ')
+            .consuming()
+            .goto("Self")
+            .build()
+            .unwrap_err();
+
+        let start = intern("start");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "entry point \"start\": Entry point is declared twice",
+                "arm 1 of \"start\": Byte and char literals can't be mixed in one machine",
+                "arm 3 of \"start\": Only a return or an error can consume its char",
+                "entry point \"main\": Entry point refers to an undefined state",
+                "priority \"KEYWORD_FROM\" > \"IDENTIFIER\": Priority refers to a token that is never \
+                 returned",
+                "arm 2 of \"start\": Arm overlaps an earlier arm on \"a\" and no priority decides \
+                 between them",
+                "arm 2 of \"start\": Arm overlaps an earlier arm on \"b\" and no priority decides \
+                 between them",
+            ]
+        );
+        assert_eq!(
+            errors[1].declaration,
+            Declaration::Arm {
+                state: start,
+                index: 1
+            }
+        );
+    }
+}
@@ -2,11 +2,28 @@
 
 pub use colored::*;
 use super::SyntaxParser;
-use automata_parser::tokens::Token;
+use automata_parser::tokens::{Token, TokenDebugInfo};
+
+/// An error found while parsing some input
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    /// Location of the token the error is about
+    pub debug_info: TokenDebugInfo,
+}
 
 pub fn syntax_err<T: AsRef<str>>(syntax_parser: &SyntaxParser, message: T, token: &Token) {
     let debug_info = token.debug_info;
 
+    syntax_parser.errors.borrow_mut().push(SyntaxError {
+        message: message.as_ref().to_string(),
+        debug_info,
+    });
+
+    if !syntax_parser.print_errors {
+        return;
+    }
+
     let (token_start, token_end) = debug_info.index_location;
 
     let source_start = if token_start > 5 { token_start - 5 } else { 0 };
@@ -4,24 +4,40 @@ extern crate automata_core;
 extern crate automata_parser;
 extern crate colored;
 
-#[macro_use]
-extern crate lazy_static;
-
+pub mod builder;
 mod errors;
+mod validation;
+
+pub use builder::{AutomataBuilder, BuildError, Declaration};
+pub use errors::SyntaxError;
 
 use automata_core::string_interning::*;
-use automata::{statements::*, states::*};
+use automata::{definitions::*, statements::*, states::*};
 use errors::*;
+use validation::*;
 use automata_parser::tokens::*;
 use automata_core::string_interning::InternedString;
+use std::cell::RefCell;
+use std::mem;
+
+/// An attribute such as `#[priority(10)]`
+struct Attribute {
+    name: InternedString,
+    argument: Option<i32>,
+    /// The token that named the attribute
+    token: Token,
+}
+
+/// Where a token is in the input
+fn span(token: &Token) -> Span {
+    let debug_info = token.debug_info;
 
-lazy_static! {
-    static ref KEYWORD_RETURN: InternedString = {
-        return intern("return");
-    };
-    static ref KEYWORD_STATE: InternedString = {
-        return intern("state");
-    };
+    Span::new(
+        debug_info.index_location.0,
+        debug_info.index_location.1,
+        debug_info.line_location.0,
+        debug_info.column_location.0,
+    )
 }
 
 /// Parses the syntax of some input
@@ -29,6 +45,13 @@ lazy_static! {
 pub struct SyntaxParser<'input> {
     parser: automata_parser::AutomataParser<'input>,
     input: &'input str,
+    /// Tokens declaring entry points, keywords and priorities, and the arrow of each statement
+    declarations: Declarations<Token>,
+    /// Errors found so far, filled in by `syntax_err`
+    errors: RefCell<Vec<SyntaxError>>,
+    print_errors: bool,
+    /// Where names are interned
+    interner: Interner,
 }
 
 impl<'input> SyntaxParser<'input> {
@@ -37,37 +60,457 @@ impl<'input> SyntaxParser<'input> {
         Self {
             parser: automata_parser::AutomataParser::new(input),
             input,
+            declarations: Declarations::new(),
+            errors: RefCell::new(Vec::new()),
+            print_errors: true,
+            interner: Interner::global(),
         }
     }
 
+    /// Set the interner names are interned in, the global one by default
+    pub fn with_interner(mut self, interner: &Interner) -> Self {
+        self.parser = self.parser.with_interner(interner);
+        self.interner = interner.clone();
+        self
+    }
+
+    /// Set whether errors are printed to stderr as they are found, which they are by default
+    pub fn with_printed_errors(mut self, print_errors: bool) -> Self {
+        self.parser = self.parser.with_printed_errors(print_errors);
+        self.print_errors = print_errors;
+        self
+    }
+
+    /// The errors found by the last parse, token errors included, in the order of the input
+    pub fn errors(&self) -> Vec<SyntaxError> {
+        let mut errors = self
+            .parser
+            .errors()
+            .iter()
+            .map(|parse_error| SyntaxError {
+                message: parse_error.message.clone(),
+                debug_info: parse_error.debug_info,
+            })
+            .chain(self.errors.borrow().iter().cloned())
+            .collect::<Vec<_>>();
+
+        errors.sort_by_key(|error| error.debug_info.index_location.0);
+        errors
+    }
+
     /// Parses the SyntaxParser's input
-    /// Returns a Vec of StateDefinition's
-    pub fn parse(&mut self) -> Vec<StateDefinition> {
-        let mut state_definitions: Vec<StateDefinition> = Vec::new();
+    /// Returns the Definitions declared by the input
+    pub fn parse(&mut self) -> Definitions {
+        let mut definitions = Definitions::with_interner(self.interner.clone());
 
         while let Some(token) = self.parser.get_next_token() {
             match token.kind.clone() {
                 TokenKind::Identifier(name) => {
-                    if name == *KEYWORD_STATE {
-                        let mut state_definition = self.parse_state_definition(token);
-                        state_definitions.append(&mut state_definition);
+                    if name == KEYWORD_STATE {
+                        for state_definition in self.parse_state_definition(token, false) {
+                            definitions.push_state(state_definition);
+                        }
+                    } else if name == KEYWORD_ENTRY {
+                        if let Some((entry_point, entry_token)) =
+                            self.parse_entry_declaration(token)
+                        {
+                            if self
+                                .declarations
+                                .entry_points
+                                .iter()
+                                .any(|(name, _)| *name == entry_point)
+                            {
+                                syntax_err(self, "Entry point is declared twice", &entry_token);
+                            } else {
+                                definitions.push_entry_point(entry_point);
+                                self.declarations
+                                    .entry_points
+                                    .push((entry_point, entry_token));
+                            }
+                        }
+                    } else if name == KEYWORD_KEYWORDS {
+                        if let Some((keyword_definition, state_token)) =
+                            self.parse_keywords_definition(token)
+                        {
+                            if self
+                                .declarations
+                                .keywords
+                                .iter()
+                                .any(|(state, _)| *state == keyword_definition.state)
+                            {
+                                syntax_err(
+                                    self,
+                                    "Keywords are already declared for this state",
+                                    &state_token,
+                                );
+                            } else {
+                                self.declarations
+                                    .keywords
+                                    .push((keyword_definition.state, state_token));
+                                definitions.push_keywords(keyword_definition);
+                            }
+                        }
+                    } else if name == KEYWORD_PRIORITY {
+                        for (higher, lower, priority_token) in
+                            self.parse_priority_declaration(token)
+                        {
+                            definitions.push_priority(higher, lower);
+                            self.declarations.priorities.push(priority_token);
+                        }
                     } else {
                         syntax_err(self, "Could not start a definition with", &token)
                     }
                 }
+                TokenKind::Hash => {
+                    if let Some(attribute) = self.parse_attribute(&token) {
+                        if attribute.name != KEYWORD_CASE_INSENSITIVE
+                            || attribute.argument.is_some()
+                        {
+                            syntax_err(self, "Attribute can't be used here", &attribute.token);
+                            continue;
+                        }
+
+                        match self.parser.get_next_token() {
+                            Some(state_token) => {
+                                if state_token.kind == TokenKind::Identifier(KEYWORD_STATE) {
+                                    for state_definition in
+                                        self.parse_state_definition(state_token, true)
+                                    {
+                                        definitions.push_state(state_definition);
+                                    }
+                                } else {
+                                    syntax_err(
+                                        self,
+                                        "Expected a state after attribute",
+                                        &state_token,
+                                    );
+                                }
+                            }
+                            None => {
+                                syntax_err(self, "Expected a state after", &attribute.token);
+                            }
+                        }
+                    }
+                }
                 _ => {
                     syntax_err(self, "Did not expect at start of definition", &token);
                 }
             }
         }
 
-        return state_definitions;
+        let declarations = mem::replace(&mut self.declarations, Declarations::new());
+        check_definitions(&mut definitions, &declarations, |message, token| {
+            syntax_err(self, message, token)
+        });
+
+        definitions
+    }
+
+    /// Check that a literal has the same alphabet as the literals before it
+    fn check_alphabet(&mut self, alphabet: Alphabet, token: &Token) {
+        if !self.declarations.check_alphabet(alphabet, token.clone()) {
+            syntax_err(
+                self,
+                "Byte and char literals can't be mixed in one machine",
+                token,
+            );
+        }
+    }
+
+    /// Parse a priority declaration such as `priority KEYWORD_SELF > IDENTIFIER;`
+    /// Declarations can be chained as in `priority A > B > C;`
+    /// Returns each pair of tokens along with the token that named the higher one
+    fn parse_priority_declaration(
+        &mut self,
+        token: Token,
+    ) -> Vec<(InternedString, InternedString, Token)> {
+        let mut result = Vec::new();
+        let mut previous: Option<(InternedString, Token)> = None;
+
+        loop {
+            let name_token = if let Some(name_token) = self.parser.get_next_token() {
+                name_token
+            } else {
+                syntax_err(self, "Expected a token name after", &token);
+                break;
+            };
+
+            let name = if let TokenKind::Identifier(name) = name_token.kind {
+                name
+            } else {
+                syntax_err(self, "Expected a token name for priority", &name_token);
+                break;
+            };
+
+            if let Some((higher, higher_token)) = previous {
+                result.push((higher, name, higher_token));
+            }
+
+            match self.parser.get_next_token() {
+                Some(Token {
+                    kind: TokenKind::GreaterThan,
+                    ..
+                }) => {
+                    previous = Some((name, name_token));
+                }
+                Some(Token {
+                    kind: TokenKind::SemiColumn,
+                    ..
+                }) => {
+                    if result.is_empty() {
+                        syntax_err(self, "Priority needs two tokens", &name_token);
+                    }
+                    break;
+                }
+                Some(other_token) => {
+                    syntax_err(self, "Expected '>' or ';' here", &other_token);
+                    break;
+                }
+                None => {
+                    syntax_err(self, "Expected ';' after priority", &name_token);
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Parse an attribute such as `#[priority(10)]`, given its `#` token
+    fn parse_attribute(&mut self, hash_token: &Token) -> Option<Attribute> {
+        match self.parser.get_next_token() {
+            Some(Token {
+                kind: TokenKind::Bracket(ScopeType::Open),
+                ..
+            }) => {}
+            Some(other_token) => {
+                syntax_err(self, "Expected '[' here", &other_token);
+                return None;
+            }
+            None => {
+                syntax_err(self, "Expected '[' after", hash_token);
+                return None;
+            }
+        }
+
+        let name_token = if let Some(name_token) = self.parser.get_next_token() {
+            name_token
+        } else {
+            syntax_err(self, "Expected an attribute name after", hash_token);
+            return None;
+        };
+
+        let name = if let TokenKind::Identifier(name) = name_token.kind {
+            name
+        } else {
+            syntax_err(self, "Expected an attribute name", &name_token);
+            return None;
+        };
+
+        let mut argument = None;
+        let mut next_token = self.parser.get_next_token();
+
+        if let Some(Token {
+            kind: TokenKind::Parenthesis(ScopeType::Open),
+            ..
+        }) = next_token
+        {
+            match self.parser.get_next_token() {
+                Some(Token {
+                    kind: TokenKind::Integer(value),
+                    ..
+                }) => argument = Some(value),
+                Some(other_token) => {
+                    syntax_err(self, "Expected an integer argument", &other_token);
+                    return None;
+                }
+                None => {
+                    syntax_err(self, "Expected an integer argument after", &name_token);
+                    return None;
+                }
+            }
+
+            match self.parser.get_next_token() {
+                Some(Token {
+                    kind: TokenKind::Parenthesis(ScopeType::Close),
+                    ..
+                }) => {}
+                Some(other_token) => {
+                    syntax_err(self, "Expected ')' here", &other_token);
+                    return None;
+                }
+                None => {
+                    syntax_err(self, "Expected ')' after", &name_token);
+                    return None;
+                }
+            }
+
+            next_token = self.parser.get_next_token();
+        }
+
+        match next_token {
+            Some(Token {
+                kind: TokenKind::Bracket(ScopeType::Close),
+                ..
+            }) => {}
+            Some(other_token) => {
+                syntax_err(self, "Expected ']' here", &other_token);
+                return None;
+            }
+            None => {
+                syntax_err(self, "Attribute has no closing ']'", &name_token);
+                return None;
+            }
+        }
+
+        Some(Attribute {
+            name,
+            argument,
+            token: name_token,
+        })
+    }
+
+    /// Parse an entry declaration such as `entry start;`
+    /// Returns the entry state's name along with the token that named it
+    fn parse_entry_declaration(&mut self, token: Token) -> Option<(InternedString, Token)> {
+        let name_token = if let Some(name_token) = self.parser.get_next_token() {
+            name_token
+        } else {
+            syntax_err(self, "Expected a state name after", &token);
+            return None;
+        };
+
+        let name = if let TokenKind::Identifier(name) = name_token.kind {
+            name
+        } else {
+            syntax_err(self, "Expected a state name for entry point", &name_token);
+            return None;
+        };
+
+        match self.parser.get_next_token() {
+            Some(Token {
+                kind: TokenKind::SemiColumn,
+                ..
+            }) => {}
+            Some(other_token) => {
+                syntax_err(self, "Expected ';' after entry point", &other_token);
+            }
+            None => {
+                syntax_err(self, "Expected ';' after entry point", &name_token);
+            }
+        }
+
+        Some((name, name_token))
+    }
+
+    /// Parse a keyword table such as `keywords identifier { "fn" => KW_FN, "if" => KW_IF }`
+    /// Returns the table along with the token that named its state
+    fn parse_keywords_definition(&mut self, token: Token) -> Option<(KeywordDefinition, Token)> {
+        let state_token = if let Some(state_token) = self.parser.get_next_token() {
+            state_token
+        } else {
+            syntax_err(self, "Expected a state name after", &token);
+            return None;
+        };
+
+        let state = if let TokenKind::Identifier(state) = state_token.kind {
+            state
+        } else {
+            syntax_err(self, "Expected a state name for keywords", &state_token);
+            return None;
+        };
+
+        match self.parser.get_next_token() {
+            Some(Token {
+                kind: TokenKind::Scope(ScopeType::Open),
+                ..
+            }) => {}
+            Some(other_token) => {
+                syntax_err(self, "Expected an open token here", &other_token);
+                return None;
+            }
+            None => {
+                syntax_err(self, "Expected an open token after", &state_token);
+                return None;
+            }
+        }
+
+        let mut keyword_definition = KeywordDefinition::new(state);
+
+        'keywords: loop {
+            let keyword_token = if let Some(keyword_token) = self.parser.get_next_token() {
+                keyword_token
+            } else {
+                syntax_err(self, "Keywords have no closing token", &state_token);
+                break 'keywords;
+            };
+
+            let keyword = match keyword_token.kind {
+                TokenKind::Scope(ScopeType::Close) => break 'keywords,
+                TokenKind::Comma => continue 'keywords,
+                TokenKind::CharSequence(ref keyword) => {
+                    self.check_alphabet(Alphabet::Chars, &keyword_token);
+                    keyword.iter().collect::<String>()
+                }
+                TokenKind::ByteSequence(ref keyword) => {
+                    self.check_alphabet(Alphabet::Bytes, &keyword_token);
+                    keyword.iter().map(|&byte| char::from(byte)).collect::<String>()
+                }
+                _ => {
+                    syntax_err(self, "Expected a keyword string", &keyword_token);
+                    continue 'keywords;
+                }
+            };
+
+            match self.parser.get_next_token() {
+                Some(Token {
+                    kind: TokenKind::Arrow,
+                    ..
+                }) => {}
+                Some(other_token) => {
+                    syntax_err(self, "Expected '=>' here", &other_token);
+                    continue 'keywords;
+                }
+                None => {
+                    syntax_err(self, "Expected '=>' after", &keyword_token);
+                    break 'keywords;
+                }
+            }
+
+            match self.parser.get_next_token() {
+                Some(Token {
+                    kind: TokenKind::Identifier(keyword_kind),
+                    ..
+                }) => {
+                    if keyword_definition
+                        .keywords
+                        .iter()
+                        .any(|(existing_keyword, _)| *existing_keyword == keyword)
+                    {
+                        syntax_err(self, "Keyword is declared twice", &keyword_token);
+                    } else {
+                        keyword_definition.push_keyword(keyword, keyword_kind);
+                    }
+                }
+                Some(other_token) => {
+                    syntax_err(self, "Expected a token name for keyword", &other_token);
+                }
+                None => {
+                    syntax_err(self, "Expected a token name after", &keyword_token);
+                    break 'keywords;
+                }
+            }
+        }
+
+        Some((keyword_definition, state_token))
     }
 
     /// Parse a StateDefinition given a token and a name
+    /// Every arm of a case insensitive state is case insensitive
     fn parse_state_definition(
         &mut self,
         token: Token,
+        case_insensitive: bool,
     ) -> Vec<StateDefinition> {
 
         let name = if let Some(token) = self.parser.get_next_token() {
@@ -76,15 +519,16 @@ impl<'input> SyntaxParser<'input> {
                     identifier
                 }
                 _ => {
-                    intern("")
+                    self.interner.intern("")
                 }
             }
         } else {
             syntax_err(self, "Expected a state name after", &token);
-            intern("no name provided")
+            self.interner.intern("no name provided")
         };
 
         let mut current_state_definition = StateDefinition::new(name);
+        let mut arm_tokens = Vec::new();
         let mut result = Vec::new();
 
         let open_token = self.parser.get_next_token();
@@ -94,12 +538,45 @@ impl<'input> SyntaxParser<'input> {
                 ..
             } = open_token
             {
+                let mut priority: Option<(i32, Token)> = None;
+
                 'statements: loop {
                     let (match_statements, next_token) = self.parse_left_side_inputs();
                     if match_statements.is_empty() {
                         if let Some(next_token) = next_token {
                             match next_token.kind.clone() {
+                                TokenKind::Hash => {
+                                    if let Some(attribute) = self.parse_attribute(&next_token) {
+                                        match attribute.argument {
+                                            Some(value) if attribute.name == KEYWORD_PRIORITY => {
+                                                priority = Some((value, attribute.token));
+                                            }
+                                            None if attribute.name == KEYWORD_PRIORITY => {
+                                                syntax_err(
+                                                    self,
+                                                    "Priority needs a value",
+                                                    &attribute.token,
+                                                );
+                                            }
+                                            _ => {
+                                                syntax_err(
+                                                    self,
+                                                    "Unknown arm attribute",
+                                                    &attribute.token,
+                                                );
+                                            }
+                                        }
+                                    }
+                                    continue 'statements;
+                                }
                                 TokenKind::Scope(ScopeType::Close) => {
+                                    if let Some((_, ref priority_token)) = priority {
+                                        syntax_err(
+                                            self,
+                                            "Priority is not followed by an arm",
+                                            priority_token,
+                                        );
+                                    }
                                     // This is ok, but we don't do anything since we want to break even on error
                                 }
                                 _ => {
@@ -112,16 +589,69 @@ impl<'input> SyntaxParser<'input> {
                         break 'statements;
                     }
 
+                    let (context, next_token) = match next_token {
+                        Some(ref slash_token) if slash_token.kind == TokenKind::Slash => {
+                            let (context, next_token) = self.parse_trailing_context(slash_token);
+
+                            if context.is_some()
+                                && match_statements.iter().any(|match_statement| {
+                                    matches!(
+                                        match_statement.0,
+                                        StatementMatchKind::Default
+                                            | StatementMatchKind::EndOfInput
+                                    )
+                                }) {
+                                syntax_err(
+                                    self,
+                                    "Trailing context can only follow chars, ranges and sequences",
+                                    slash_token,
+                                );
+                            }
+
+                            (context, next_token)
+                        }
+                        next_token => (None, next_token),
+                    };
+
                     if let Some(next_token) = next_token {
+                        let lookahead = match next_token.kind {
+                            TokenKind::ConsumingArrow => Lookahead::Consume,
+                            _ => Lookahead::Keep,
+                        };
+
                         match next_token.kind.clone() {
-                            TokenKind::Arrow => {
+                            TokenKind::Arrow | TokenKind::ConsumingArrow => {
+                                let arm_priority = match priority.take() {
+                                    Some((value, _)) => value,
+                                    None => 0,
+                                };
+
                                 if let Some(destination) = self.parse_destination(&next_token) {
-                                    match_statements.into_iter().for_each(|match_statement| {
-                                        current_state_definition.push_statement(Statement::new(
-                                            destination,
-                                            match_statement,
-                                        ));
-                                    })
+                                    if lookahead == Lookahead::Consume && !ends_token(&destination)
+                                    {
+                                        syntax_err(
+                                            self,
+                                            "Only a return or an error can consume its char with '=>>'",
+                                            &next_token,
+                                        );
+                                    }
+
+                                    for (match_statement, insensitive_input, input_span) in
+                                        match_statements
+                                    {
+                                        let mut statement =
+                                            Statement::new(destination, match_statement, lookahead)
+                                                .with_priority(arm_priority)
+                                                .with_span(input_span);
+                                        statement.context = context.clone();
+
+                                        if case_insensitive || insensitive_input {
+                                            statement = statement.case_insensitive();
+                                        }
+
+                                        current_state_definition.push_statement(statement);
+                                        arm_tokens.push(next_token.clone());
+                                    }
                                 } else {
                                     syntax_err(
                                         self,
@@ -131,7 +661,7 @@ impl<'input> SyntaxParser<'input> {
                                 }
                             }
                             _ => {
-                                syntax_err(self, "Expected '=>' here", &next_token);
+                                syntax_err(self, "Expected '=>' or '=>>' here", &next_token);
                             }
                         }
                     } else {
@@ -153,18 +683,22 @@ impl<'input> SyntaxParser<'input> {
             syntax_err(self, "Expected an open token after", &token);
         }
 
+        self.declarations.arms.insert(name, arm_tokens);
         result.push(current_state_definition);
         return result;
     }
 
-    fn parse_left_side_inputs(&mut self) -> (Vec<StatementMatchKind>, Option<Token>) {
+    /// Parse the match kinds of an arm, separated by `|`
+    /// Returns each match kind with whether it was written case insensitive and where, along
+    /// with the token that ended them
+    fn parse_left_side_inputs(&mut self) -> (Vec<(StatementMatchKind, bool, Span)>, Option<Token>) {
         let mut result = Vec::new();
 
         let mut buffered_match_kinds = Vec::new();
 
         'input_loop: while let Some(token) = self.parser.get_next_token() {
             match token.kind.clone() {
-                TokenKind::Arrow => {
+                TokenKind::Arrow | TokenKind::ConsumingArrow | TokenKind::Slash => {
                     result.append(&mut buffered_match_kinds);
                     return (result, Some(token));
                 }
@@ -172,37 +706,126 @@ impl<'input> SyntaxParser<'input> {
                     if !buffered_match_kinds.is_empty() {
                         syntax_err(self, "| required between match kinds", &token);
                     }
-                    buffered_match_kinds.push(StatementMatchKind::Default);
+                    buffered_match_kinds.push((
+                        StatementMatchKind::Default,
+                        false,
+                        span(&token),
+                    ));
+                }
+                TokenKind::Identifier(identifier) if identifier == KEYWORD_EOF => {
+                    if !buffered_match_kinds.is_empty() {
+                        syntax_err(self, "| required between match kinds", &token);
+                    }
+                    buffered_match_kinds.push((
+                        StatementMatchKind::EndOfInput,
+                        false,
+                        span(&token),
+                    ));
                 }
                 TokenKind::Char(chr) => {
                     if !buffered_match_kinds.is_empty() {
                         syntax_err(self, "| required between match kinds", &token);
                     }
-                    buffered_match_kinds.push(StatementMatchKind::Literal(chr));
+                    self.check_alphabet(Alphabet::Chars, &token);
+                    buffered_match_kinds.push((
+                        StatementMatchKind::Literal(chr),
+                        false,
+                        span(&token),
+                    ));
+                }
+                TokenKind::InsensitiveChar(chr) => {
+                    if !buffered_match_kinds.is_empty() {
+                        syntax_err(self, "| required between match kinds", &token);
+                    }
+                    self.check_alphabet(Alphabet::Chars, &token);
+                    buffered_match_kinds.push((
+                        StatementMatchKind::Literal(chr),
+                        true,
+                        span(&token),
+                    ));
+                }
+                TokenKind::Byte(byte) => {
+                    if !buffered_match_kinds.is_empty() {
+                        syntax_err(self, "| required between match kinds", &token);
+                    }
+                    self.check_alphabet(Alphabet::Bytes, &token);
+                    buffered_match_kinds.push((
+                        StatementMatchKind::Literal(char::from(byte)),
+                        false,
+                        span(&token),
+                    ));
                 }
                 TokenKind::CharSequence(sequence) => {
                     if !buffered_match_kinds.is_empty() {
                         syntax_err(self, "| required between match kinds", &token);
                     }
-                    buffered_match_kinds.push(StatementMatchKind::Sequence(sequence));
+                    self.check_alphabet(Alphabet::Chars, &token);
+                    buffered_match_kinds.push((
+                        StatementMatchKind::Sequence(sequence),
+                        false,
+                        span(&token),
+                    ));
+                }
+                TokenKind::InsensitiveCharSequence(sequence) => {
+                    if !buffered_match_kinds.is_empty() {
+                        syntax_err(self, "| required between match kinds", &token);
+                    }
+                    self.check_alphabet(Alphabet::Chars, &token);
+                    buffered_match_kinds.push((
+                        StatementMatchKind::Sequence(sequence),
+                        true,
+                        span(&token),
+                    ));
+                }
+                TokenKind::ByteSequence(sequence) => {
+                    if !buffered_match_kinds.is_empty() {
+                        syntax_err(self, "| required between match kinds", &token);
+                    }
+                    self.check_alphabet(Alphabet::Bytes, &token);
+                    let sequence = sequence.into_iter().map(char::from).collect();
+                    buffered_match_kinds.push((
+                        StatementMatchKind::Sequence(sequence),
+                        false,
+                        span(&token),
+                    ));
                 }
                 TokenKind::Range => {
                     if buffered_match_kinds.len() > 1 {
                         syntax_err(self, "Cannot apply range on multiple literals", &token);
                     }
 
-                    if let Some(range_open) =
-                        buffered_match_kinds.get(buffered_match_kinds.len() - 1)
+                    if let Some(&(ref range_open, insensitive, open_span)) =
+                        buffered_match_kinds.last()
                     {
                         match range_open {
                             StatementMatchKind::Literal(range_open) => {
                                 if let Some(range_close) = self.parser.get_next_token() {
-                                    match range_close.kind.clone() {
-                                        TokenKind::Char(range_close) => {
+                                    let range_close_span = span(&range_close);
+                                    let range_close_char = match range_close.kind {
+                                        TokenKind::Char(chr) => {
+                                            self.check_alphabet(Alphabet::Chars, &range_close);
+                                            Some(chr)
+                                        }
+                                        TokenKind::Byte(byte) => {
+                                            self.check_alphabet(Alphabet::Bytes, &range_close);
+                                            Some(char::from(byte))
+                                        }
+                                        _ => None,
+                                    };
+
+                                    match range_close_char {
+                                        Some(range_close) => {
                                             let range = CharRange::new(*range_open, range_close);
+                                            let range_span = Span {
+                                                end: range_close_span.end,
+                                                ..open_span
+                                            };
                                             let _ = buffered_match_kinds.pop();
-                                            buffered_match_kinds
-                                                .push(StatementMatchKind::Range(range));
+                                            buffered_match_kinds.push((
+                                                StatementMatchKind::Range(range),
+                                                insensitive,
+                                                range_span,
+                                            ));
                                         }
                                         _ => {
                                             syntax_err(
@@ -240,6 +863,67 @@ impl<'input> SyntaxParser<'input> {
         return (result, None);
     }
 
+    /// Parse the context that follows a `/` in a match declaration, up to the arrow
+    /// Returns the context along with the token that ended it
+    fn parse_trailing_context(
+        &mut self,
+        slash_token: &Token,
+    ) -> (Option<TrailingContext>, Option<Token>) {
+        let (mut alternatives, mut next_token) = self.parse_left_side_inputs();
+        let mut negated = false;
+
+        if alternatives.is_empty() {
+            if let Some(Token {
+                kind: TokenKind::Exclamation,
+                ..
+            }) = next_token
+            {
+                negated = true;
+                let (negated_alternatives, token) = self.parse_left_side_inputs();
+                alternatives = negated_alternatives;
+                next_token = token;
+            }
+        }
+
+        if alternatives.is_empty() {
+            syntax_err(self, "Expected a trailing context after", slash_token);
+            return (None, next_token);
+        }
+
+        if alternatives.iter().any(|&(_, insensitive, _)| insensitive) {
+            syntax_err(self, "Trailing context can't be case insensitive", slash_token);
+        }
+
+        let alternatives = alternatives
+            .into_iter()
+            .map(|(alternative, _, _)| alternative)
+            .collect::<Vec<_>>();
+
+        for alternative in &alternatives {
+            match *alternative {
+                StatementMatchKind::Default | StatementMatchKind::EndOfInput => {
+                    syntax_err(
+                        self,
+                        "Trailing context can only contain chars, ranges and sequences",
+                        slash_token,
+                    );
+                    return (None, next_token);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(Token {
+            kind: TokenKind::Slash,
+            ..
+        }) = next_token
+        {
+            syntax_err(self, "Only one trailing context is allowed", slash_token);
+        }
+
+        (Some(TrailingContext::new(negated, alternatives)), next_token)
+    }
+
     fn parse_destination(&mut self, token: &Token) -> Option<Destination> {
         if let Some(destination_token) = self.parser.get_next_token() {
             if let Token {
@@ -247,7 +931,7 @@ impl<'input> SyntaxParser<'input> {
                 ..
             } = destination_token
             {
-                if destination == *KEYWORD_RETURN {
+                if destination == KEYWORD_RETURN {
                     if let Some(return_identifier_token) = self.parser.get_next_token() {
                         if let Token {
                             kind: TokenKind::Identifier(return_identifier),
@@ -265,6 +949,37 @@ impl<'input> SyntaxParser<'input> {
                     } else {
                         syntax_err(self, "Expected identifier after return", &destination_token);
                     }
+                } else if destination == KEYWORD_PUSH {
+                    if let Some(mode_token) = self.parser.get_next_token() {
+                        if let Token {
+                            kind: TokenKind::Identifier(mode),
+                            ..
+                        } = mode_token
+                        {
+                            return Some(Destination::Push(mode));
+                        } else {
+                            syntax_err(self, "Expected state name after push", &mode_token);
+                        }
+                    } else {
+                        syntax_err(self, "Expected state name after push", &destination_token);
+                    }
+                } else if destination == KEYWORD_POP {
+                    return Some(Destination::Pop);
+                } else if destination == KEYWORD_ERROR {
+                    if let Some(message_token) = self.parser.get_next_token() {
+                        if let Token {
+                            kind: TokenKind::CharSequence(ref message),
+                            ..
+                        } = message_token
+                        {
+                            let message = message.iter().collect::<String>();
+                            return Some(Destination::Error(self.interner.intern(message)));
+                        } else {
+                            syntax_err(self, "Expected a message after error", &message_token);
+                        }
+                    } else {
+                        syntax_err(self, "Expected a message after error", &destination_token);
+                    }
                 } else {
                     return Some(Destination::State(destination));
                 }
@@ -278,3 +993,432 @@ impl<'input> SyntaxParser<'input> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse some input, giving back its definitions and its errors as (message, line, column)
+    fn parse(input: &str) -> (Definitions, Vec<(String, usize, usize)>) {
+        let mut parser = SyntaxParser::new(input).with_printed_errors(false);
+        let definitions = parser.parse();
+        let errors = parser
+            .errors()
+            .into_iter()
+            .map(|error| {
+                (
+                    error.message,
+                    error.debug_info.line_location.0,
+                    error.debug_info.column_location.0,
+                )
+            })
+            .collect();
+
+        (definitions, errors)
+    }
+
+    #[test]
+    fn entry_declaration_test() {
+        let (definitions, errors) = parse(
+            "entry default;\n\
+             entry inside_template;\n\
+             state inside_template { _ => return TEXT }\n\
+             state default { _ => return TEXT }",
+        );
+        assert_eq!(errors, Vec::new());
+        assert_eq!(
+            definitions.entry_points,
+            vec![intern("default"), intern("inside_template")]
+        );
+
+        let (definitions, errors) = parse("state start { _ => return TEXT }");
+        assert_eq!(errors, Vec::new());
+        assert_eq!(definitions.entry_points, Vec::new());
+
+        let (definitions, errors) = parse(
+            "entry start;\n\
+             entry missing;\n\
+             entry start;\n\
+             state start { _ => return TEXT }",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                ("Entry point refers to an undefined state".to_string(), 1, 7),
+                ("Entry point is declared twice".to_string(), 2, 7),
+            ]
+        );
+        assert_eq!(
+            definitions.entry_points,
+            vec![intern("start"), intern("missing")]
+        );
+    }
+
+    #[test]
+    fn keywords_test() {
+        let (definitions, errors) = parse(
+            "keywords identifier {\n\
+             \"fn\" => KEYWORD_FN\n\
+             \"fn\" => KEYWORD_FUNCTION\n\
+             \"let\" => KEYWORD_LET\n\
+             }\n\
+             keywords identifier { \"if\" => KEYWORD_IF }\n\
+             state identifier {\n\
+             'a'..'z' => Self\n\
+             _ => return IDENTIFIER\n\
+             }",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                ("Keyword is declared twice".to_string(), 2, 0),
+                (
+                    "Keywords are already declared for this state".to_string(),
+                    5,
+                    10
+                ),
+            ]
+        );
+        assert_eq!(definitions.keywords.len(), 1);
+        assert_eq!(definitions.keywords[0].state, intern("identifier"));
+        assert_eq!(
+            definitions.keywords[0].keywords,
+            vec![
+                ("fn".to_string(), intern("KEYWORD_FN")),
+                ("let".to_string(), intern("KEYWORD_LET")),
+            ]
+        );
+    }
+
+    #[test]
+    fn push_pop_test() {
+        let (definitions, errors) = parse(
+            "state start {\n\
+             '{' => push Self\n\
+             '}' => pop\n\
+             '\"' => push string\n\
+             }\n\
+             state string { _ => Self }",
+        );
+        assert_eq!(errors, Vec::new());
+        assert_eq!(
+            definitions.states[0]
+                .statements
+                .iter()
+                .map(|statement| statement.destination)
+                .collect::<Vec<_>>(),
+            vec![
+                Destination::Push(KEYWORD_SELF),
+                Destination::Pop,
+                Destination::Push(intern("string")),
+            ]
+        );
+
+        let (_, errors) = parse("state start {\n'a' => push 'b'\n}");
+        assert_eq!(
+            errors,
+            vec![
+                ("Could find valid destination after ".to_string(), 1, 5),
+                ("Expected state name after push".to_string(), 1, 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_destination_test() {
+        let (definitions, errors) = parse(
+            "state start {\n\
+             '\"' => string\n\
+             }\n\
+             state string {\n\
+             '\"' => return STRING\n\
+             eof => error \"Unterminated string\"\n\
+             _ => Self\n\
+             }",
+        );
+        assert_eq!(errors, Vec::new());
+        assert_eq!(
+            definitions.states[1].statements[1].destination,
+            Destination::Error(intern("Unterminated string"))
+        );
+
+        let (_, errors) = parse("state start {\neof => error UNTERMINATED\n}");
+        assert_eq!(
+            errors,
+            vec![
+                ("Could find valid destination after ".to_string(), 1, 5),
+                ("Expected a message after error".to_string(), 1, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn consuming_arrow_test() {
+        let (definitions, errors) = parse(
+            "state start {\n\
+             '(' =>> return OPEN\n\
+             eof =>> error \"Expected a parenthesis\"\n\
+             _ => return OTHER\n\
+             }",
+        );
+        assert_eq!(errors, Vec::new());
+        assert_eq!(
+            definitions.states[0]
+                .statements
+                .iter()
+                .map(|statement| statement.lookahead)
+                .collect::<Vec<_>>(),
+            vec![Lookahead::Consume, Lookahead::Consume, Lookahead::Keep]
+        );
+
+        let (_, errors) = parse(
+            "state start {\n\
+             'a' =>> word\n\
+             '{' =>> push start\n\
+             '}' =>> pop\n\
+             }\n\
+             state word { _ => return WORD }",
+        );
+        let message = "Only a return or an error can consume its char with '=>>'".to_string();
+        assert_eq!(
+            errors,
+            vec![
+                (message.clone(), 1, 5),
+                (message.clone(), 2, 5),
+                (message, 3, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_context_test() {
+        let (definitions, errors) = parse(
+            "state start {\n\
+             '-' / '>' =>> return ARROW\n\
+             '-' / !'0'..'9' | \"--\" => return MINUS\n\
+             _ => return OTHER\n\
+             }",
+        );
+        assert_eq!(errors, Vec::new());
+        assert_eq!(
+            definitions.states[0]
+                .statements
+                .iter()
+                .map(|statement| statement.context.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some(TrailingContext::new(
+                    false,
+                    vec![StatementMatchKind::Literal('>')]
+                )),
+                Some(TrailingContext::new(
+                    true,
+                    vec![
+                        StatementMatchKind::Range(CharRange::new('0', '9')),
+                        StatementMatchKind::Sequence(vec!['-', '-']),
+                    ]
+                )),
+                None,
+            ]
+        );
+
+        let (_, errors) = parse("state start {\n'-' / '>' / '=' => return ARROW\n}");
+        assert_eq!(
+            errors,
+            vec![
+                ("Only one trailing context is allowed".to_string(), 1, 5),
+                ("Expected '=>' or '=>>' here".to_string(), 1, 13),
+            ]
+        );
+
+        let (_, errors) = parse(
+            "state start {\n\
+             _ / 'a' => return OTHER\n\
+             eof / 'a' => return END\n\
+             'a' / _ => return A\n\
+             }",
+        );
+        let follow_message = "Trailing context can only follow chars, ranges and sequences";
+        assert_eq!(
+            errors,
+            vec![
+                (follow_message.to_string(), 1, 3),
+                (follow_message.to_string(), 2, 5),
+                (
+                    "Trailing context can only contain chars, ranges and sequences".to_string(),
+                    3,
+                    5
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn priority_test() {
+        let (definitions, errors) = parse(
+            "priority KEYWORD_IF > IDENTIFIER > NAME;\n\
+             state start {\n\
+             \"if\" => return KEYWORD_IF\n\
+             #[priority(2)]\n\
+             'a'..'z' => return IDENTIFIER\n\
+             'A'..'Z' => return NAME\n\
+             }",
+        );
+        assert_eq!(errors, Vec::new());
+        assert_eq!(
+            definitions.priorities,
+            vec![
+                (intern("KEYWORD_IF"), intern("IDENTIFIER")),
+                (intern("IDENTIFIER"), intern("NAME")),
+            ]
+        );
+        assert_eq!(
+            definitions.states[0]
+                .statements
+                .iter()
+                .map(|statement| statement.priority)
+                .collect::<Vec<_>>(),
+            vec![0, 2, 0]
+        );
+
+        let (definitions, errors) = parse(
+            "priority IDENTIFIER;\n\
+             state start {\n\
+             'a'..'z' => return IDENTIFIER\n\
+             #[priority(1)]\n\
+             }",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                ("Priority needs two tokens".to_string(), 0, 10),
+                ("Priority is not followed by an arm".to_string(), 3, 2),
+            ]
+        );
+        assert_eq!(definitions.priorities, Vec::new());
+    }
+
+    #[test]
+    fn case_insensitive_test() {
+        let (definitions, errors) = parse(
+            "state start {\n\
+             i'x' => return X\n\
+             i\"select\" => return SELECT\n\
+             'y' => return Y\n\
+             '#' => comment\n\
+             }\n\
+             #[case_insensitive]\n\
+             state comment {\n\
+             \"end\" => return COMMENT\n\
+             _ => Self\n\
+             }",
+        );
+        assert_eq!(errors, Vec::new());
+
+        let statements = definitions
+            .states
+            .iter()
+            .flat_map(|state| state.statements.iter())
+            .map(|statement| (statement.match_kind.clone(), statement.case_insensitive))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statements,
+            vec![
+                (StatementMatchKind::Literal('x'), true),
+                (
+                    StatementMatchKind::Sequence("select".chars().collect()),
+                    true
+                ),
+                (StatementMatchKind::Literal('y'), false),
+                (StatementMatchKind::Literal('#'), false),
+                (StatementMatchKind::Sequence(vec!['e', 'n', 'd']), true),
+                (StatementMatchKind::Default, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn byte_alphabet_test() {
+        let (definitions, errors) = parse(
+            "state start {\n\
+             b'\\x00' => return NULL\n\
+             b\"\\xDE\\xAD\" => return MAGIC\n\
+             0x01..0x7F => return BYTE\n\
+             }",
+        );
+        assert_eq!(errors, Vec::new());
+        assert_eq!(definitions.alphabet, Alphabet::Bytes);
+        assert_eq!(
+            definitions.states[0]
+                .statements
+                .iter()
+                .map(|statement| statement.match_kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                StatementMatchKind::Literal('\u{0}'),
+                StatementMatchKind::Sequence(vec!['\u{DE}', '\u{AD}']),
+                StatementMatchKind::Range(CharRange::new('\u{1}', '\u{7F}')),
+            ]
+        );
+
+        let (definitions, errors) = parse(
+            "state start {\n\
+             b'\\x00' => return NULL\n\
+             'a' => return A\n\
+             }",
+        );
+        assert_eq!(
+            errors,
+            vec![(
+                "Byte and char literals can't be mixed in one machine".to_string(),
+                2,
+                0
+            )]
+        );
+        assert_eq!(definitions.alphabet, Alphabet::Bytes);
+    }
+
+    #[test]
+    fn interner_test() {
+        let interner = Interner::new();
+        let mut parser = SyntaxParser::new("state start { _ => return OWN_TOKEN }")
+            .with_printed_errors(false)
+            .with_interner(&interner);
+        let definitions = parser.parse();
+
+        assert_eq!(parser.errors(), Vec::new());
+        assert_eq!(definitions.interner, interner);
+        assert_eq!(definitions.states[0].name, KEYWORD_START);
+
+        let token = match definitions.states[0].statements[0].destination {
+            Destination::Return(token) => token,
+            ref destination => panic!("Expected a return, got {:?}", destination),
+        };
+        assert_eq!(
+            interner.with_str(token, str::to_string).unwrap(),
+            "OWN_TOKEN"
+        );
+        assert_eq!(Interner::new().with_str(token, str::to_string), None);
+    }
+
+    #[test]
+    fn replaced_arm_test() {
+        let (_, errors) = parse(
+            "state start {\n\
+             '-' => return MINUS\n\
+             \"->\" =>> return ARROW\n\
+             '=' =>> return EQUALS\n\
+             \"=>\" =>> return FAT_ARROW\n\
+             }",
+        );
+        assert_eq!(
+            errors,
+            vec![(
+                "A longer sequence takes \"-\" over, and the arm can't be done once it's consumed"
+                    .to_string(),
+                1,
+                5
+            )]
+        );
+    }
+}
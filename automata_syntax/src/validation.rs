@@ -0,0 +1,169 @@
+//! Checks run once all the definitions of a machine are known
+//!
+//! They are shared by `SyntaxParser` and `AutomataBuilder`, which point the errors at different
+//! things: tokens of the input for the former, the calls that declared something for the latter.
+
+use automata::{definitions::*, priorities::*, statements::*};
+use automata_core::string_interning::*;
+use std::collections::HashMap;
+
+/// Where the parts of some definitions were declared
+pub(crate) struct Declarations<L> {
+    /// Entry points, in declaration order
+    pub entry_points: Vec<(InternedString, L)>,
+    /// States with keywords, in declaration order
+    pub keywords: Vec<(InternedString, L)>,
+    /// One for each token priority of the definitions
+    pub priorities: Vec<L>,
+    /// One for each statement, by state
+    pub arms: HashMap<InternedString, Vec<L>>,
+    /// The alphabet of the first literal, along with that literal
+    pub alphabet: Option<(Alphabet, L)>,
+}
+
+impl<L> Declarations<L> {
+    pub fn new() -> Self {
+        Self {
+            entry_points: Vec::new(),
+            keywords: Vec::new(),
+            priorities: Vec::new(),
+            arms: HashMap::new(),
+            alphabet: None,
+        }
+    }
+
+    /// Record the alphabet of a literal
+    /// Returns false if the literals before it have another alphabet
+    pub fn check_alphabet(&mut self, alphabet: Alphabet, location: L) -> bool {
+        match self.alphabet {
+            Some((first_alphabet, _)) => first_alphabet == alphabet,
+            None => {
+                self.alphabet = Some((alphabet, location));
+                true
+            }
+        }
+    }
+}
+
+/// Whether a destination ends the token, the only ones `=>>` can give the char their arm matched
+pub(crate) fn ends_token(destination: &Destination) -> bool {
+    matches!(*destination, Destination::Return(_) | Destination::Error(_))
+}
+
+/// Check what refers to states and tokens, then report the arms that priorities leave ambiguous
+/// Sets the alphabet of the definitions
+pub(crate) fn check_definitions<L, F: FnMut(&str, &L)>(
+    definitions: &mut Definitions,
+    declarations: &Declarations<L>,
+    mut report: F,
+) {
+    let is_defined = |state: InternedString| {
+        definitions
+            .states
+            .iter()
+            .any(|state_definition| state_definition.name == state)
+    };
+
+    for (entry_point, location) in &declarations.entry_points {
+        if !is_defined(*entry_point) {
+            report("Entry point refers to an undefined state", location);
+        }
+    }
+
+    for (state, location) in &declarations.keywords {
+        if !is_defined(*state) {
+            report("Keywords refer to an undefined state", location);
+        }
+    }
+
+    check_priorities(definitions, declarations, &mut report);
+
+    if let Some((alphabet, ref location)) = declarations.alphabet {
+        definitions.alphabet = alphabet;
+
+        if alphabet == Alphabet::Bytes
+            && definitions.states.iter().any(|state_definition| {
+                state_definition
+                    .statements
+                    .iter()
+                    .any(|statement| statement.case_insensitive)
+            })
+        {
+            report("Byte machines can't be case insensitive", location);
+        }
+    }
+}
+
+fn check_priorities<L, F: FnMut(&str, &L)>(
+    definitions: &Definitions,
+    declarations: &Declarations<L>,
+    report: &mut F,
+) {
+    let priorities = TokenPriorities::new(&definitions.priorities);
+
+    if let Some(token) = priorities.find_cycle() {
+        let index = definitions
+            .priorities
+            .iter()
+            .position(|&(higher, lower)| higher == token || lower == token)
+            .unwrap();
+        report(
+            "Token priorities form a cycle",
+            &declarations.priorities[index],
+        );
+    }
+
+    for (index, &(higher, lower)) in definitions.priorities.iter().enumerate() {
+        for token in &[higher, lower] {
+            let is_returned = definitions.states.iter().any(|state_definition| {
+                state_definition
+                    .statements
+                    .iter()
+                    .any(|statement| statement.destination == Destination::Return(*token))
+            }) || definitions.keywords.iter().any(|keyword_definition| {
+                keyword_definition
+                    .keywords
+                    .iter()
+                    .any(|(_, keyword_token)| keyword_token == token)
+            });
+
+            if !is_returned {
+                report(
+                    "Priority refers to a token that is never returned",
+                    &declarations.priorities[index],
+                );
+            }
+        }
+    }
+
+    for state_definition in &definitions.states {
+        for conflict in select_arms(state_definition, &priorities).conflicts {
+            let (arm, message) = match conflict.kind {
+                ConflictKind::Ambiguous => (
+                    conflict.second,
+                    format!(
+                        "Arm overlaps an earlier arm on {:?} and no priority decides between them",
+                        conflict.input
+                    ),
+                ),
+                ConflictKind::Replaced(replaced) => (
+                    replaced,
+                    format!(
+                        "A longer sequence takes {:?} over, and the arm can't be done once it's \
+                         consumed",
+                        conflict.input
+                    ),
+                ),
+            };
+
+            let location = declarations
+                .arms
+                .get(&state_definition.name)
+                .and_then(|arms| arms.get(arm));
+
+            if let Some(location) = location {
+                report(&message, location);
+            }
+        }
+    }
+}
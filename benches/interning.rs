@@ -0,0 +1,268 @@
+//! Interning costs on a large automata file, run with `cargo +nightly bench`
+//!
+//! The file has a few thousand states, each with keywords, sequences sharing a prefix and tokens
+//! of its own, so names are interned on every identifier the parser reads and looked up whenever
+//! states are sorted or written out.
+//!
+//! The `baseline_*` benches run the same work on the interner names had before, a `HashMap` from
+//! owned strings behind a mutex, so the gain can be measured again on any machine.
+
+#![feature(test)]
+
+extern crate automata;
+extern crate automata_core;
+extern crate automata_syntax;
+extern crate test;
+
+use automata::definitions::Definitions;
+use automata::Automata;
+use automata_core::string_interning::*;
+use automata_syntax::SyntaxParser;
+use std::fmt::Write;
+use test::Bencher;
+
+const STATE_COUNT: usize = 500;
+
+/// An automata file with `state_count` states
+fn large_source(state_count: usize) -> String {
+    let mut source = "entry state_0;\n".to_string();
+
+    for index in 0..state_count {
+        let next = (index + 1) % state_count;
+        let _ = write!(
+            source,
+            r##"
+keywords state_{index} {{
+    "if_{index}" => KEYWORD_IF_{index}
+    "else_{index}" => KEYWORD_ELSE_{index}
+}}
+
+state state_{index} {{
+    'a'..'z' | '_' => state_{next}
+    '0'..'9' => Self
+    "#define_{index}" =>> return DEFINE_{index}
+    "#default_{index}" =>> return DEFAULT_{index}
+    '#' =>> return HASH_{index}
+    eof => return END
+    _ => return TOKEN_{index}
+}}
+"##,
+            index = index,
+            next = next
+        );
+    }
+
+    source
+}
+
+fn parse(source: &str, interner: &Interner) -> Definitions {
+    let mut parser = SyntaxParser::new(source)
+        .with_printed_errors(false)
+        .with_interner(interner);
+    let definitions = parser.parse();
+    assert!(parser.errors().is_empty());
+
+    definitions
+}
+
+fn resolve(source: &str, interner: &Interner) -> Automata {
+    Automata::resolve_from(parse(source, interner))
+}
+
+/// The names of the generated file's states
+fn state_names() -> Vec<String> {
+    (0..STATE_COUNT)
+        .map(|index| format!("state_{}", index))
+        .collect()
+}
+
+/// The interner as it was before names were `u32` found by `&str` without a lock
+mod baseline {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const PREDEFINED: &[&str] = &[
+        "Self",
+        "start",
+        "return",
+        "state",
+        "entry",
+        "push",
+        "pop",
+        "eof",
+        "error",
+        "keywords",
+        "priority",
+        "case_insensitive",
+    ];
+
+    const PREDEFINED_INTERNER: usize = 0;
+
+    #[derive(Copy, Clone, PartialEq, Eq, Hash)]
+    pub struct InternedString {
+        value: usize,
+        interner: usize,
+    }
+
+    #[derive(Clone)]
+    pub struct Interner {
+        id: usize,
+        strings: Arc<Mutex<InternedStrings>>,
+    }
+
+    #[derive(Default)]
+    struct InternedStrings {
+        intern_map: HashMap<(String, bool), InternedString>,
+        strings: Vec<(String, bool)>,
+    }
+
+    impl Interner {
+        pub fn new() -> Self {
+            Interner {
+                id: PREDEFINED_INTERNER + 1,
+                strings: Arc::new(Mutex::new(InternedStrings::default())),
+            }
+        }
+
+        pub fn intern<T: AsRef<str>>(&self, raw_str: T) -> InternedString {
+            if let Some(index) = PREDEFINED.iter().position(|&word| word == raw_str.as_ref()) {
+                return InternedString {
+                    value: index,
+                    interner: PREDEFINED_INTERNER,
+                };
+            }
+
+            let raw_str = (raw_str.as_ref().to_string(), false);
+            let mut strings = self.strings.lock().unwrap();
+
+            if let Some(&interned_string) = strings.intern_map.get(&raw_str) {
+                return interned_string;
+            }
+
+            let interned_string = InternedString {
+                value: strings.strings.len(),
+                interner: self.id,
+            };
+
+            strings.intern_map.insert(raw_str.clone(), interned_string);
+            strings.strings.push(raw_str);
+
+            interned_string
+        }
+
+        pub fn get(&self, interned_string: InternedString) -> Option<String> {
+            let strings = self.strings.lock().unwrap();
+
+            if interned_string.interner == PREDEFINED_INTERNER {
+                return PREDEFINED
+                    .get(interned_string.value)
+                    .map(|word| word.to_string());
+            }
+
+            strings
+                .strings
+                .get(interned_string.value)
+                .map(|(string, _)| string.clone())
+        }
+    }
+}
+
+#[bench]
+fn parse_and_resolve(bencher: &mut Bencher) {
+    let source = large_source(STATE_COUNT);
+
+    bencher.iter(|| resolve(&source, &Interner::new()).states().len());
+}
+
+#[bench]
+fn resolve_from(bencher: &mut Bencher) {
+    let definitions = parse(&large_source(STATE_COUNT), &Interner::new());
+
+    bencher.iter(|| Automata::resolve_from(definitions.clone()).states().len());
+}
+
+#[bench]
+fn write_text(bencher: &mut Bencher) {
+    let source = large_source(STATE_COUNT);
+    let automata = resolve(&source, &Interner::new());
+
+    bencher.iter(|| automata.to_text().len());
+}
+
+#[bench]
+fn intern_known_names(bencher: &mut Bencher) {
+    let names = state_names();
+    let interner = Interner::new();
+
+    for name in &names {
+        interner.intern(name);
+    }
+
+    bencher.iter(|| {
+        names
+            .iter()
+            .map(|name| interner.intern(name))
+            .collect::<Vec<_>>()
+    });
+}
+
+#[bench]
+fn debug_names(bencher: &mut Bencher) {
+    let interner = Interner::new();
+    let names = interner.scope(|| {
+        (0..STATE_COUNT)
+            .map(|index| intern(format!("state_{}", index)))
+            .collect::<Vec<_>>()
+    });
+
+    bencher.iter(|| interner.scope(|| format!("{:?}", names).len()));
+}
+
+#[bench]
+fn read_names(bencher: &mut Bencher) {
+    let interner = Interner::new();
+    let names = state_names()
+        .iter()
+        .map(|name| interner.intern(name))
+        .collect::<Vec<_>>();
+
+    bencher.iter(|| {
+        names
+            .iter()
+            .map(|&name| interner.with_str(name, str::len).unwrap())
+            .sum::<usize>()
+    });
+}
+
+#[bench]
+fn baseline_intern_known_names(bencher: &mut Bencher) {
+    let names = state_names();
+    let interner = baseline::Interner::new();
+
+    for name in &names {
+        interner.intern(name);
+    }
+
+    bencher.iter(|| {
+        names
+            .iter()
+            .map(|name| interner.intern(name))
+            .collect::<Vec<_>>()
+    });
+}
+
+#[bench]
+fn baseline_read_names(bencher: &mut Bencher) {
+    let interner = baseline::Interner::new();
+    let names = state_names()
+        .iter()
+        .map(|name| interner.intern(name))
+        .collect::<Vec<_>>();
+
+    bencher.iter(|| {
+        names
+            .iter()
+            .map(|&name| interner.get(name).unwrap().len())
+            .sum::<usize>()
+    });
+}
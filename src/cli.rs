@@ -0,0 +1,151 @@
+//! The command line
+//!
+//! ```text
+//! snow_automata [file] [--backend name] [--option name=value]... [--out dir]
+//! snow_automata [file] --rust match|table
+//! snow_automata --backends
+//! ```
+//!
+//! Without a backend, the resolved automata is printed out in the text format of
+//! `automata::text_format`. With one, the files it writes are put in `--out`, or printed out
+//! when there is a single one. `--rust table` is short for `--backend rust --option output=table`.
+//! Errors in the file are printed out and make it exit with status 1 either way.
+//!
+//! Backends are looked up in the registry given to `run`, so a binary can add its own backends:
+//!
+//! ```ignore
+//! let mut registry = BackendRegistry::with_builtin_backends();
+//! registry.register(Box::new(DslBackend::new()));
+//! snow_automata::cli::run(registry);
+//! ```
+
+use automata_codegen::backend::{parse_option, BackendRegistry, LexerIr};
+use automata_syntax::SyntaxParser;
+use automata::Automata;
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Run the command line with the arguments of the process
+pub fn run(mut registry: BackendRegistry) {
+    let mut input_path = "automata.sa".to_string();
+    let mut backend_name = None;
+    let mut options = Vec::new();
+    let mut out_dir = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backend" => backend_name = Some(value_of(&arg, args.next())),
+            "--option" => options.push(value_of(&arg, args.next())),
+            "--out" => out_dir = Some(PathBuf::from(value_of(&arg, args.next()))),
+            "--rust" => {
+                backend_name = Some("rust".to_string());
+                options.push(format!("output={}", value_of(&arg, args.next())));
+            }
+            "--backends" => {
+                print_backends(&registry);
+                return;
+            }
+            _ => input_path = arg,
+        }
+    }
+
+    let mut automata_text = String::new();
+
+    let mut automata_file = File::open(&input_path).expect("Could not open input file...");
+
+    let _ = automata_file
+        .read_to_string(&mut automata_text)
+        .expect("Could not read file...");
+
+    let mut parser = SyntaxParser::new(&automata_text);
+    let definitions = parser.parse();
+
+    // The parser has printed its errors already
+    if !parser.errors().is_empty() {
+        process::exit(1);
+    }
+
+    let backend_name = match backend_name {
+        Some(backend_name) => backend_name,
+        None => {
+            let automata = Automata::resolve_from(definitions);
+            print!("{}", automata.to_text());
+            return;
+        }
+    };
+
+    let backend = match registry.get_mut(&backend_name) {
+        Some(backend) => backend,
+        None => fail(format!(
+            "Unknown backend `{}`, --backends lists them",
+            backend_name
+        )),
+    };
+
+    for option in &options {
+        let (name, value) = parse_option(option);
+        if let Err(error) = backend.set_option(name, value) {
+            fail(error);
+        }
+    }
+
+    let automata = Automata::resolve_from(definitions);
+    let name = Path::new(&input_path)
+        .file_stem()
+        .map_or("lexer".into(), |stem| stem.to_string_lossy());
+
+    let output = backend
+        .generate(&LexerIr::new(name, &automata))
+        .unwrap_or_else(|error| fail(error));
+
+    for note in &output.notes {
+        eprintln!("{}", note);
+    }
+
+    match out_dir {
+        Some(out_dir) => {
+            if let Err(error) = output.write_to(&out_dir) {
+                fail(format!("{}: {}", out_dir.display(), error));
+            }
+        }
+        None if output.files.len() == 1 => {
+            if let Err(error) = io::stdout().write_all(&output.files[0].contents) {
+                fail(error);
+            }
+        }
+        None => fail(format!(
+            "Backend `{}` writes {} files, --out picks the directory they go to",
+            backend_name,
+            output.files.len()
+        )),
+    }
+}
+
+/// The value following an argument
+fn value_of(arg: &str, value: Option<String>) -> String {
+    match value {
+        Some(value) => value,
+        None => fail(format!("{} needs a value", arg)),
+    }
+}
+
+fn print_backends(registry: &BackendRegistry) {
+    for backend in registry.backends() {
+        println!("{}: {}", backend.name(), backend.description());
+
+        for &(name, description) in backend.options() {
+            println!("    --option {}=...: {}", name, description);
+        }
+    }
+}
+
+fn fail<T: Display>(message: T) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
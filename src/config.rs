@@ -0,0 +1,319 @@
+//! Compiling the automata files of a crate through a backend
+//!
+//! Each `.sa` file is compiled in `OUT_DIR`, keeping the directories it's in below the processed
+//! directory. With the Rust backend, `src/lexers/sql/select.sa` is written to
+//! `$OUT_DIR/sql/select.rs` when processing `src/lexers`. Files are only rewritten when their
+//! content changes, and cargo is told to run the build script again when an automata file changes.
+//!
+//! Syntax errors are written to stderr the way rustc writes them, which cargo shows when the build
+//! script fails, and each one is also sent to cargo as a one line warning.
+
+use automata_codegen::backend::{Backend, BackendError, LexerIr};
+use automata_codegen::rust::RustBackend;
+use automata_syntax::{SyntaxError, SyntaxParser};
+use automata::Automata;
+use automata_core::string_interning::Interner;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extension of automata files
+const AUTOMATA_EXTENSION: &str = "sa";
+
+/// Compiles automata files through a backend, from a build script
+pub struct Config {
+    backend: Box<dyn Backend>,
+    out_dir: Option<PathBuf>,
+}
+
+/// Why automata files could not be compiled
+#[derive(Debug)]
+pub enum ProcessError {
+    /// `OUT_DIR` isn't set, as happens outside of build scripts, and no output directory was set
+    NoOutDir,
+    /// A file or directory could not be read or written
+    Io(PathBuf, io::Error),
+    /// A file has syntax errors, which were written to stderr
+    Syntax { path: PathBuf, error_count: usize },
+    /// The backend could not write a file out
+    Backend(PathBuf, BackendError),
+}
+
+impl Config {
+    /// Create a new Config, writing Rust `match` expressions to `OUT_DIR`
+    pub fn new() -> Self {
+        Self {
+            backend: Box::new(RustBackend::new()),
+            out_dir: None,
+        }
+    }
+
+    /// Set the backend files are compiled through
+    pub fn with_backend<B: Backend + 'static>(mut self, backend: B) -> Self {
+        self.backend = Box::new(backend);
+        self
+    }
+
+    /// Set the directory files are written to instead of `OUT_DIR`
+    pub fn with_out_dir<P: AsRef<Path>>(mut self, out_dir: P) -> Self {
+        self.out_dir = Some(out_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Compile every automata file below a directory
+    /// Every file is compiled even when some have errors, the first error is returned
+    pub fn process_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), ProcessError> {
+        let dir = dir.as_ref();
+        let out_dir = self.out_dir()?;
+
+        println!("cargo:rerun-if-changed={}", dir.display());
+
+        let mut paths = Vec::new();
+        find_automata_files(dir, &mut paths)?;
+        paths.sort();
+
+        let mut first_error = None;
+
+        for path in paths {
+            let relative_dir = path.strip_prefix(dir).unwrap().parent().unwrap();
+
+            match self.compile(&path, &out_dir.join(relative_dir)) {
+                Ok(()) => {}
+                Err(error @ ProcessError::Syntax { .. }) => {
+                    first_error = first_error.or(Some(error));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Compile one automata file, its output going right in the output directory
+    pub fn process_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ProcessError> {
+        self.compile(path.as_ref(), &self.out_dir()?)
+    }
+
+    fn out_dir(&self) -> Result<PathBuf, ProcessError> {
+        match self.out_dir {
+            Some(ref out_dir) => Ok(out_dir.clone()),
+            None => env::var_os("OUT_DIR")
+                .map(PathBuf::from)
+                .ok_or(ProcessError::NoOutDir),
+        }
+    }
+
+    fn compile(&self, path: &Path, out_dir: &Path) -> Result<(), ProcessError> {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let text = fs::read_to_string(path)
+            .map_err(|error| ProcessError::Io(path.to_path_buf(), error))?;
+
+        // The names of the file are freed once its output is written
+        let interner = Interner::new();
+        let mut parser = SyntaxParser::new(&text)
+            .with_printed_errors(false)
+            .with_interner(&interner);
+        let definitions = parser.parse();
+
+        let errors = parser.errors();
+        if !errors.is_empty() {
+            for error in &errors {
+                let (line, column) = line_and_column(&text, error.debug_info.index_location.0);
+                println!(
+                    "cargo:warning={}:{}:{}: {}",
+                    path.display(),
+                    line,
+                    column,
+                    error.message
+                );
+                eprintln!("{}", render_error(path, &text, error));
+            }
+
+            return Err(ProcessError::Syntax {
+                path: path.to_path_buf(),
+                error_count: errors.len(),
+            });
+        }
+
+        let automata = Automata::resolve_from(definitions);
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        let output = self
+            .backend
+            .generate(&LexerIr::new(name, &automata))
+            .map_err(|error| ProcessError::Backend(path.to_path_buf(), error))?;
+
+        output
+            .write_to(out_dir)
+            .map(|_| ())
+            .map_err(|error| ProcessError::Io(out_dir.to_path_buf(), error))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("backend", &self.backend.name())
+            .field("out_dir", &self.out_dir)
+            .finish()
+    }
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProcessError::NoOutDir => write!(f, "OUT_DIR is not set, is this a build script?"),
+            ProcessError::Io(ref path, ref error) => write!(f, "{}: {}", path.display(), error),
+            ProcessError::Syntax {
+                ref path,
+                error_count,
+            } => write!(
+                f,
+                "{}: {} syntax error{}",
+                path.display(),
+                error_count,
+                if error_count == 1 { "" } else { "s" }
+            ),
+            ProcessError::Backend(ref path, ref error) => {
+                write!(f, "{}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl Error for ProcessError {}
+
+/// Push the automata files below a directory
+fn find_automata_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), ProcessError> {
+    let entries = fs::read_dir(dir).map_err(|error| ProcessError::Io(dir.to_path_buf(), error))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|error| ProcessError::Io(dir.to_path_buf(), error))?
+            .path();
+
+        if path.is_dir() {
+            find_automata_files(&path, paths)?;
+        } else if path.extension() == Some(AUTOMATA_EXTENSION.as_ref()) {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Line and column of a char index, both counted from 1
+fn line_and_column(text: &str, index: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+
+    for chr in text.chars().take(index) {
+        if chr == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    (line, column)
+}
+
+/// An error with the line it's on, the way rustc writes them
+fn render_error(path: &Path, text: &str, error: &SyntaxError) -> String {
+    let (start, end) = error.debug_info.index_location;
+    let (line, column) = line_and_column(text, start);
+    let source_line = text.lines().nth(line - 1).unwrap_or("");
+
+    let line_number = line.to_string();
+    let margin = " ".repeat(line_number.len());
+    let underline_len = end
+        .saturating_sub(start)
+        .min(source_line.chars().count().saturating_sub(column - 1))
+        .max(1);
+
+    format!(
+        "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+        error.message,
+        margin,
+        path.display(),
+        line,
+        column,
+        margin,
+        line_number,
+        source_line,
+        margin,
+        " ".repeat(column - 1),
+        "^".repeat(underline_len)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_dir_test() {
+        let directory =
+            env::temp_dir().join(format!("snow_automata_config_{}", ::std::process::id()));
+        let lexers = directory.join("lexers");
+        let out_dir = directory.join("out");
+        fs::create_dir_all(lexers.join("nested")).unwrap();
+
+        fs::write(
+            lexers.join("words.sa"),
+            "state start {\n    'a'..'z' => Self\n    _ => return WORD\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            lexers.join("nested").join("spaces.sa"),
+            "state start {\n    ' ' =>> return SPACE\n}\n",
+        )
+        .unwrap();
+        fs::write(lexers.join("notes.txt"), "not an automata").unwrap();
+
+        let config = Config::new().with_out_dir(&out_dir);
+        config.process_dir(&lexers).unwrap();
+
+        let words = fs::read_to_string(out_dir.join("words.rs")).unwrap();
+        assert!(words.contains("pub enum Token"));
+        assert!(words.contains("WORD"));
+        assert!(out_dir.join("nested").join("spaces.rs").exists());
+        assert!(!out_dir.join("notes.rs").exists());
+
+        let broken = "state start {\n    'a' -> Self\n}\n";
+        fs::write(lexers.join("broken.sa"), broken).unwrap();
+
+        match config.process_dir(&lexers) {
+            Err(ProcessError::Syntax { path, .. }) => assert_eq!(path, lexers.join("broken.sa")),
+            result => panic!("Expected a syntax error, got {:?}", result),
+        }
+
+        let mut parser = SyntaxParser::new(broken).with_printed_errors(false);
+        let _ = parser.parse();
+
+        assert_eq!(
+            render_error(Path::new("broken.sa"), broken, &parser.errors()[0]),
+            "error: State has no closing token\n \
+             --> broken.sa:1:13\n  \
+             |\n\
+             1 | state start {\n  \
+             |             ^\n"
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
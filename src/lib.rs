@@ -0,0 +1,117 @@
+//! Compiling automata files from a build script
+//!
+//! ```ignore
+//! extern crate snow_automata;
+//!
+//! fn main() {
+//!     snow_automata::Config::new()
+//!         .process_dir("src/lexers")
+//!         .unwrap();
+//! }
+//! ```
+//!
+//! Every `.sa` file under `src/lexers` is compiled to a Rust lexer module in `OUT_DIR`, see
+//! `Config`. The command line lives in `cli`, so binaries adding their own backends can reuse it.
+
+pub extern crate automata;
+pub extern crate automata_codegen;
+pub extern crate automata_core;
+extern crate automata_syntax;
+
+pub mod cli;
+mod config;
+
+pub use automata_codegen::backend;
+pub use automata_codegen::rust::{RustBackend, RustOutput};
+pub use config::{Config, ProcessError};
+
+#[cfg(test)]
+mod tests {
+    use automata::interpreter::Interpreter;
+    use automata::Automata;
+    use automata_syntax::SyntaxParser;
+
+    /// An automata example of the README, with the inputs it is documented to lex
+    struct Example {
+        line: usize,
+        source: String,
+        /// Inputs along with the tokens they lex as
+        lexes: Vec<(String, Vec<String>)>,
+    }
+
+    /// The code blocks without a language, which are automata, along with the
+    /// `<!-- "input" lexes as A B -->` comments following them
+    fn readme_examples() -> Vec<Example> {
+        let mut examples: Vec<Example> = Vec::new();
+        let mut lines = include_str!("../README.md").lines().enumerate();
+
+        while let Some((index, line)) = lines.next() {
+            if line.starts_with("```") {
+                let source = lines
+                    .by_ref()
+                    .take_while(|&(_, line)| line != "```")
+                    .map(|(_, line)| format!("{}\n", line))
+                    .collect();
+
+                if line == "```" {
+                    examples.push(Example {
+                        line: index + 1,
+                        source,
+                        lexes: Vec::new(),
+                    });
+                }
+            } else if let Some(comment) = line
+                .strip_prefix("<!-- \"")
+                .and_then(|comment| comment.strip_suffix(" -->"))
+            {
+                let (input, tokens) = comment.split_once("\" lexes as ").unwrap();
+                examples.last_mut().unwrap().lexes.push((
+                    input.to_string(),
+                    tokens.split_whitespace().map(str::to_string).collect(),
+                ));
+            }
+        }
+
+        examples
+    }
+
+    #[test]
+    fn readme_examples_test() {
+        let examples = readme_examples();
+        assert!(examples.iter().any(|example| !example.lexes.is_empty()));
+
+        for example in examples {
+            let mut parser = SyntaxParser::new(&example.source).with_printed_errors(false);
+            let definitions = parser.parse();
+            assert_eq!(
+                parser.errors(),
+                Vec::new(),
+                "example at README.md:{}",
+                example.line
+            );
+
+            let automata = Automata::resolve_from(definitions);
+            let interpreter = Interpreter::new(&automata);
+
+            for (input, expected) in example.lexes {
+                let entry_point = automata.entry_points()[0];
+                let tokens = interpreter
+                    .lex(entry_point, &input)
+                    .unwrap()
+                    .map(|result| match result {
+                        Ok(lexeme) => automata
+                            .interner()
+                            .with_str(lexeme.token, str::to_string)
+                            .unwrap(),
+                        Err(error) => format!("{:?}", error.kind),
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    tokens, expected,
+                    "{:?} in the example at README.md:{}",
+                    input, example.line
+                );
+            }
+        }
+    }
+}